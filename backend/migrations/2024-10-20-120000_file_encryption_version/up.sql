@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD encryption_version SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE s3_buckets ADD backend VARCHAR(16) NOT NULL DEFAULT 's3';
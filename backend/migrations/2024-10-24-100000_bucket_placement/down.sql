@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE files DROP COLUMN size;
+
+ALTER TABLE s3_buckets DROP COLUMN status;
+ALTER TABLE s3_buckets DROP COLUMN weight;
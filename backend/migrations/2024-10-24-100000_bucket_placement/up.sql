@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE s3_buckets ADD weight INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE s3_buckets ADD status VARCHAR(16) NOT NULL DEFAULT 'active';
+
+ALTER TABLE files ADD size BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE files DROP COLUMN kek_id;
+ALTER TABLE files ALTER COLUMN key TYPE VARCHAR(44);
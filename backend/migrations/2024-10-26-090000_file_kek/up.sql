@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE files ALTER COLUMN key TYPE VARCHAR(64);
+ALTER TABLE files ADD kek_id INTEGER;
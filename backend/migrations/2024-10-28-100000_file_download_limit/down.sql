@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE files DROP COLUMN download_count;
+ALTER TABLE files DROP COLUMN max_downloads;
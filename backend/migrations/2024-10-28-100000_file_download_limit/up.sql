@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD max_downloads INTEGER;
+ALTER TABLE files ADD download_count INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD delete_token_hash VARCHAR(64);
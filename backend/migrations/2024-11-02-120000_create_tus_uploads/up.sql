@@ -0,0 +1,24 @@
+-- Your SQL goes here
+
+CREATE TABLE tus_uploads (
+    id SERIAL PRIMARY KEY,
+    file UUID NOT NULL UNIQUE,
+    s3_bucket_id INTEGER NOT NULL REFERENCES s3_buckets(id),
+    storage_upload_id VARCHAR(1024) NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    file_name VARCHAR(96) NOT NULL,
+    file_type VARCHAR(96) NOT NULL,
+    lifetime BIGINT NOT NULL,
+    max_downloads INTEGER,
+    key VARCHAR(64) NOT NULL,
+    kek_id INTEGER NOT NULL,
+    nonce VARCHAR(16) NOT NULL,
+    segment_index INTEGER NOT NULL DEFAULT 0,
+    pending_plaintext BYTEA NOT NULL DEFAULT '',
+    pending_ciphertext BYTEA NOT NULL DEFAULT '',
+    part_etags TEXT[] NOT NULL DEFAULT '{}',
+    stored_size BIGINT NOT NULL DEFAULT 0,
+    date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    date_updated TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+CREATE TABLE job_runs (
+    name VARCHAR(32) PRIMARY KEY,
+    last_success TIMESTAMP NOT NULL
+);
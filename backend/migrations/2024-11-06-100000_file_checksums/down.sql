@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE tus_uploads DROP COLUMN client_sha256;
+ALTER TABLE tus_uploads DROP COLUMN received_sha256_state;
+ALTER TABLE tus_uploads DROP COLUMN stored_sha256_state;
+
+ALTER TABLE files DROP COLUMN date_verified;
+ALTER TABLE files DROP COLUMN client_sha256;
+ALTER TABLE files DROP COLUMN stored_sha256;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD stored_sha256 VARCHAR(64);
+ALTER TABLE files ADD client_sha256 VARCHAR(64);
+ALTER TABLE files ADD date_verified TIMESTAMP;
+
+ALTER TABLE tus_uploads ADD stored_sha256_state BYTEA NOT NULL DEFAULT '';
+ALTER TABLE tus_uploads ADD received_sha256_state BYTEA NOT NULL DEFAULT '';
+ALTER TABLE tus_uploads ADD client_sha256 VARCHAR(64);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE tus_uploads DROP COLUMN password_key_mix;
+ALTER TABLE tus_uploads DROP COLUMN password_verifier;
+ALTER TABLE tus_uploads DROP COLUMN password_salt;
+
+ALTER TABLE files DROP COLUMN password_locked_till;
+ALTER TABLE files DROP COLUMN password_failures;
+ALTER TABLE files DROP COLUMN password_key_mix;
+ALTER TABLE files DROP COLUMN password_verifier;
+ALTER TABLE files DROP COLUMN password_salt;
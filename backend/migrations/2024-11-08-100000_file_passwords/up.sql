@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD password_salt VARCHAR(24);
+ALTER TABLE files ADD password_verifier VARCHAR(64);
+ALTER TABLE files ADD password_key_mix BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE files ADD password_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE files ADD password_locked_till TIMESTAMP;
+
+ALTER TABLE tus_uploads ADD password_salt VARCHAR(24);
+ALTER TABLE tus_uploads ADD password_verifier VARCHAR(64);
+ALTER TABLE tus_uploads ADD password_key_mix BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX files_bundle_id;
+ALTER TABLE files DROP COLUMN bundle_id;
+
+DROP TABLE bundles;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE bundles (
+    id SERIAL PRIMARY KEY,
+    bundle UUID NOT NULL UNIQUE,
+    manifest TEXT NOT NULL,
+    available_till TIMESTAMP NOT NULL,
+    date_created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delete_token_hash VARCHAR(64) NOT NULL
+);
+
+ALTER TABLE files ADD bundle_id INTEGER REFERENCES bundles(id) ON DELETE SET NULL;
+CREATE INDEX files_bundle_id ON files (bundle_id);
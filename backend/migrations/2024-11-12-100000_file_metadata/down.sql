@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE tus_uploads DROP COLUMN metadata;
+
+ALTER TABLE files DROP COLUMN metadata_version;
+ALTER TABLE files DROP COLUMN metadata;
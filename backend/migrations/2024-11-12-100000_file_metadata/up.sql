@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD metadata TEXT;
+ALTER TABLE files ADD metadata_version SMALLINT NOT NULL DEFAULT 0;
+
+ALTER TABLE tus_uploads ADD metadata TEXT;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE tus_uploads DROP COLUMN padded;
+
+ALTER TABLE files DROP COLUMN padded;
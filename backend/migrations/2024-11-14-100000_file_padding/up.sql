@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD padded BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE tus_uploads ADD padded BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD date_downloaded TIMESTAMP;
+
+-- Downloads counted so far happened some time after the file was uploaded
+UPDATE files SET date_downloaded = date_created WHERE download_count > 0;
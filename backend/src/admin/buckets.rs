@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use clap::{Args, Subcommand};
+
+use crate::{
+    config,
+    database::{
+        actions,
+        models::{self, NewS3Bucket},
+    },
+    reconcile, secrets, storage, DbPool,
+};
+
+#[derive(Subcommand)]
+pub enum BucketCommand {
+    /// Add a bucket, after checking it can be written to, read from and deleted from
+    Add(AddBucket),
+    /// List every bucket with its status and usage
+    List,
+    /// Check a bucket is reachable by writing, reading and deleting a probe object
+    Test { id: i32 },
+    /// Start sending new uploads to a bucket again
+    Enable { id: i32 },
+    /// Stop sending new uploads to a bucket, while its files stay downloadable
+    Disable {
+        id: i32,
+        /// Also stop extending the lifetime of its files when they're
+        /// accessed, so the bucket empties out
+        #[arg(long)]
+        drain: bool,
+    },
+    /// Remove a bucket. Refused while files still reference it
+    Remove { id: i32 },
+    /// Delete objects no file refers to, and files whose object is gone
+    Reconcile {
+        /// Only this bucket instead of every bucket
+        id: Option<i32>,
+        /// Only report what's out of sync
+        #[arg(long)]
+        dry_run: bool,
+        /// Leave objects and files younger than this alone, defaults to
+        /// jobs.reconcile_grace
+        #[arg(long, value_parser = duration)]
+        grace: Option<i64>,
+    },
+}
+
+#[derive(Args)]
+pub struct AddBucket {
+    /// Bucket name, or the directory for the local backend
+    #[arg(long)]
+    name: String,
+    #[arg(long, default_value = "")]
+    region: String,
+    /// Endpoint including the region if the provider puts it in the host,
+    /// for example fsn1.your-objectstorage.com
+    #[arg(long, default_value = "")]
+    endpoint: String,
+    #[arg(long, env = "BUCKET_ACCESS_KEY", default_value = "")]
+    access_key: String,
+    #[arg(
+        long,
+        env = "BUCKET_SECRET_KEY",
+        default_value = "",
+        hide_env_values = true
+    )]
+    secret_key: String,
+    /// s3, local or memory
+    #[arg(long, default_value = "s3")]
+    backend: String,
+    #[arg(long, default_value_t = 1)]
+    weight: i32,
+}
+
+pub async fn run(pool: &DbPool, command: BucketCommand) -> Result<(), String> {
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+
+    match command {
+        BucketCommand::Add(bucket) => {
+            // The local backend keeps its directory in `endpoint`
+            let endpoint = match bucket.backend.as_str() {
+                "local" => &bucket.name,
+                _ => &bucket.endpoint,
+            };
+
+            let access_key = secrets::seal("access_key", &bucket.access_key)?;
+            let secret_key = secrets::seal("secret_key", &bucket.secret_key)?;
+
+            let bucket_info = models::S3Bucket {
+                id: 0,
+                bucket_name: bucket.name.clone(),
+                region: bucket.region.clone(),
+                endpoint: endpoint.clone(),
+                access_key: access_key.clone(),
+                secret_key: secret_key.clone(),
+                backend: bucket.backend.clone(),
+                weight: bucket.weight,
+                status: models::BUCKET_ACTIVE.to_string(),
+            };
+
+            let backend = storage::backend_for(bucket_info)
+                .ok_or_else(|| format!("Unknown backend {}", bucket.backend))?;
+            storage::probe(backend.as_ref()).await?;
+
+            let id = actions::add_s3_bucket(
+                &mut conn,
+                NewS3Bucket {
+                    bucket_name: &bucket.name,
+                    region: &bucket.region,
+                    endpoint,
+                    access_key: &access_key,
+                    secret_key: &secret_key,
+                    backend: &bucket.backend,
+                    weight: bucket.weight,
+                    status: models::BUCKET_ACTIVE,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+            println!("Added bucket {}", id);
+        }
+        BucketCommand::List => {
+            let buckets = actions::get_s3_buckets(&mut conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            let usage: HashMap<i32, (i64, i64)> = actions::get_s3_bucket_usage(&mut conn)
+                .await
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .map(|(id, files, bytes)| (id, (files, bytes)))
+                .collect();
+
+            println!(
+                "{:<6}{:<9}{:<11}{:<8}{:<10}{:<16}NAME",
+                "ID", "BACKEND", "STATUS", "WEIGHT", "FILES", "BYTES"
+            );
+            for bucket in buckets {
+                let (files, bytes) = usage.get(&bucket.id).copied().unwrap_or_default();
+                println!(
+                    "{:<6}{:<9}{:<11}{:<8}{:<10}{:<16}{}",
+                    bucket.id,
+                    bucket.backend,
+                    bucket.status,
+                    bucket.weight,
+                    files,
+                    bytes,
+                    bucket.bucket_name
+                );
+            }
+        }
+        BucketCommand::Test { id } => {
+            let backend = storage::get_specific_storage(&mut conn, id)
+                .await
+                .ok_or_else(|| format!("Couldn't load bucket {}", id))?;
+            storage::probe(backend.as_ref()).await?;
+
+            println!("Bucket {} is reachable", id);
+        }
+        BucketCommand::Enable { id } => {
+            set_status(pool, id, models::BUCKET_ACTIVE).await?;
+        }
+        BucketCommand::Disable { id, drain } => {
+            let status = match drain {
+                true => models::BUCKET_DRAINING,
+                false => models::BUCKET_READ_ONLY,
+            };
+            set_status(pool, id, status).await?;
+        }
+        BucketCommand::Remove { id } => {
+            let files = actions::count_files_in_s3_bucket(&mut conn, id)
+                .await
+                .map_err(|e| e.to_string())?;
+            if files > 0 {
+                return Err(format!(
+                    "Bucket {} still holds {} files, disable it with --drain and wait for them to expire",
+                    id, files
+                ));
+            }
+
+            match actions::delete_s3_bucket(&mut conn, id).await {
+                Ok(0) => return Err(format!("Bucket {} doesn't exist", id)),
+                Ok(_) => println!("Removed bucket {}", id),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        BucketCommand::Reconcile { id, dry_run, grace } => {
+            let grace = grace.unwrap_or(config::config().jobs.reconcile_grace);
+            let buckets = match id {
+                Some(id) => vec![actions::get_s3_bucket_by_id(&mut conn, id)
+                    .await
+                    .map_err(|_| format!("Bucket {} doesn't exist", id))?],
+                None => actions::get_s3_buckets(&mut conn)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            };
+
+            let (orphan, missing) = match dry_run {
+                true => ("Orphaned object", "Missing object of file"),
+                false => ("Deleted orphaned object", "Deleted file without object"),
+            };
+            for bucket in buckets {
+                let id = bucket.id;
+                let report = reconcile::reconcile_bucket(&mut conn, bucket, grace, dry_run).await?;
+
+                for (key, size) in &report.orphan_objects {
+                    println!("Bucket {}: {} {} ({} bytes)", id, orphan, key, size);
+                }
+                for file in &report.missing_objects {
+                    println!("Bucket {}: {} {}", id, missing, file);
+                }
+                if report.is_empty() {
+                    println!("Bucket {} is in sync", id);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn duration(value: &str) -> Result<i64, String> {
+    config::parse_duration(value).ok_or_else(|| format!("{} isn't a duration", value))
+}
+
+async fn set_status(pool: &DbPool, id: i32, status: &str) -> Result<(), String> {
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+
+    match actions::set_s3_bucket_status(&mut conn, id, status).await {
+        Ok(0) => Err(format!("Bucket {} doesn't exist", id)),
+        Ok(_) => {
+            println!("Bucket {} is now {}", id, status);
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::{database::actions, jobs::Job, lifetime, DbPool};
+
+pub async fn run(pool: &DbPool) -> Result<(), String> {
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+
+    let runs: HashMap<String, _> = actions::get_job_runs(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|run| (run.name, run.last_success))
+        .collect();
+
+    println!("{:<12}{:<12}LAST SUCCESS (UTC)", "JOB", "INTERVAL");
+    for job in Job::ALL {
+        let last_success = runs
+            .get(job.name())
+            .map(|last_success| last_success.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "never".to_string());
+
+        println!(
+            "{:<12}{:<12}{}",
+            job.name(),
+            lifetime::format_duration(job.interval()),
+            last_success
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use clap::Subcommand;
+
+use crate::database::migrations;
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// List the migrations and whether they have been applied
+    Status,
+    /// Apply every pending migration
+    Up,
+    /// Revert the most recently applied migration
+    Down,
+}
+
+pub async fn run(database_url: &str, command: MigrateCommand) -> Result<(), String> {
+    match command {
+        MigrateCommand::Status => {
+            let status = migrations::status(database_url)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            for (version, applied) in &status.migrations {
+                let state = if *applied { "applied" } else { "pending" };
+                println!("{}\t{}", version, state);
+            }
+
+            for version in &status.unknown {
+                println!("{}\tunknown to this build", version);
+            }
+        }
+        MigrateCommand::Up => {
+            let applied = migrations::run_pending(database_url)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+
+            for version in applied {
+                println!("Applied migration {}", version);
+            }
+        }
+        MigrateCommand::Down => {
+            let version = migrations::revert_last(database_url)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("Reverted migration {}", version);
+        }
+    }
+
+    Ok(())
+}
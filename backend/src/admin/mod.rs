@@ -0,0 +1,30 @@
+use clap::{Parser, Subcommand};
+
+pub mod buckets;
+pub mod jobs;
+pub mod migrations;
+pub mod secrets;
+
+#[derive(Parser)]
+#[command(about = "Anonymous file hosting")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server (default)
+    Serve,
+    /// Manage storage buckets
+    #[command(subcommand)]
+    Bucket(buckets::BucketCommand),
+    /// Manage the master key that bucket credentials are sealed with
+    #[command(subcommand)]
+    Secrets(secrets::SecretsCommand),
+    /// Manage the database schema
+    #[command(subcommand)]
+    Migrate(migrations::MigrateCommand),
+    /// Show when each background job last completed
+    Jobs,
+}
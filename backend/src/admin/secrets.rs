@@ -0,0 +1,83 @@
+use clap::Subcommand;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection};
+
+use crate::{
+    database::{actions, DbError},
+    secrets::{self, MasterKey},
+    DbPool,
+};
+
+#[derive(Subcommand)]
+pub enum SecretsCommand {
+    /// Re-seal every bucket's credentials with the current master key.
+    ///
+    /// Credentials sealed with a previous key are opened with OLD_MASTER_KEY
+    /// or OLD_MASTER_KEY_FILE. Credentials stored before sealing existed are
+    /// sealed as well.
+    Rotate,
+}
+
+pub async fn run(pool: &DbPool, command: SecretsCommand) -> Result<(), String> {
+    match command {
+        SecretsCommand::Rotate => rotate(pool).await,
+    }
+}
+
+async fn rotate(pool: &DbPool) -> Result<(), String> {
+    let new_key = secrets::master_key()?;
+    let old_key = MasterKey::from_env("OLD_MASTER_KEY")?;
+
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+    let buckets = actions::get_s3_buckets(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Open everything before writing anything, so a wrong old key doesn't
+    // leave the table half rotated
+    let mut credentials = Vec::with_capacity(buckets.len());
+    for bucket in buckets {
+        let access_key = reopen(old_key.as_ref(), new_key, "access_key", &bucket.access_key)
+            .ok_or_else(|| format!("Couldn't open the access key of bucket {}", bucket.id))?;
+        let secret_key = reopen(old_key.as_ref(), new_key, "secret_key", &bucket.secret_key)
+            .ok_or_else(|| format!("Couldn't open the secret key of bucket {}", bucket.id))?;
+
+        credentials.push((
+            bucket.id,
+            new_key.seal("access_key", &access_key),
+            new_key.seal("secret_key", &secret_key),
+        ));
+    }
+
+    let rotated = credentials.len();
+    conn.transaction::<_, DbError, _>(|conn| {
+        async move {
+            for (id, access_key, secret_key) in credentials {
+                actions::set_s3_bucket_credentials(conn, id, &access_key, &secret_key).await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    println!("Sealed the credentials of {} buckets", rotated);
+    Ok(())
+}
+
+/// Opens `value` with the old key if there is one, falling back to the new key
+/// so running the rotation twice is harmless.
+fn reopen(
+    old_key: Option<&MasterKey>,
+    new_key: &MasterKey,
+    field: &str,
+    value: &str,
+) -> Option<String> {
+    if !secrets::is_sealed(value) {
+        return Some(value.to_string());
+    }
+
+    old_key
+        .and_then(|key| key.open(field, value))
+        .or_else(|| new_key.open(field, value))
+}
@@ -0,0 +1,125 @@
+use chrono::{Duration, Utc};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection};
+use uuid::Uuid;
+
+use crate::{
+    database::{
+        actions,
+        models::{self, NewBundle},
+    },
+    error::AppError,
+    files::{self, hash_delete_token, new_delete_token},
+    lifetime,
+};
+
+/// Most files a bundle can hold.
+pub const MAX_FILES: usize = 100;
+/// Longest manifest a bundle can have, base64url encoded.
+pub const MAX_MANIFEST_SIZE: usize = 64 * 1024;
+
+/// Puts `file_ids` in a new bundle with `manifest`, keeping all of them
+/// available for `lifetime` seconds from now. Returns the bundle and the
+/// token the uploader can delete it with.
+pub async fn create_bundle(
+    conn: &mut AsyncPgConnection,
+    manifest: &str,
+    lifetime: i64,
+    file_ids: &[i32],
+) -> Result<(Uuid, String), AppError> {
+    let bundle = Uuid::new_v4();
+    let delete_token = new_delete_token();
+    let delete_token_hash = hash_delete_token(&delete_token);
+    let available_till = (Utc::now() + Duration::seconds(lifetime)).naive_utc();
+
+    conn.transaction::<_, AppError, _>(|conn| {
+        async move {
+            let new_bundle = NewBundle {
+                bundle: &bundle,
+                manifest,
+                available_till,
+                delete_token_hash: &delete_token_hash,
+            };
+            let id = actions::add_bundle(conn, &new_bundle).await?;
+
+            // A file can only be in one bundle, or its lifetime would be
+            // shared twice
+            let added = actions::add_files_to_bundle(conn, id, file_ids, available_till).await?;
+            if added != file_ids.len() {
+                return Err(AppError::Conflict("File is already in a bundle"));
+            }
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok((bundle, delete_token))
+}
+
+/// Like `files::get_file`, opening a bundle keeps it and its files around
+/// when the lifetime policy says so.
+pub async fn get_bundle(
+    conn: &mut AsyncPgConnection,
+    bundle_uuid: Uuid,
+) -> Result<models::Bundle, AppError> {
+    let policy = lifetime::policy();
+    let extend_by = policy
+        .extend_on_access
+        .then(|| Duration::seconds(policy.extend_by));
+
+    let bundle = actions::get_bundle_record(conn, bundle_uuid, extend_by)
+        .await?
+        .ok_or(AppError::NotFound("bundle"))?;
+
+    if bundle.available_till <= Utc::now().naive_utc() {
+        return Err(AppError::Expired);
+    }
+
+    Ok(bundle)
+}
+
+/// Files of `bundle` that can still be downloaded. Others are listed in the
+/// manifest but have expired or run out of downloads.
+pub async fn available_files(
+    conn: &mut AsyncPgConnection,
+    bundle: &models::Bundle,
+) -> Result<Vec<models::File>, AppError> {
+    let now = Utc::now().naive_utc();
+
+    Ok(actions::get_bundle_files(conn, bundle.id)
+        .await?
+        .into_iter()
+        .filter(|file| file.available_till > now)
+        .filter(|file| match file.max_downloads {
+            Some(max_downloads) => file.download_count < max_downloads,
+            None => true,
+        })
+        .collect())
+}
+
+/// The bundle `bundle_uuid`, if `delete_token` is the token it was created
+/// with.
+pub async fn get_owned_bundle(
+    conn: &mut AsyncPgConnection,
+    bundle_uuid: Uuid,
+    delete_token: &str,
+) -> Result<Option<models::Bundle>, AppError> {
+    Ok(
+        actions::get_bundle_by_delete_token(conn, bundle_uuid, &hash_delete_token(delete_token))
+            .await?,
+    )
+}
+
+/// Deletes the bundle with every file in it. When storage fails part way the
+/// bundle stays, with the files that are left.
+pub async fn delete_bundle(
+    conn: &mut AsyncPgConnection,
+    bundle: &models::Bundle,
+) -> Result<(), AppError> {
+    for file in actions::get_bundle_files(conn, bundle.id).await? {
+        files::delete_file(conn, &file).await?;
+    }
+
+    Ok(actions::delete_bundle(conn, bundle.id).await?)
+}
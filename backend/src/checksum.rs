@@ -0,0 +1,185 @@
+use sha2::{compress256, digest::generic_array::GenericArray};
+
+const BLOCK_SIZE: usize = 64;
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 whose progress can be saved and picked up again, so a digest can
+/// span the requests of a resumable upload. `sha2` doesn't expose its state,
+/// so this drives its compression function directly.
+pub struct Sha256 {
+    state: [u32; 8],
+    length: u64,
+    buffer: Vec<u8>,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            state: INITIAL_STATE,
+            length: 0,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+        }
+    }
+
+    /// Continues from what [`Sha256::save`] returned.
+    pub fn resume(saved: &[u8]) -> Option<Self> {
+        let (state, rest) = saved.split_at_checked(32)?;
+        let (length, buffer) = rest.split_at_checked(8)?;
+        if buffer.len() >= BLOCK_SIZE {
+            return None;
+        }
+
+        let mut words = [0u32; 8];
+        for (word, bytes) in words.iter_mut().zip(state.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().ok()?);
+        }
+
+        Some(Sha256 {
+            state: words,
+            length: u64::from_be_bytes(length.try_into().ok()?),
+            buffer: buffer.to_vec(),
+        })
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut saved = Vec::with_capacity(40 + self.buffer.len());
+        for word in self.state {
+            saved.extend_from_slice(&word.to_be_bytes());
+        }
+        saved.extend_from_slice(&self.length.to_be_bytes());
+        saved.extend_from_slice(&self.buffer);
+        saved
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        if !self.buffer.is_empty() {
+            let missing = (BLOCK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+
+            if self.buffer.len() < BLOCK_SIZE {
+                return;
+            }
+
+            compress(&mut self.state, &self.buffer);
+            self.buffer.clear();
+        }
+
+        let whole = data.len() - data.len() % BLOCK_SIZE;
+        compress(&mut self.state, &data[..whole]);
+        self.buffer.extend_from_slice(&data[whole..]);
+    }
+
+    /// The digest, hex encoded.
+    pub fn finalize(mut self) -> String {
+        let bits = self.length * 8;
+
+        // A one bit, zeros up to 8 bytes before the end of a block and the
+        // length in bits
+        let zeros = (BLOCK_SIZE * 2 - 9 - self.buffer.len()) % BLOCK_SIZE;
+        let mut padding = vec![0x80];
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&bits.to_be_bytes());
+        self.update(&padding);
+
+        let digest: Vec<u8> = self
+            .state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        hex::encode(digest)
+    }
+}
+
+fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+    for block in blocks.chunks_exact(BLOCK_SIZE) {
+        compress256(state, std::slice::from_ref(GenericArray::from_slice(block)));
+    }
+}
+
+/// Whether `digest` looks like a hex encoded SHA-256, as clients send them.
+pub fn is_sha256(digest: &str) -> bool {
+    digest.len() == 64
+        && digest
+            .bytes()
+            .all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Digest;
+
+    use super::*;
+
+    fn expected(data: &[u8]) -> String {
+        hex::encode(sha2::Sha256::digest(data))
+    }
+
+    fn data(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    #[test]
+    fn matches_sha2() {
+        for length in 0..300 {
+            let data = data(length);
+            let mut sha256 = Sha256::new();
+            sha256.update(&data);
+            assert_eq!(sha256.finalize(), expected(&data), "length {}", length);
+        }
+    }
+
+    #[test]
+    fn matches_sha2_across_save_and_resume() {
+        // Around where the padding needs a second block, and block edges
+        let lengths = [55, 56, 57, 63, 64, 65, 119, 120, 127, 128, 129, 200];
+
+        for length in lengths {
+            let data = data(length);
+
+            for split in [0, 1, 55, 56, 63, 64, 65, length / 2, length] {
+                let split = split.min(length);
+
+                let mut sha256 = Sha256::new();
+                sha256.update(&data[..split]);
+                let mut sha256 = Sha256::resume(&sha256.save()).unwrap();
+                sha256.update(&data[split..]);
+
+                assert_eq!(
+                    sha256.finalize(),
+                    expected(&data),
+                    "length {} split at {}",
+                    length,
+                    split
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matches_sha2_in_small_updates() {
+        let data = data(1000);
+
+        let mut sha256 = Sha256::new();
+        for chunk in data.chunks(13) {
+            sha256 = Sha256::resume(&sha256.save()).unwrap();
+            sha256.update(chunk);
+        }
+
+        assert_eq!(sha256.finalize(), expected(&data));
+    }
+
+    #[test]
+    fn rejects_invalid_state() {
+        let saved = Sha256::new().save();
+        assert!(Sha256::resume(&saved[..39]).is_none());
+
+        let mut too_long = saved.clone();
+        too_long.extend_from_slice(&[0; BLOCK_SIZE]);
+        assert!(Sha256::resume(&too_long).is_none());
+    }
+}
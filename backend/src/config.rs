@@ -0,0 +1,517 @@
+use std::{net::ToSocketAddrs, path::Path, str::FromStr};
+
+use lazy_static::lazy_static;
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{lifetime, placement::Strategy};
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+pub const MINUTE: i64 = 60;
+pub const HOUR: i64 = 60 * MINUTE;
+pub const DAY: i64 = 24 * HOUR;
+pub const WEEK: i64 = 7 * DAY;
+
+lazy_static! {
+    static ref CONFIG: Result<Config, String> = Config::load();
+}
+
+/// Server configuration. Read from the TOML file named in `CONFIG_FILE`, or
+/// `config.toml` when that exists, and then overridden by environment
+/// variables. Every setting has a default except the database URL.
+///
+/// Secrets aren't part of it, the master key and KEKs are only read from the
+/// environment.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: Server,
+    pub paths: Paths,
+    pub limits: Limits,
+    pub database: Database,
+    pub placement: Placement,
+    pub lifetime: lifetime::Policy,
+    pub jobs: Jobs,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Server {
+    /// Addresses to listen on, like `0.0.0.0:8080`.
+    pub listen: Vec<String>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            listen: vec!["0.0.0.0:8080".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+    /// Directory the frontend is served from.
+    pub static_dir: String,
+    /// Glob matching the page templates.
+    pub templates: String,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Paths {
+            static_dir: "./../frontend".to_string(),
+            templates: "./../frontend/templates/**/*.html".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Largest file that can be uploaded, in bytes.
+    pub max_upload_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_upload_size: 1_073_741_824, // 1GB
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Database {
+    pub url: String,
+    /// Most connections kept open, defaults to four per CPU.
+    pub pool_size: Option<usize>,
+    /// Apply pending migrations before serving. Without it the server refuses
+    /// to start until they're applied with `backend migrate up`.
+    pub migrate_on_startup: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Placement {
+    pub strategy: Strategy,
+    /// Avoid buckets that recently failed a write.
+    pub skip_unhealthy: bool,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            strategy: Strategy::RoundRobin,
+            skip_unhealthy: true,
+        }
+    }
+}
+
+/// Schedules of the background jobs. Durations are in seconds.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Jobs {
+    /// How often expired files and abandoned uploads are deleted.
+    #[serde(deserialize_with = "duration")]
+    pub cleanup_interval: i64,
+    /// How often keys wrapped with a retired KEK are re-wrapped.
+    #[serde(deserialize_with = "duration")]
+    pub rewrap_interval: i64,
+    pub rewrap_batch_size: i64,
+    /// Most expired files or abandoned uploads loaded at once.
+    pub cleanup_batch_size: i64,
+    /// Resumable uploads that haven't received data for this long are given
+    /// up on.
+    #[serde(deserialize_with = "duration")]
+    pub abandoned_upload_after: i64,
+    /// How often buckets are compared with the files table.
+    #[serde(deserialize_with = "duration")]
+    pub reconcile_interval: i64,
+    /// Objects and files younger than this are left alone by reconciliation,
+    /// they may belong to an upload that's still in flight.
+    #[serde(deserialize_with = "duration")]
+    pub reconcile_grace: i64,
+    /// Only report what's out of sync, without deleting anything.
+    pub reconcile_dry_run: bool,
+    /// How often stored objects are read back and checked against their
+    /// digests.
+    #[serde(deserialize_with = "duration")]
+    pub scrub_interval: i64,
+    /// Most files checked per run.
+    pub scrub_batch_size: i64,
+    /// Files are checked again once this long has passed since their last
+    /// check.
+    #[serde(deserialize_with = "duration")]
+    pub scrub_every: i64,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs {
+            cleanup_interval: HOUR,
+            rewrap_interval: 10 * MINUTE,
+            rewrap_batch_size: 100,
+            cleanup_batch_size: 100,
+            abandoned_upload_after: DAY,
+            reconcile_interval: DAY,
+            reconcile_grace: DAY,
+            reconcile_dry_run: true,
+            scrub_interval: HOUR,
+            scrub_batch_size: 100,
+            scrub_every: 7 * DAY,
+        }
+    }
+}
+
+impl Config {
+    fn load() -> Result<Self, String> {
+        let mut config = match env("CONFIG_FILE") {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            }
+            None => Config::default(),
+        };
+
+        config.override_from_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read config file {}: {}", path, e))?;
+
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {}", path, e))
+    }
+
+    fn override_from_env(&mut self) -> Result<(), String> {
+        set(&mut self.server.listen, "LISTEN", list)?;
+        set(&mut self.paths.static_dir, "STATIC_DIR", string)?;
+        set(&mut self.paths.templates, "TEMPLATES", string)?;
+        set(&mut self.limits.max_upload_size, "MAX_UPLOAD_SIZE", number)?;
+        set(&mut self.database.url, "DATABASE_URL", string)?;
+        set(
+            &mut self.database.migrate_on_startup,
+            "MIGRATE_ON_STARTUP",
+            number,
+        )?;
+        set(
+            &mut self.database.pool_size,
+            "DATABASE_POOL_SIZE",
+            |value| value.parse().ok().map(Some),
+        )?;
+        set(
+            &mut self.placement.strategy,
+            "PLACEMENT_STRATEGY",
+            |value| Strategy::from_str(value).ok(),
+        )?;
+        set(
+            &mut self.placement.skip_unhealthy,
+            "PLACEMENT_SKIP_UNHEALTHY",
+            number,
+        )?;
+
+        let lifetime = &mut self.lifetime;
+        set(&mut lifetime.options, "LIFETIME_OPTIONS", |value| {
+            list(value)?.iter().map(|d| parse_duration(d)).collect()
+        })?;
+        set(&mut lifetime.min, "LIFETIME_MIN", parse_duration)?;
+        set(&mut lifetime.max, "LIFETIME_MAX", parse_duration)?;
+        set(&mut lifetime.default, "LIFETIME_DEFAULT", parse_duration)?;
+        set(
+            &mut lifetime.extend_on_access,
+            "LIFETIME_EXTEND_ON_ACCESS",
+            number,
+        )?;
+        set(
+            &mut lifetime.extend_by,
+            "LIFETIME_EXTEND_BY",
+            parse_duration,
+        )?;
+
+        let jobs = &mut self.jobs;
+        set(
+            &mut jobs.cleanup_interval,
+            "CLEANUP_INTERVAL",
+            parse_duration,
+        )?;
+        set(&mut jobs.rewrap_interval, "REWRAP_INTERVAL", parse_duration)?;
+        set(&mut jobs.rewrap_batch_size, "REWRAP_BATCH_SIZE", number)?;
+        set(&mut jobs.cleanup_batch_size, "CLEANUP_BATCH_SIZE", number)?;
+        set(
+            &mut jobs.abandoned_upload_after,
+            "ABANDONED_UPLOAD_AFTER",
+            parse_duration,
+        )?;
+        set(
+            &mut jobs.reconcile_interval,
+            "RECONCILE_INTERVAL",
+            parse_duration,
+        )?;
+        set(&mut jobs.reconcile_grace, "RECONCILE_GRACE", parse_duration)?;
+        set(&mut jobs.reconcile_dry_run, "RECONCILE_DRY_RUN", number)?;
+        set(&mut jobs.scrub_interval, "SCRUB_INTERVAL", parse_duration)?;
+        set(&mut jobs.scrub_batch_size, "SCRUB_BATCH_SIZE", number)?;
+        set(&mut jobs.scrub_every, "SCRUB_EVERY", parse_duration)?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.server.listen.is_empty() {
+            return Err("server.listen should list at least one address".to_string());
+        }
+
+        for address in &self.server.listen {
+            address
+                .to_socket_addrs()
+                .map_err(|e| format!("server.listen {} isn't an address: {}", address, e))?;
+        }
+
+        if self.limits.max_upload_size == 0 {
+            return Err("limits.max_upload_size should be more than 0".to_string());
+        }
+
+        if self.database.url.is_empty() {
+            return Err("database.url or DATABASE_URL should be set".to_string());
+        }
+
+        if self.database.pool_size == Some(0) {
+            return Err("database.pool_size should be more than 0".to_string());
+        }
+
+        self.lifetime.validate()?;
+
+        if self.jobs.cleanup_interval <= 0
+            || self.jobs.rewrap_interval <= 0
+            || self.jobs.reconcile_interval <= 0
+            || self.jobs.scrub_interval <= 0
+        {
+            return Err("jobs intervals should be longer than 0".to_string());
+        }
+
+        if self.jobs.rewrap_batch_size <= 0
+            || self.jobs.cleanup_batch_size <= 0
+            || self.jobs.scrub_batch_size <= 0
+        {
+            return Err("jobs batch sizes should be more than 0".to_string());
+        }
+
+        if self.jobs.abandoned_upload_after <= 0 {
+            return Err("jobs.abandoned_upload_after should be longer than 0".to_string());
+        }
+
+        if self.jobs.reconcile_grace <= 0 {
+            return Err("jobs.reconcile_grace should be longer than 0".to_string());
+        }
+
+        if self.jobs.scrub_every <= 0 {
+            return Err("jobs.scrub_every should be longer than 0".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Checks what only serving needs, so admin commands also run where the
+    /// frontend isn't around.
+    pub fn validate_serve(&self) -> Result<(), String> {
+        if !Path::new(&self.paths.static_dir).is_dir() {
+            return Err(format!(
+                "paths.static_dir {} isn't a directory",
+                self.paths.static_dir
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Compose passes unset variables through as empty strings
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Overrides `field` with the environment variable `name` when it's set.
+fn set<T>(field: &mut T, name: &str, parse: impl Fn(&str) -> Option<T>) -> Result<(), String> {
+    if let Some(value) = env(name) {
+        *field = parse(&value).ok_or_else(|| format!("{} {} isn't valid", name, value))?;
+    }
+
+    Ok(())
+}
+
+fn string(value: &str) -> Option<String> {
+    Some(value.to_string())
+}
+
+fn number<T: FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+fn list(value: &str) -> Option<Vec<String>> {
+    Some(
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .collect(),
+    )
+}
+
+/// Makes sure the configuration is valid, so a broken setup fails at startup
+/// with a clear error instead of on the first request.
+pub fn check_config() -> Result<(), String> {
+    CONFIG.as_ref().map(|_| ()).map_err(|e| e.clone())
+}
+
+pub fn config() -> &'static Config {
+    CONFIG.as_ref().expect("Config is checked at startup")
+}
+
+/// A number of seconds, or a number followed by `m`, `h`, `d` or `w`.
+pub fn parse_duration(duration: &str) -> Option<i64> {
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+
+    let unit = match unit {
+        "s" => 1,
+        "m" => MINUTE,
+        "h" => HOUR,
+        "d" => DAY,
+        "w" => WEEK,
+        _ => return None,
+    };
+
+    number.parse::<i64>().ok()?.checked_mul(unit)
+}
+
+/// A duration in the config file, either a number of seconds or a string
+/// like `28d`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDuration {
+    Seconds(i64),
+    Text(String),
+}
+
+impl RawDuration {
+    fn seconds<E: Error>(self) -> Result<i64, E> {
+        match self {
+            RawDuration::Seconds(seconds) => Ok(seconds),
+            RawDuration::Text(duration) => parse_duration(&duration)
+                .ok_or_else(|| E::custom(format!("{} isn't a duration", duration))),
+        }
+    }
+}
+
+/// Reads a duration written like `28d` or `3600` as seconds.
+pub fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    RawDuration::deserialize(deserializer)?.seconds()
+}
+
+pub fn durations<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<i64>, D::Error> {
+    Vec::<RawDuration>::deserialize(deserializer)?
+        .into_iter()
+        .map(RawDuration::seconds)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30"), Some(30));
+        assert_eq!(parse_duration("5m"), Some(5 * MINUTE));
+        assert_eq!(parse_duration("1h"), Some(HOUR));
+        assert_eq!(parse_duration("2d"), Some(2 * DAY));
+        assert_eq!(parse_duration("1w"), Some(WEEK));
+
+        for invalid in ["", "m", "5y", "-5", "1.5h", "5 m"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn reads_durations_as_numbers_or_strings() {
+        let config: Config = toml::from_str(
+            r#"
+            [lifetime]
+            options = [3600, "1d"]
+            max = "4w"
+
+            [jobs]
+            cleanup_interval = 90
+            scrub_every = "2w"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.lifetime.options, [HOUR, DAY]);
+        assert_eq!(config.lifetime.max, 4 * WEEK);
+        assert_eq!(config.jobs.cleanup_interval, 90);
+        assert_eq!(config.jobs.scrub_every, 2 * WEEK);
+        assert_eq!(config.jobs.reconcile_grace, DAY);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        for invalid in [
+            "[jobs]\ncleanup_interval = \"5y\"",
+            "[jobs]\ncleanup_interval = true",
+            "[lifetime]\noptions = [\"1d\", \"soon\"]",
+            "[server]\nlisten_on = [\"0.0.0.0:8080\"]",
+            "[placement]\nstrategy = \"nearest\"",
+        ] {
+            assert!(toml::from_str::<Config>(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn validates_settings() {
+        let valid = || Config {
+            database: Database {
+                url: "postgres://localhost/cipherdrop".to_string(),
+                ..Database::default()
+            },
+            ..Config::default()
+        };
+        assert!(valid().validate().is_ok());
+
+        let mut config = valid();
+        config.database.url.clear();
+        assert!(config.validate().is_err());
+
+        let mut config = valid();
+        config.database.pool_size = Some(0);
+        assert!(config.validate().is_err());
+
+        let mut config = valid();
+        config.server.listen.clear();
+        assert!(config.validate().is_err());
+
+        let mut config = valid();
+        config.jobs.scrub_interval = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = valid();
+        config.lifetime.default = 2 * config.lifetime.max;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn checks_static_dir_only_for_serving() {
+        let mut config = Config::default();
+        config.database.url = "postgres://localhost/cipherdrop".to_string();
+        config.paths.static_dir = "./does-not-exist".to_string();
+
+        assert!(config.validate().is_ok());
+        assert!(config.validate_serve().is_err());
+    }
+}
@@ -1,39 +1,44 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
 use base64::{engine::general_purpose, Engine};
 use rand::RngCore;
 
+/// Files sealed with a single AES-256-GCM call over the whole body.
+pub const LEGACY_VERSION: i16 = 0;
+/// Files sealed with the segmented stream format, see `docs/encryption.md`.
+pub const STREAM_VERSION: i16 = 1;
+
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+pub const TAG_SIZE: usize = 16;
+pub const ENCRYPTED_SEGMENT_SIZE: usize = SEGMENT_SIZE + TAG_SIZE;
+
+const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Magic, format version, log2 of the segment size and two reserved bytes.
+/// Every segment is authenticated against it.
+pub const HEADER: [u8; 8] = [b'C', b'D', b'S', b'E', STREAM_VERSION as u8, 16, 0, 0];
+
 pub struct Encrypted {
     pub key: String,
     pub nonce: String,
+    pub version: i16,
     pub result: Vec<u8>,
 }
 
-pub fn encrypt(value: Vec<u8>) -> Option<Encrypted> {
-    let key = Aes256Gcm::generate_key(&mut OsRng);
-    let key_base64 = general_purpose::STANDARD.encode(key.as_slice());
-
-    let mut nonce_bytes = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
-    let nonce_base64 = general_purpose::STANDARD.encode(nonce_bytes);
-
-    let cipher = Aes256Gcm::new(&key);
-    let ciphertext = match cipher.encrypt(nonce, value.as_ref()) {
-        Ok(res) => res,
-        _ => return None,
-    };
-
-    Some(Encrypted {
-        key: key_base64,
-        nonce: nonce_base64,
-        result: ciphertext,
-    })
+pub fn decrypt(en: Encrypted) -> Option<Vec<u8>> {
+    match en.version {
+        LEGACY_VERSION => decrypt_legacy(en),
+        STREAM_VERSION => {
+            let decryptor = StreamDecryptor::new(&en.key, &en.nonce)?;
+            decryptor.decrypt_all(&en.result)
+        }
+        _ => None,
+    }
 }
 
-pub fn decrypt(en: Encrypted) -> Option<Vec<u8>> {
+fn decrypt_legacy(en: Encrypted) -> Option<Vec<u8>> {
     let decoded_key = general_purpose::STANDARD
         .decode(&en.key)
         .expect("Failed decoding base64 key"); // This is ok since the key never leaves our eco
@@ -45,8 +50,325 @@ pub fn decrypt(en: Encrypted) -> Option<Vec<u8>> {
     let nonce = Nonce::from_slice(&decoded_nonce);
 
     let cipher_for_decryption = Aes256Gcm::new(key_for_decryption);
-    match cipher_for_decryption.decrypt(nonce, en.result.as_ref()) {
-        Ok(res) => Some(res),
-        _ => return None,
+    cipher_for_decryption
+        .decrypt(nonce, en.result.as_ref())
+        .ok()
+}
+
+/// Size of the plaintext inside a stream encrypted object of `size` bytes.
+pub fn plaintext_size(size: u64) -> Option<u64> {
+    let body = size.checked_sub(HEADER.len() as u64)?;
+    let segments = body.div_ceil(ENCRYPTED_SEGMENT_SIZE as u64);
+    let last_segment =
+        body.checked_sub(segments.checked_sub(1)? * ENCRYPTED_SEGMENT_SIZE as u64)?;
+    if last_segment < TAG_SIZE as u64 {
+        return None;
+    }
+
+    Some(body - segments * TAG_SIZE as u64)
+}
+
+/// Number of segments a plaintext of `size` bytes is split into.
+pub fn segment_count(size: u64) -> u64 {
+    size.div_ceil(SEGMENT_SIZE as u64).max(1)
+}
+
+/// Offset of segment `index` inside a stream encrypted object.
+pub fn segment_offset(index: u64) -> u64 {
+    HEADER.len() as u64 + index * ENCRYPTED_SEGMENT_SIZE as u64
+}
+
+fn segment_nonce(prefix: &[u8; NONCE_PREFIX_SIZE], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Incremental encryption into the segmented stream format.
+///
+/// Feed the plaintext through [`StreamEncryptor::update`] and write out
+/// whatever it returns, then write the final segment returned by
+/// [`StreamEncryptor::finalize`]. At most one segment is buffered, because the
+/// last segment can only be sealed once we know no more data follows.
+pub struct StreamEncryptor {
+    key: String,
+    nonce: String,
+    cipher: Aes256Gcm,
+    prefix: [u8; NONCE_PREFIX_SIZE],
+    buffer: Vec<u8>,
+    index: u32,
+    header_written: bool,
+}
+
+impl StreamEncryptor {
+    pub fn new() -> Self {
+        let key = Aes256Gcm::generate_key(&mut OsRng);
+
+        let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut prefix);
+
+        StreamEncryptor {
+            key: general_purpose::STANDARD.encode(key.as_slice()),
+            nonce: general_purpose::STANDARD.encode(prefix),
+            cipher: Aes256Gcm::new(&key),
+            prefix,
+            buffer: Vec::with_capacity(SEGMENT_SIZE),
+            index: 0,
+            header_written: false,
+        }
+    }
+
+    /// Continues an encryption from the state saved with
+    /// [`StreamEncryptor::state`], so it can span several requests. The header
+    /// has to be part of what was written before.
+    pub fn resume(key: &str, nonce: &str, index: u32, buffer: Vec<u8>) -> Option<Self> {
+        let decoded_key = general_purpose::STANDARD.decode(key).ok()?;
+        let prefix = general_purpose::STANDARD.decode(nonce).ok()?;
+
+        Some(StreamEncryptor {
+            key: key.to_string(),
+            nonce: nonce.to_string(),
+            cipher: Aes256Gcm::new_from_slice(&decoded_key).ok()?,
+            prefix: prefix.try_into().ok()?,
+            buffer,
+            index,
+            header_written: true,
+        })
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    /// The index of the next segment and the plaintext that hasn't been
+    /// sealed yet. Together with the key and nonce this is everything needed
+    /// to resume.
+    pub fn state(&self) -> (u32, &[u8]) {
+        (self.index, &self.buffer)
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = self.take_header();
+        self.buffer.extend_from_slice(data);
+
+        while self.buffer.len() > SEGMENT_SIZE {
+            let segment: Vec<u8> = self.buffer.drain(..SEGMENT_SIZE).collect();
+            out.extend_from_slice(&self.seal(&segment, false));
+        }
+
+        out
+    }
+
+    /// Finishes the stream. The returned [`Encrypted::result`] holds the final
+    /// segment that has to be written after everything returned by `update`.
+    pub fn finalize(mut self) -> Encrypted {
+        let mut result = self.take_header();
+        let segment = std::mem::take(&mut self.buffer);
+        result.extend_from_slice(&self.seal(&segment, true));
+
+        Encrypted {
+            key: self.key,
+            nonce: self.nonce,
+            version: STREAM_VERSION,
+            result,
+        }
+    }
+
+    fn take_header(&mut self) -> Vec<u8> {
+        if self.header_written {
+            return Vec::new();
+        }
+
+        self.header_written = true;
+        HEADER.to_vec()
+    }
+
+    fn seal(&mut self, segment: &[u8], last: bool) -> Vec<u8> {
+        let nonce = segment_nonce(&self.prefix, self.index, last);
+        self.index += 1;
+
+        self.cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: segment,
+                    aad: &HEADER,
+                },
+            )
+            .expect("Segment is always small enough to seal")
+    }
+}
+
+/// Opens segments of the stream format, in any order.
+pub struct StreamDecryptor {
+    cipher: Aes256Gcm,
+    prefix: [u8; NONCE_PREFIX_SIZE],
+}
+
+impl StreamDecryptor {
+    pub fn new(key: &str, nonce: &str) -> Option<Self> {
+        let key = general_purpose::STANDARD.decode(key).ok()?;
+        let prefix = general_purpose::STANDARD.decode(nonce).ok()?;
+
+        Some(StreamDecryptor {
+            cipher: Aes256Gcm::new_from_slice(&key).ok()?,
+            prefix: prefix.try_into().ok()?,
+        })
+    }
+
+    /// `last` has to be set for the final segment of the object, otherwise
+    /// authentication fails. This is what detects truncated files.
+    pub fn open_segment(&self, index: u32, last: bool, segment: &[u8]) -> Option<Vec<u8>> {
+        let nonce = segment_nonce(&self.prefix, index, last);
+
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: segment,
+                    aad: &HEADER,
+                },
+            )
+            .ok()
+    }
+
+    pub fn decrypt_all(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let body = data.strip_prefix(HEADER.as_slice())?;
+        if body.is_empty() {
+            return None;
+        }
+
+        let segments = body.len().div_ceil(ENCRYPTED_SEGMENT_SIZE);
+        let mut result = Vec::with_capacity(body.len());
+
+        for (index, segment) in body.chunks(ENCRYPTED_SEGMENT_SIZE).enumerate() {
+            let last = index + 1 == segments;
+            result.extend_from_slice(&self.open_segment(index as u32, last, segment)?);
+        }
+
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    // The test vectors from docs/encryption.md
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    const PREFIX: &str = "oKGio6Slpg==";
+
+    fn seal(plaintext: &[u8]) -> Vec<u8> {
+        // Resuming at the start skips the random key and prefix `new` picks
+        let mut encryptor = StreamEncryptor::resume(KEY, PREFIX, 0, Vec::new()).unwrap();
+        let mut sealed = HEADER.to_vec();
+        sealed.extend_from_slice(&encryptor.update(plaintext));
+        sealed.extend_from_slice(&encryptor.finalize().result);
+        sealed
+    }
+
+    fn open(sealed: &[u8]) -> Option<Vec<u8>> {
+        StreamDecryptor::new(KEY, PREFIX)
+            .unwrap()
+            .decrypt_all(sealed)
+    }
+
+    fn large_plaintext() -> Vec<u8> {
+        (0..131073).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn seals_empty_vector() {
+        let sealed = seal(b"");
+        assert_eq!(
+            hex::encode(&sealed),
+            "434453450110000000429a73c5b639bcfda77ecbdae10fd2"
+        );
+        assert_eq!(open(&sealed).unwrap(), b"");
+    }
+
+    #[test]
+    fn seals_short_vector() {
+        let sealed = seal(b"CipherDrop");
+        assert_eq!(
+            hex::encode(&sealed),
+            "434453450110000034083976dd74a4b26c156ebe541adce6c8959bb3a1eaceb33842"
+        );
+        assert_eq!(open(&sealed).unwrap(), b"CipherDrop");
+    }
+
+    #[test]
+    fn seals_multi_segment_vector() {
+        let plaintext = large_plaintext();
+        let sealed = seal(&plaintext);
+
+        assert_eq!(sealed.len(), 131129);
+        assert_eq!(
+            hex::encode(Sha256::digest(&sealed)),
+            "8a819b9bb9f906b126c91d0e4816e01304c89407e3262dcccfa976c9b0cc131c"
+        );
+        assert_eq!(plaintext_size(sealed.len() as u64), Some(131073));
+        assert_eq!(open(&sealed).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn resumed_stream_matches_one_pass() {
+        let plaintext = large_plaintext();
+
+        let mut encryptor = StreamEncryptor::resume(KEY, PREFIX, 0, Vec::new()).unwrap();
+        let mut sealed = HEADER.to_vec();
+        for chunk in plaintext.chunks(50000) {
+            sealed.extend_from_slice(&encryptor.update(chunk));
+
+            let (index, buffer) = encryptor.state();
+            encryptor = StreamEncryptor::resume(KEY, PREFIX, index, buffer.to_vec()).unwrap();
+        }
+        sealed.extend_from_slice(&encryptor.finalize().result);
+
+        assert_eq!(sealed, seal(&plaintext));
+    }
+
+    #[test]
+    fn truncated_stream_fails() {
+        let sealed = seal(&large_plaintext());
+
+        assert!(open(&sealed[..segment_offset(2) as usize]).is_none());
+        assert!(open(&sealed[..sealed.len() - 1]).is_none());
+        assert!(open(&HEADER).is_none());
+    }
+
+    #[test]
+    fn reordered_segments_fail() {
+        let sealed = seal(&large_plaintext());
+        let first = segment_offset(0) as usize..segment_offset(1) as usize;
+        let second = segment_offset(1) as usize..segment_offset(2) as usize;
+
+        let mut reordered = HEADER.to_vec();
+        reordered.extend_from_slice(&sealed[second]);
+        reordered.extend_from_slice(&sealed[first]);
+        reordered.extend_from_slice(&sealed[segment_offset(2) as usize..]);
+
+        assert!(open(&reordered).is_none());
+    }
+
+    #[test]
+    fn flipped_final_flag_fails() {
+        let sealed = seal(&large_plaintext());
+        let decryptor = StreamDecryptor::new(KEY, PREFIX).unwrap();
+        let middle = &sealed[segment_offset(1) as usize..segment_offset(2) as usize];
+        let last = &sealed[segment_offset(2) as usize..];
+
+        assert!(decryptor.open_segment(1, false, middle).is_some());
+        assert!(decryptor.open_segment(1, true, middle).is_none());
+        assert!(decryptor.open_segment(2, true, last).is_some());
+        assert!(decryptor.open_segment(2, false, last).is_none());
     }
 }
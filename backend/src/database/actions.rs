@@ -1,22 +1,145 @@
-use chrono::{Duration, NaiveDateTime, Utc};
-use diesel::{ExpressionMethods, QueryDsl};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use diesel::{
+    define_sql_function,
+    dsl::{count_star, exists, not, sql},
+    sql_types::{BigInt, Nullable, Timestamp},
+    BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, OptionalExtension,
+    PgSortExpressionMethods, QueryDsl,
+};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use uuid::Uuid;
 
 use crate::{
     crypt::Encrypted,
-    schema::{files, s3_buckets},
+    files::FileUpload,
+    password,
+    schema::{bundles, files, job_runs, s3_buckets, tus_uploads},
 };
 
 use super::{
-    models::{self, NewFile},
+    models::{self, NewBundle, NewFile, NewS3Bucket, NewTusUpload, TusProgress},
     DbError,
 };
 
-pub async fn get_s3_bucket(
+define_sql_function!(fn pg_try_advisory_lock(key: BigInt) -> Bool);
+define_sql_function!(fn pg_advisory_unlock(key: BigInt) -> Bool);
+
+pub async fn get_writable_s3_buckets(
+    conn: &mut AsyncPgConnection,
+) -> Result<Vec<models::S3Bucket>, DbError> {
+    Ok(s3_buckets::table
+        .filter(s3_buckets::status.eq(models::BUCKET_ACTIVE))
+        .order(s3_buckets::id)
+        .load::<models::S3Bucket>(conn)
+        .await?)
+}
+
+pub async fn get_s3_buckets(
+    conn: &mut AsyncPgConnection,
+) -> Result<Vec<models::S3Bucket>, DbError> {
+    Ok(s3_buckets::table
+        .order(s3_buckets::id)
+        .load::<models::S3Bucket>(conn)
+        .await?)
+}
+
+/// Number of files and stored bytes per bucket. Buckets without files are left out.
+pub async fn get_s3_bucket_usage(
     conn: &mut AsyncPgConnection,
-) -> Result<Option<models::S3Bucket>, DbError> {
-    Ok(s3_buckets::table.first::<models::S3Bucket>(conn).await.ok())
+) -> Result<Vec<(i32, i64, i64)>, DbError> {
+    Ok(files::table
+        .group_by(files::s3_bucket_id)
+        .select((
+            files::s3_bucket_id,
+            count_star(),
+            sql::<BigInt>("COALESCE(SUM(files.size), 0)::BIGINT"),
+        ))
+        .load::<(i32, i64, i64)>(conn)
+        .await?)
+}
+
+pub async fn add_s3_bucket(
+    conn: &mut AsyncPgConnection,
+    bucket: NewS3Bucket<'_>,
+) -> Result<i32, DbError> {
+    Ok(diesel::insert_into(s3_buckets::table)
+        .values(&bucket)
+        .returning(s3_buckets::id)
+        .get_result(conn)
+        .await?)
+}
+
+pub async fn set_s3_bucket_status(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    status: &str,
+) -> Result<usize, DbError> {
+    Ok(
+        diesel::update(s3_buckets::table.filter(s3_buckets::id.eq(id)))
+            .set(s3_buckets::status.eq(status))
+            .execute(conn)
+            .await?,
+    )
+}
+
+pub async fn set_s3_bucket_credentials(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    access_key: &str,
+    secret_key: &str,
+) -> Result<usize, DbError> {
+    Ok(
+        diesel::update(s3_buckets::table.filter(s3_buckets::id.eq(id)))
+            .set((
+                s3_buckets::access_key.eq(access_key),
+                s3_buckets::secret_key.eq(secret_key),
+            ))
+            .execute(conn)
+            .await?,
+    )
+}
+
+pub async fn count_files_in_s3_bucket(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Result<i64, DbError> {
+    Ok(files::table
+        .filter(files::s3_bucket_id.eq(id))
+        .count()
+        .get_result(conn)
+        .await?)
+}
+
+/// Every file in the bucket, with when it was created.
+pub async fn get_files_in_s3_bucket(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Result<Vec<(Uuid, NaiveDateTime)>, DbError> {
+    Ok(files::table
+        .filter(files::s3_bucket_id.eq(id))
+        .select((files::file, files::date_created))
+        .load::<(Uuid, NaiveDateTime)>(conn)
+        .await?)
+}
+
+/// Resumable uploads in progress in the bucket.
+pub async fn get_tus_uploads_in_s3_bucket(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Result<Vec<Uuid>, DbError> {
+    Ok(tus_uploads::table
+        .filter(tus_uploads::s3_bucket_id.eq(id))
+        .select(tus_uploads::file)
+        .load::<Uuid>(conn)
+        .await?)
+}
+
+pub async fn delete_s3_bucket(conn: &mut AsyncPgConnection, id: i32) -> Result<usize, DbError> {
+    Ok(
+        diesel::delete(s3_buckets::table.filter(s3_buckets::id.eq(id)))
+            .execute(conn)
+            .await?,
+    )
 }
 
 pub async fn get_s3_bucket_by_id(
@@ -32,65 +155,553 @@ pub async fn get_s3_bucket_by_id(
 pub async fn add_file_record(
     conn: &mut AsyncPgConnection,
     encrypted_file: Encrypted,
-    unique_id: Uuid,
-    file_name: String,
-    file_type: String,
-    lifetime: i64,
-    s3_bucket_id: i32,
-) -> Result<(), ()> {
+    kek_id: i32,
+    delete_token_hash: &str,
+    upload: FileUpload,
+    available_till: i64,
+) -> Result<(), DbError> {
+    let (file_name, file_type, metadata, metadata_version) = upload.metadata.columns();
     let new_file = NewFile {
-        file: &unique_id,
-        file_name: &file_name,
-        file_type: &file_type,
+        file: &upload.unique_id,
+        file_name,
+        file_type,
         key: &encrypted_file.key,
         nonce: &encrypted_file.nonce,
-        available_till: NaiveDateTime::from_timestamp(lifetime.into(), 0),
-        s3_bucket_id: &s3_bucket_id,
+        available_till: DateTime::from_timestamp(available_till, 0)
+            .unwrap_or_default()
+            .naive_utc(),
+        s3_bucket_id: &upload.s3_bucket_id,
+        encryption_version: encrypted_file.version,
+        size: upload.size,
+        kek_id: Some(kek_id),
+        max_downloads: upload.max_downloads,
+        delete_token_hash: Some(delete_token_hash),
+        stored_sha256: upload.stored_sha256.as_deref(),
+        client_sha256: upload.client_sha256.as_deref(),
+        password_salt: upload
+            .password
+            .as_ref()
+            .map(|password| password.salt.as_str()),
+        password_verifier: upload
+            .password
+            .as_ref()
+            .map(|password| password.verifier.as_str()),
+        password_key_mix: upload
+            .password
+            .as_ref()
+            .is_some_and(|password| password.key_mix),
+        metadata,
+        metadata_version,
+        padded: upload.padded,
     };
 
-    let result = diesel::insert_into(files::table)
+    diesel::insert_into(files::table)
         .values(&new_file)
         .execute(conn)
-        .await;
+        .await?;
 
-    if result.is_err() {
-        return Err(());
-    }
     Ok(())
 }
 
+/// The file `file_uuid`. With `extend_by` set, it's kept available for at
+/// least that long from now unless its bucket is draining. Files that have
+/// already expired are returned as they are and never extended, and files in
+/// a bundle are only extended with it, see `get_bundle_record`.
 pub async fn get_file_record(
     conn: &mut AsyncPgConnection,
     file_uuid: Uuid,
-) -> Result<models::File, DbError> {
-    let found_file = files::table
+    extend_by: Option<Duration>,
+) -> Result<Option<models::File>, DbError> {
+    let mut found_file = match files::table
         .filter(files::file.eq(file_uuid))
         .first::<models::File>(conn)
+        .await
+        .optional()?
+    {
+        Some(found_file) => found_file,
+        None => return Ok(None),
+    };
+
+    let now = Utc::now().naive_utc();
+    let extend_by = match extend_by {
+        Some(extend_by) if found_file.available_till > now && found_file.bundle_id.is_none() => {
+            extend_by
+        }
+        _ => return Ok(Some(found_file)),
+    };
+
+    let bucket_status = s3_buckets::table
+        .filter(s3_buckets::id.eq(found_file.s3_bucket_id))
+        .select(s3_buckets::status)
+        .first::<String>(conn)
         .await?;
 
-    let new_available_till = Utc::now().naive_utc() + Duration::hours(24);
-    if found_file.available_till < new_available_till {
-        let _ = diesel::update(files::table.filter(files::file.eq(file_uuid)))
-            .set(files::available_till.eq(new_available_till))
-            .execute(conn)
-            .await?;
+    let new_available_till = now + extend_by;
+    if bucket_status != models::BUCKET_DRAINING && found_file.available_till < new_available_till {
+        // Guarded again, the file could expire between the two queries
+        let updated = diesel::update(
+            files::table
+                .filter(files::file.eq(file_uuid))
+                .filter(files::available_till.gt(now)),
+        )
+        .set(files::available_till.eq(new_available_till))
+        .execute(conn)
+        .await?;
+
+        if updated > 0 {
+            found_file.available_till = new_available_till;
+        }
     }
 
-    Ok(found_file)
+    Ok(Some(found_file))
+}
+
+/// The file `file_uuid`, if `delete_token_hash` matches its delete token.
+/// Unlike `get_file_record` this doesn't count as an access.
+pub async fn get_file_by_delete_token(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+    delete_token_hash: &str,
+) -> Result<Option<models::File>, DbError> {
+    Ok(files::table
+        .filter(files::file.eq(file_uuid))
+        .filter(files::delete_token_hash.eq(delete_token_hash))
+        .first::<models::File>(conn)
+        .await
+        .optional()?)
 }
 
-pub async fn get_expired_files(conn: &mut AsyncPgConnection) -> Result<Vec<models::File>, DbError> {
+/// Up to `limit` expired files, leaving out those in `skip_buckets`. Files
+/// that used up their downloads are included once their last download was
+/// counted before `used_up_before`.
+pub async fn get_expired_files(
+    conn: &mut AsyncPgConnection,
+    skip_buckets: &[i32],
+    used_up_before: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<models::File>, DbError> {
     let current_time = Utc::now().naive_utc();
 
+    // Files that used up their downloads normally delete themselves once the
+    // last download is streamed, this catches the ones whose last download
+    // was cut off. Until the grace period is over it may still be streaming.
     Ok(files::table
-        .filter(files::available_till.lt(current_time))
+        .filter(
+            files::available_till
+                .lt(current_time)
+                .or(files::download_count
+                    .nullable()
+                    .ge(files::max_downloads)
+                    .and(files::date_downloaded.lt(used_up_before))
+                    .assume_not_null()),
+        )
+        .filter(files::s3_bucket_id.ne_all(skip_buckets))
+        .order(files::available_till)
+        .limit(limit)
         .load::<models::File>(conn)
         .await?)
 }
 
+/// Counts a download of a file with a download limit, unless the limit has
+/// been reached. Returns the new download count, or `None` when no downloads
+/// are left.
+pub async fn claim_download(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+) -> Result<Option<i32>, DbError> {
+    Ok(diesel::update(
+        files::table
+            .filter(files::file.eq(file_uuid))
+            .filter(files::download_count.nullable().lt(files::max_downloads)),
+    )
+    .set((
+        files::download_count.eq(files::download_count + 1),
+        files::date_downloaded.eq(Utc::now().naive_utc()),
+    ))
+    .returning(files::download_count)
+    .get_result(conn)
+    .await
+    .optional()?)
+}
+
+/// Counts a password attempt on file `id` as a failure, unless attempts are
+/// locked out. Once `password::FREE_ATTEMPTS` are used up, every attempt locks
+/// out the next ones for twice as long as the last, starting at
+/// `password::LOCKOUT` seconds. Returns the number of failures in a row.
+pub async fn claim_password_attempt(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    now: NaiveDateTime,
+) -> Result<Option<i32>, DbError> {
+    let locked_till = sql::<Nullable<Timestamp>>(&format!(
+        "CASE WHEN password_failures + 1 >= {} THEN ",
+        password::FREE_ATTEMPTS
+    ))
+    .bind::<Timestamp, _>(now)
+    .sql(&format!(
+        " + make_interval(secs => LEAST({} * POWER(2, LEAST(password_failures + 1 - {}, 20)), {})) END",
+        password::LOCKOUT,
+        password::FREE_ATTEMPTS,
+        password::MAX_LOCKOUT
+    ));
+
+    Ok(diesel::update(
+        files::table.filter(files::id.eq(id)).filter(
+            files::password_locked_till
+                .is_null()
+                .or(files::password_locked_till.le(now)),
+        ),
+    )
+    .set((
+        files::password_failures.eq(files::password_failures + 1),
+        files::password_locked_till.eq(locked_till),
+    ))
+    .returning(files::password_failures)
+    .get_result(conn)
+    .await
+    .optional()?)
+}
+
+pub async fn reset_password_failures(conn: &mut AsyncPgConnection, id: i32) -> Result<(), DbError> {
+    diesel::update(files::table.filter(files::id.eq(id)))
+        .set((
+            files::password_failures.eq(0),
+            files::password_locked_till.eq(None::<NaiveDateTime>),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
 pub async fn delete_file(conn: &mut AsyncPgConnection, file_uuid: Uuid) -> Result<(), DbError> {
     diesel::delete(files::table.filter(files::file.eq(file_uuid)))
         .execute(conn)
         .await?;
     Ok(())
 }
+
+pub async fn add_bundle(
+    conn: &mut AsyncPgConnection,
+    new_bundle: &NewBundle<'_>,
+) -> Result<i32, DbError> {
+    Ok(diesel::insert_into(bundles::table)
+        .values(new_bundle)
+        .returning(bundles::id)
+        .get_result(conn)
+        .await?)
+}
+
+/// Puts the files `file_ids` that aren't in a bundle yet in bundle `id`, to
+/// be available till exactly when it is. Returns how many were put in.
+pub async fn add_files_to_bundle(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    file_ids: &[i32],
+    available_till: NaiveDateTime,
+) -> Result<usize, DbError> {
+    Ok(diesel::update(
+        files::table
+            .filter(files::id.eq_any(file_ids))
+            .filter(files::bundle_id.is_null()),
+    )
+    .set((
+        files::bundle_id.eq(id),
+        files::available_till.eq(available_till),
+    ))
+    .execute(conn)
+    .await?)
+}
+
+/// The bundle `bundle_uuid`, extended like `get_file_record` extends a file.
+/// Its files are extended along with it, except those in a draining bucket.
+pub async fn get_bundle_record(
+    conn: &mut AsyncPgConnection,
+    bundle_uuid: Uuid,
+    extend_by: Option<Duration>,
+) -> Result<Option<models::Bundle>, DbError> {
+    let mut found_bundle = match bundles::table
+        .filter(bundles::bundle.eq(bundle_uuid))
+        .first::<models::Bundle>(conn)
+        .await
+        .optional()?
+    {
+        Some(found_bundle) => found_bundle,
+        None => return Ok(None),
+    };
+
+    let now = Utc::now().naive_utc();
+    let new_available_till = match extend_by {
+        Some(extend_by) if found_bundle.available_till > now => now + extend_by,
+        _ => return Ok(Some(found_bundle)),
+    };
+
+    if found_bundle.available_till >= new_available_till {
+        return Ok(Some(found_bundle));
+    }
+
+    let updated = diesel::update(
+        bundles::table
+            .filter(bundles::id.eq(found_bundle.id))
+            .filter(bundles::available_till.gt(now)),
+    )
+    .set(bundles::available_till.eq(new_available_till))
+    .execute(conn)
+    .await?;
+
+    if updated > 0 {
+        found_bundle.available_till = new_available_till;
+
+        let extendable_buckets = s3_buckets::table
+            .filter(s3_buckets::status.ne(models::BUCKET_DRAINING))
+            .select(s3_buckets::id);
+        diesel::update(
+            files::table
+                .filter(files::bundle_id.eq(found_bundle.id))
+                .filter(files::available_till.gt(now))
+                .filter(files::s3_bucket_id.eq_any(extendable_buckets)),
+        )
+        .set(files::available_till.eq(new_available_till))
+        .execute(conn)
+        .await?;
+    }
+
+    Ok(Some(found_bundle))
+}
+
+/// The bundle `bundle_uuid`, if `delete_token_hash` matches its delete token.
+pub async fn get_bundle_by_delete_token(
+    conn: &mut AsyncPgConnection,
+    bundle_uuid: Uuid,
+    delete_token_hash: &str,
+) -> Result<Option<models::Bundle>, DbError> {
+    Ok(bundles::table
+        .filter(bundles::bundle.eq(bundle_uuid))
+        .filter(bundles::delete_token_hash.eq(delete_token_hash))
+        .first::<models::Bundle>(conn)
+        .await
+        .optional()?)
+}
+
+/// The files still in bundle `id`, in the order they were uploaded.
+pub async fn get_bundle_files(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Result<Vec<models::File>, DbError> {
+    Ok(files::table
+        .filter(files::bundle_id.eq(id))
+        .order(files::id)
+        .load::<models::File>(conn)
+        .await?)
+}
+
+pub async fn delete_bundle(conn: &mut AsyncPgConnection, id: i32) -> Result<(), DbError> {
+    diesel::delete(bundles::table.filter(bundles::id.eq(id)))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Deletes expired bundles whose files are all gone, which the cleanup of
+/// expired files takes care of. Returns how many were deleted.
+pub async fn delete_expired_bundles(conn: &mut AsyncPgConnection) -> Result<usize, DbError> {
+    let now = Utc::now().naive_utc();
+    let has_files = files::table.filter(files::bundle_id.eq(bundles::id.nullable()));
+
+    Ok(diesel::delete(
+        bundles::table
+            .filter(bundles::available_till.lt(now))
+            .filter(not(exists(has_files))),
+    )
+    .execute(conn)
+    .await?)
+}
+
+/// Files whose key isn't wrapped yet, or is wrapped with one of `retired_keks`.
+pub async fn get_files_to_rewrap(
+    conn: &mut AsyncPgConnection,
+    retired_keks: &[i32],
+    limit: i64,
+) -> Result<Vec<models::File>, DbError> {
+    Ok(files::table
+        .filter(
+            files::kek_id
+                .is_null()
+                .or(files::kek_id.eq_any(retired_keks)),
+        )
+        .order(files::id)
+        .limit(limit)
+        .load::<models::File>(conn)
+        .await?)
+}
+
+/// Replaces the wrapped key of a file, as long as it still holds `old_key`.
+pub async fn set_file_key(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    old_key: &str,
+    key: &str,
+    kek_id: i32,
+) -> Result<usize, DbError> {
+    Ok(diesel::update(
+        files::table
+            .filter(files::id.eq(id))
+            .filter(files::key.eq(old_key)),
+    )
+    .set((files::key.eq(key), files::kek_id.eq(kek_id)))
+    .execute(conn)
+    .await?)
+}
+
+/// Unexpired files with a digest that weren't verified since `before`, the
+/// ones never verified or verified longest ago first.
+pub async fn get_files_to_scrub(
+    conn: &mut AsyncPgConnection,
+    before: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<models::File>, DbError> {
+    Ok(files::table
+        .filter(files::stored_sha256.is_not_null())
+        .filter(files::available_till.gt(Utc::now().naive_utc()))
+        .filter(
+            files::date_verified
+                .is_null()
+                .or(files::date_verified.lt(before)),
+        )
+        .order((files::date_verified.asc().nulls_first(), files::id))
+        .limit(limit)
+        .load::<models::File>(conn)
+        .await?)
+}
+
+pub async fn set_file_verified(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+    date_verified: NaiveDateTime,
+) -> Result<(), DbError> {
+    diesel::update(files::table.filter(files::id.eq(id)))
+        .set(files::date_verified.eq(date_verified))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn add_tus_upload(
+    conn: &mut AsyncPgConnection,
+    upload: NewTusUpload<'_>,
+) -> Result<(), DbError> {
+    diesel::insert_into(tus_uploads::table)
+        .values(&upload)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_tus_upload(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+) -> Result<Option<models::TusUpload>, DbError> {
+    Ok(tus_uploads::table
+        .filter(tus_uploads::file.eq(file_uuid))
+        .first::<models::TusUpload>(conn)
+        .await
+        .optional()?)
+}
+
+/// Saves the progress of an upload, as long as no other request moved it on
+/// from `old_offset` in the meantime. Returns whether it was saved.
+pub async fn set_tus_progress(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+    old_offset: i64,
+    progress: TusProgress<'_>,
+) -> Result<bool, DbError> {
+    let updated = diesel::update(
+        tus_uploads::table
+            .filter(tus_uploads::file.eq(file_uuid))
+            .filter(tus_uploads::upload_offset.eq(old_offset)),
+    )
+    .set(&progress)
+    .execute(conn)
+    .await?;
+
+    Ok(updated > 0)
+}
+
+pub async fn delete_tus_upload(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+) -> Result<(), DbError> {
+    diesel::delete(tus_uploads::table.filter(tus_uploads::file.eq(file_uuid)))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Up to `limit` uploads that haven't received any data since `before`,
+/// leaving out those in `skip_buckets`.
+pub async fn get_abandoned_tus_uploads(
+    conn: &mut AsyncPgConnection,
+    skip_buckets: &[i32],
+    before: NaiveDateTime,
+    limit: i64,
+) -> Result<Vec<models::TusUpload>, DbError> {
+    Ok(tus_uploads::table
+        .filter(tus_uploads::date_updated.lt(before))
+        .filter(tus_uploads::s3_bucket_id.ne_all(skip_buckets))
+        .order(tus_uploads::date_updated)
+        .limit(limit)
+        .load::<models::TusUpload>(conn)
+        .await?)
+}
+
+/// Takes the session level advisory lock `key` if no other session holds it.
+/// It's held until `unlock` is called on the same connection, or the
+/// connection is closed.
+pub async fn try_lock(conn: &mut AsyncPgConnection, key: i64) -> Result<bool, DbError> {
+    Ok(diesel::select(pg_try_advisory_lock(key))
+        .get_result(conn)
+        .await?)
+}
+
+pub async fn unlock(conn: &mut AsyncPgConnection, key: i64) -> Result<bool, DbError> {
+    Ok(diesel::select(pg_advisory_unlock(key))
+        .get_result(conn)
+        .await?)
+}
+
+pub async fn get_job_runs(conn: &mut AsyncPgConnection) -> Result<Vec<models::JobRun>, DbError> {
+    Ok(job_runs::table
+        .order(job_runs::name)
+        .load::<models::JobRun>(conn)
+        .await?)
+}
+
+pub async fn get_job_last_success(
+    conn: &mut AsyncPgConnection,
+    name: &str,
+) -> Result<Option<NaiveDateTime>, DbError> {
+    Ok(job_runs::table
+        .filter(job_runs::name.eq(name))
+        .select(job_runs::last_success)
+        .first::<NaiveDateTime>(conn)
+        .await
+        .optional()?)
+}
+
+pub async fn set_job_last_success(
+    conn: &mut AsyncPgConnection,
+    name: &str,
+    last_success: NaiveDateTime,
+) -> Result<(), DbError> {
+    diesel::insert_into(job_runs::table)
+        .values((
+            job_runs::name.eq(name),
+            job_runs::last_success.eq(last_success),
+        ))
+        .on_conflict(job_runs::name)
+        .do_update()
+        .set(job_runs::last_success.eq(last_success))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
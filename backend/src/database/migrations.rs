@@ -0,0 +1,132 @@
+use diesel::{migration::MigrationSource, pg::Pg, Connection};
+use diesel_async::{async_connection_wrapper::AsyncConnectionWrapper, AsyncPgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use crate::database::DbError;
+
+/// Everything in `backend/migrations`, built into the binary so deployments
+/// don't need the diesel CLI.
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+type MigrationConnection = AsyncConnectionWrapper<AsyncPgConnection>;
+
+/// Where the database stands compared to the migrations this build knows.
+pub struct Status {
+    /// Every known migration, and whether it has been applied.
+    pub migrations: Vec<(String, bool)>,
+    /// Migrations the database has that this build doesn't know about,
+    /// meaning it was migrated by a newer version.
+    pub unknown: Vec<String>,
+}
+
+impl Status {
+    pub fn pending(&self) -> Vec<&str> {
+        self.migrations
+            .iter()
+            .filter(|(_, applied)| !applied)
+            .map(|(version, _)| version.as_str())
+            .collect()
+    }
+}
+
+/// Migrations run on a blocking connection, so they're kept off the async
+/// workers.
+async fn with_connection<T, F>(database_url: &str, f: F) -> Result<T, DbError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut MigrationConnection) -> Result<T, DbError> + Send + 'static,
+{
+    let database_url = database_url.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let mut conn = MigrationConnection::establish(&database_url)?;
+        f(&mut conn)
+    })
+    .await?
+}
+
+pub async fn status(database_url: &str) -> Result<Status, DbError> {
+    with_connection(database_url, |conn| {
+        let applied: Vec<String> = conn
+            .applied_migrations()?
+            .into_iter()
+            .map(|version| version.to_string())
+            .collect();
+
+        let known: Vec<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+
+        Ok(Status {
+            unknown: applied
+                .iter()
+                .filter(|version| !known.contains(version))
+                .cloned()
+                .collect(),
+            migrations: known
+                .into_iter()
+                .map(|version| {
+                    let is_applied = applied.contains(&version);
+                    (version, is_applied)
+                })
+                .collect(),
+        })
+    })
+    .await
+}
+
+/// Applies every pending migration, returning their versions.
+pub async fn run_pending(database_url: &str) -> Result<Vec<String>, DbError> {
+    with_connection(database_url, |conn| {
+        Ok(conn
+            .run_pending_migrations(MIGRATIONS)?
+            .into_iter()
+            .map(|version| version.to_string())
+            .collect())
+    })
+    .await
+}
+
+/// Reverts the most recently applied migration, returning its version.
+pub async fn revert_last(database_url: &str) -> Result<String, DbError> {
+    with_connection(database_url, |conn| {
+        Ok(conn.revert_last_migration(MIGRATIONS)?.to_string())
+    })
+    .await
+}
+
+/// Makes sure the database schema is what this build expects before serving
+/// anything, applying pending migrations first when `migrate` is set.
+pub async fn check_schema(database_url: &str, migrate: bool) -> Result<(), String> {
+    if migrate {
+        let applied = run_pending(database_url)
+            .await
+            .map_err(|e| format!("Couldn't migrate the database: {}", e))?;
+
+        for version in applied {
+            println!("Applied migration {}", version);
+        }
+    }
+
+    let status = status(database_url)
+        .await
+        .map_err(|e| format!("Couldn't check the database schema: {}", e))?;
+
+    let pending = status.pending();
+    if !pending.is_empty() {
+        return Err(format!(
+            "The database is missing migrations {}, run `backend migrate up` or set database.migrate_on_startup",
+            pending.join(", ")
+        ));
+    }
+
+    if !status.unknown.is_empty() {
+        return Err(format!(
+            "The database has migrations {} this build doesn't know, it was migrated by a newer version",
+            status.unknown.join(", ")
+        ));
+    }
+
+    Ok(())
+}
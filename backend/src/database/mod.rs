@@ -1,4 +1,5 @@
 pub mod actions;
+pub mod migrations;
 pub mod models;
 
 pub type DbError = Box<dyn std::error::Error + Send + Sync>;
@@ -1,11 +1,20 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::schema::files;
+use crate::schema::{bundles, files, s3_buckets, tus_uploads};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+/// Only active buckets take new uploads. Any other status keeps serving the
+/// files already in the bucket.
+pub const BUCKET_ACTIVE: &str = "active";
+pub const BUCKET_READ_ONLY: &str = "read_only";
+/// Files in a draining bucket are no longer kept alive when accessed, so the
+/// bucket empties out and can be removed.
+pub const BUCKET_DRAINING: &str = "draining";
+
+/// `access_key` and `secret_key` are sealed with the master key, see
+/// `secrets.rs`. Only the s3 backend opens them.
+#[derive(Clone, Queryable)]
 #[diesel(table_name = s3_buckets)]
 pub struct S3Bucket {
     pub id: i32,
@@ -14,6 +23,22 @@ pub struct S3Bucket {
     pub endpoint: String,
     pub access_key: String,
     pub secret_key: String,
+    pub backend: String,
+    pub weight: i32,
+    pub status: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = s3_buckets)]
+pub struct NewS3Bucket<'a> {
+    pub bucket_name: &'a str,
+    pub region: &'a str,
+    pub endpoint: &'a str,
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub backend: &'a str,
+    pub weight: i32,
+    pub status: &'a str,
 }
 
 #[derive(Insertable)]
@@ -26,11 +51,25 @@ pub struct NewFile<'a> {
     pub nonce: &'a str,
     pub available_till: NaiveDateTime,
     pub s3_bucket_id: &'a i32,
+    pub encryption_version: i16,
+    pub size: i64,
+    pub kek_id: Option<i32>,
+    pub max_downloads: Option<i32>,
+    pub delete_token_hash: Option<&'a str>,
+    pub stored_sha256: Option<&'a str>,
+    pub client_sha256: Option<&'a str>,
+    pub password_salt: Option<&'a str>,
+    pub password_verifier: Option<&'a str>,
+    pub password_key_mix: bool,
+    pub metadata: Option<&'a str>,
+    pub metadata_version: i16,
+    pub padded: bool,
 }
 
 #[derive(Debug, Clone, Queryable)]
 #[diesel(table_name = files)]
 pub struct File {
+    #[allow(dead_code)]
     pub id: i32,
     pub file: uuid::Uuid,
     pub file_name: String,
@@ -40,4 +79,170 @@ pub struct File {
     pub available_till: chrono::NaiveDateTime,
     pub date_created: chrono::NaiveDateTime,
     pub s3_bucket_id: i32,
+    pub encryption_version: i16,
+    #[allow(dead_code)]
+    pub size: i64,
+    /// KEK that `key` is wrapped with, `None` for keys stored before keys
+    /// were wrapped.
+    pub kek_id: Option<i32>,
+    /// The file is deleted once it has been downloaded this many times.
+    /// `None` means no limit.
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
+    /// SHA-256 of the token the uploader can delete the file with, hex
+    /// encoded. Never read back, only matched against.
+    #[allow(dead_code)]
+    pub delete_token_hash: Option<String>,
+    /// SHA-256 of the object as it was written to storage, hex encoded. Files
+    /// stored before digests were recorded don't have one.
+    pub stored_sha256: Option<String>,
+    /// SHA-256 the uploader gave of what it sent, which is what the server
+    /// decrypts back to.
+    pub client_sha256: Option<String>,
+    /// When the scrub job last checked the file.
+    #[allow(dead_code)]
+    pub date_verified: Option<NaiveDateTime>,
+    /// Salt the password is derived with, see `password.rs`. Set for files
+    /// that are password protected.
+    pub password_salt: Option<String>,
+    /// SHA-256 of the proof derived from the password, hex encoded.
+    pub password_verifier: Option<String>,
+    /// The password is also mixed into the key the link holds, so the link
+    /// alone can't decrypt the file.
+    pub password_key_mix: bool,
+    /// Wrong passwords in a row. Past a few, attempts are locked out until
+    /// `password_locked_till`.
+    #[allow(dead_code)]
+    pub password_failures: i32,
+    pub password_locked_till: Option<NaiveDateTime>,
+    /// Bundle the file was put in, which it then shares its lifetime with.
+    pub bundle_id: Option<i32>,
+    /// Name and type sealed by the uploader, see `metadata.rs`. `file_name`
+    /// and `file_type` are empty then.
+    pub metadata: Option<String>,
+    pub metadata_version: i16,
+    /// The client padded the file to hide its size, see `padding.rs`.
+    pub padded: bool,
+    /// When a download of a file with a download limit was last counted.
+    #[allow(dead_code)]
+    pub date_downloaded: Option<NaiveDateTime>,
+}
+
+/// Files shared under one link. What's in it, names included, is only known
+/// to whoever has the link: `manifest` is sealed by the uploader's browser
+/// and served as it is.
+#[derive(Debug, Clone, Queryable)]
+#[diesel(table_name = bundles)]
+pub struct Bundle {
+    pub id: i32,
+    pub bundle: Uuid,
+    pub manifest: String,
+    pub available_till: NaiveDateTime,
+    #[allow(dead_code)]
+    pub date_created: NaiveDateTime,
+    /// SHA-256 of the token the uploader can delete the bundle with, like
+    /// the delete token of a file.
+    #[allow(dead_code)]
+    pub delete_token_hash: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = bundles)]
+pub struct NewBundle<'a> {
+    pub bundle: &'a Uuid,
+    pub manifest: &'a str,
+    pub available_till: NaiveDateTime,
+    pub delete_token_hash: &'a str,
+}
+
+/// A resumable upload that hasn't completed yet. Everything needed to pick up
+/// the encryption where the last request stopped is kept here, the key wrapped
+/// like the key of a file.
+#[derive(Debug, Clone, Queryable)]
+#[diesel(table_name = tus_uploads)]
+pub struct TusUpload {
+    #[allow(dead_code)]
+    pub id: i32,
+    pub file: Uuid,
+    pub s3_bucket_id: i32,
+    pub storage_upload_id: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub file_name: String,
+    pub file_type: String,
+    pub lifetime: i64,
+    pub max_downloads: Option<i32>,
+    pub key: String,
+    pub kek_id: i32,
+    pub nonce: String,
+    pub segment_index: i32,
+    /// Plaintext that doesn't fill a segment yet.
+    pub pending_plaintext: Vec<u8>,
+    /// Sealed segments that don't fill a storage part yet.
+    pub pending_ciphertext: Vec<u8>,
+    pub part_etags: Vec<String>,
+    pub stored_size: i64,
+    #[allow(dead_code)]
+    pub date_created: NaiveDateTime,
+    #[allow(dead_code)]
+    pub date_updated: NaiveDateTime,
+    /// Progress of the digests of what was stored and what was received, see
+    /// `checksum.rs`. Empty for uploads started before digests were kept.
+    pub stored_sha256_state: Vec<u8>,
+    pub received_sha256_state: Vec<u8>,
+    pub client_sha256: Option<String>,
+    pub password_salt: Option<String>,
+    pub password_verifier: Option<String>,
+    pub password_key_mix: bool,
+    pub metadata: Option<String>,
+    pub padded: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tus_uploads)]
+pub struct NewTusUpload<'a> {
+    pub file: &'a Uuid,
+    pub s3_bucket_id: i32,
+    pub storage_upload_id: &'a str,
+    pub upload_length: i64,
+    pub file_name: &'a str,
+    pub file_type: &'a str,
+    pub lifetime: i64,
+    pub max_downloads: Option<i32>,
+    pub key: &'a str,
+    pub kek_id: i32,
+    pub nonce: &'a str,
+    pub pending_ciphertext: &'a [u8],
+    pub stored_size: i64,
+    pub stored_sha256_state: &'a [u8],
+    pub received_sha256_state: &'a [u8],
+    pub client_sha256: Option<&'a str>,
+    pub password_salt: Option<&'a str>,
+    pub password_verifier: Option<&'a str>,
+    pub password_key_mix: bool,
+    pub metadata: Option<&'a str>,
+    pub padded: bool,
+}
+
+/// Progress of a resumable upload after a request, see [`TusUpload`].
+#[derive(AsChangeset)]
+#[diesel(table_name = tus_uploads)]
+pub struct TusProgress<'a> {
+    pub upload_offset: i64,
+    pub segment_index: i32,
+    pub pending_plaintext: &'a [u8],
+    pub pending_ciphertext: &'a [u8],
+    pub part_etags: &'a [String],
+    pub stored_size: i64,
+    pub date_updated: NaiveDateTime,
+    pub stored_sha256_state: &'a [u8],
+    pub received_sha256_state: &'a [u8],
+}
+
+/// When a background job last completed. It's the time the run started, so
+/// the next run is due exactly one interval later.
+#[derive(Queryable)]
+pub struct JobRun {
+    pub name: String,
+    pub last_success: NaiveDateTime,
 }
@@ -0,0 +1,189 @@
+use std::fmt;
+
+use actix_multipart::MultipartError;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+
+use crate::{database::DbError, storage::StorageError};
+
+/// Everything a request can fail with. Each variant has its own status code
+/// and a stable `code` clients can match on, the message is only meant for
+/// humans. Causes of server side failures are logged, never sent to clients.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(&'static str),
+    /// What couldn't be found, like "file".
+    NotFound(&'static str),
+    /// The file is past its lifetime, even if cleanup hasn't removed it yet.
+    Expired,
+    DownloadLimitReached,
+    PayloadTooLarge(String),
+    Conflict(&'static str),
+    Locked,
+    UnsupportedMediaType(&'static str),
+    PreconditionFailed(&'static str),
+    /// What was uploaded doesn't match the checksum the client sent with it.
+    ChecksumMismatch,
+    /// The file is password protected and no proof of the password was sent.
+    PasswordRequired,
+    WrongPassword,
+    /// Too many wrong passwords for the file, attempts are locked out for a
+    /// while.
+    TooManyAttempts,
+    StorageUnavailable(String),
+    /// The object no longer matches the digest taken when it was stored.
+    Corrupted(String),
+    /// The object is intact but doesn't open with the file's key.
+    DecryptFailed(String),
+    Database(DbError),
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    success: bool,
+    code: &'a str,
+    message: String,
+}
+
+impl AppError {
+    /// Stable identifier of the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::NotFound(_) => "not_found",
+            AppError::Expired => "expired",
+            AppError::DownloadLimitReached => "download_limit_reached",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::Conflict(_) => "conflict",
+            AppError::Locked => "locked",
+            AppError::UnsupportedMediaType(_) => "unsupported_media_type",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::ChecksumMismatch => "checksum_mismatch",
+            AppError::PasswordRequired => "password_required",
+            AppError::WrongPassword => "wrong_password",
+            AppError::TooManyAttempts => "too_many_attempts",
+            AppError::StorageUnavailable(_) => "storage_unavailable",
+            AppError::Corrupted(_) => "corrupted",
+            AppError::DecryptFailed(_) => "decrypt_failed",
+            AppError::Database(_) | AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// Logs the cause of server side failures. Done when rendering the
+    /// response, errors that can't be answered anymore have to log themselves.
+    pub fn log(&self) {
+        if let Some(cause) = self.cause() {
+            eprintln!("{}: {}", self.code(), cause);
+        }
+    }
+
+    /// What went wrong on our side, for the log.
+    fn cause(&self) -> Option<String> {
+        match self {
+            AppError::StorageUnavailable(cause)
+            | AppError::Corrupted(cause)
+            | AppError::DecryptFailed(cause)
+            | AppError::Internal(cause) => Some(cause.clone()),
+            AppError::Database(e) => Some(e.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(message) | AppError::PayloadTooLarge(message) => {
+                write!(f, "{}", message)
+            }
+            AppError::Unauthorized(message)
+            | AppError::Conflict(message)
+            | AppError::UnsupportedMediaType(message)
+            | AppError::PreconditionFailed(message) => write!(f, "{}", message),
+            AppError::NotFound(what) => write!(f, "Couldn't find {}", what),
+            AppError::Expired => write!(f, "This file has expired"),
+            AppError::DownloadLimitReached => write!(f, "This file has no downloads left"),
+            AppError::Locked => write!(f, "Upload is being written to by another request"),
+            AppError::ChecksumMismatch => write!(f, "The file doesn't match its checksum"),
+            AppError::PasswordRequired => write!(f, "This file is password protected"),
+            AppError::WrongPassword => write!(f, "Wrong password"),
+            AppError::TooManyAttempts => {
+                write!(f, "Too many wrong passwords, please try again later")
+            }
+            AppError::StorageUnavailable(_) => {
+                write!(f, "Storage is unavailable, please try again later")
+            }
+            AppError::Corrupted(_) => write!(f, "This file is damaged in storage"),
+            AppError::DecryptFailed(_) => write!(f, "Couldn't decrypt file"),
+            AppError::Database(_) | AppError::Internal(_) => {
+                write!(f, "Internal error, please try again later")
+            }
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) | AppError::ChecksumMismatch => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) | AppError::PasswordRequired => StatusCode::UNAUTHORIZED,
+            AppError::WrongPassword => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Expired | AppError::DownloadLimitReached => StatusCode::GONE,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Locked => StatusCode::LOCKED,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            AppError::StorageUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Corrupted(_)
+            | AppError::DecryptFailed(_)
+            | AppError::Database(_)
+            | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.log();
+
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            success: false,
+            code: self.code(),
+            message: self.to_string(),
+        })
+    }
+}
+
+impl From<DbError> for AppError {
+    fn from(e: DbError) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(e: diesel::result::Error) -> Self {
+        AppError::Database(Box::new(e))
+    }
+}
+
+impl From<deadpool::managed::PoolError<diesel_async::pooled_connection::PoolError>> for AppError {
+    fn from(e: deadpool::managed::PoolError<diesel_async::pooled_connection::PoolError>) -> Self {
+        AppError::Database(Box::new(e))
+    }
+}
+
+impl From<StorageError> for AppError {
+    fn from(e: StorageError) -> Self {
+        AppError::StorageUnavailable(e.to_string())
+    }
+}
+
+impl From<MultipartError> for AppError {
+    fn from(e: MultipartError) -> Self {
+        AppError::BadRequest(e.to_string())
+    }
+}
@@ -1,5 +1,9 @@
-use chrono::Utc;
+use aes_gcm::aead::OsRng;
+use base64::{engine::general_purpose, Engine};
+use chrono::{Duration, Utc};
 use diesel_async::AsyncPgConnection;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use crate::{
@@ -8,50 +12,152 @@ use crate::{
         actions::{self, add_file_record, get_file_record},
         models,
     },
-    s3::get_s3_specific_bucket,
+    error::AppError,
+    keyring, lifetime,
+    metadata::Metadata,
+    password::Password,
+    storage::{get_specific_storage, StorageError},
 };
 
+pub struct FileUpload {
+    pub unique_id: Uuid,
+    pub metadata: Metadata,
+    pub lifetime: i64,
+    pub s3_bucket_id: i32,
+    pub size: i64,
+    pub max_downloads: Option<i32>,
+    pub stored_sha256: Option<String>,
+    pub client_sha256: Option<String>,
+    pub password: Option<Password>,
+    pub padded: bool,
+}
+
+/// Stores the record of an uploaded file. Returns the token the uploader can
+/// delete the file with, only its hash is kept.
 pub async fn create_file(
     conn: &mut AsyncPgConnection,
     file: Encrypted,
-    unique_id: Uuid,
-    file_name: String,
-    file_type: String,
-    lifetime: i64,
-    s3_bucket_id: i32,
-) -> Result<(), ()> {
-    let available_till = Utc::now().timestamp() + lifetime;
-
-    return add_file_record(
+    upload: FileUpload,
+) -> Result<String, AppError> {
+    let available_till = Utc::now().timestamp() + upload.lifetime;
+    let (key, kek_id) = keyring::wrap_key(&file.key)
+        .ok_or_else(|| AppError::Internal("Couldn't wrap file key".to_string()))?;
+
+    let delete_token = new_delete_token();
+
+    add_file_record(
         conn,
-        file,
-        unique_id,
-        file_name,
-        file_type,
+        Encrypted { key, ..file },
+        kek_id,
+        &hash_delete_token(&delete_token),
+        upload,
         available_till,
-        s3_bucket_id,
     )
-    .await;
+    .await?;
+
+    Ok(delete_token)
+}
+
+/// A random token for deleting something before it expires, which only the
+/// uploader gets to see.
+pub fn new_delete_token() -> String {
+    let mut delete_token = [0u8; 32];
+    OsRng.fill_bytes(&mut delete_token);
+    general_purpose::URL_SAFE_NO_PAD.encode(delete_token)
 }
 
-pub async fn get_file(conn: &mut AsyncPgConnection, file_uuid: Uuid) -> Result<models::File, ()> {
-    match get_file_record(conn, file_uuid).await {
-        Ok(file) => Ok(file),
-        _ => Err(()),
+/// What's stored of a delete token.
+pub fn hash_delete_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The file `file_uuid`, if `delete_token` is the token it was uploaded with.
+pub async fn get_owned_file(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+    delete_token: &str,
+) -> Result<Option<models::File>, AppError> {
+    Ok(
+        actions::get_file_by_delete_token(conn, file_uuid, &hash_delete_token(delete_token))
+            .await?,
+    )
+}
+
+pub async fn get_file(
+    conn: &mut AsyncPgConnection,
+    file_uuid: Uuid,
+) -> Result<models::File, AppError> {
+    let policy = lifetime::policy();
+    let extend_by = policy
+        .extend_on_access
+        .then(|| Duration::seconds(policy.extend_by));
+
+    let file = get_file_record(conn, file_uuid, extend_by)
+        .await?
+        .ok_or(AppError::NotFound("file"))?;
+
+    // Gone as soon as it expires, not only once the cleanup job removed it
+    if file.available_till <= Utc::now().naive_utc() {
+        return Err(AppError::Expired);
     }
+
+    Ok(file)
 }
 
-pub async fn delete_file(conn: &mut AsyncPgConnection, file: &models::File) -> Result<(), ()> {
-    let bucket = match get_s3_specific_bucket(conn, file.s3_bucket_id).await {
-        Some(bucket) => bucket,
-        _ => return Err(()),
+/// Counts a download of `file` against its download limit. `None` when there
+/// are no downloads left, otherwise whether this is the last one, after which
+/// the file has to be deleted.
+pub async fn claim_download(
+    conn: &mut AsyncPgConnection,
+    file: &models::File,
+) -> Result<Option<bool>, AppError> {
+    let max_downloads = match file.max_downloads {
+        Some(max_downloads) => max_downloads,
+        None => return Ok(Some(false)),
     };
 
-    let delete_result = bucket.delete_object(format!("{}", file.file)).await;
+    Ok(actions::claim_download(conn, file.file)
+        .await?
+        .map(|count| count >= max_downloads))
+}
+
+pub async fn delete_file(
+    conn: &mut AsyncPgConnection,
+    file: &models::File,
+) -> Result<(), AppError> {
+    let storage = get_specific_storage(conn, file.s3_bucket_id)
+        .await
+        .ok_or_else(|| storage_unavailable(file.s3_bucket_id))?;
+
+    match storage.delete(&file.file.to_string()).await {
+        Ok(()) | Err(StorageError::NotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(actions::delete_file(conn, file.file).await?)
+}
+
+pub fn storage_unavailable(bucket_id: i32) -> AppError {
+    AppError::StorageUnavailable(format!("Couldn't receive storage of bucket {}", bucket_id))
+}
+
+/// Aborts the storage upload and forgets about it. The upload is kept when
+/// its storage can't abort it, so the parts stored so far aren't left behind.
+pub async fn abandon_upload(
+    conn: &mut AsyncPgConnection,
+    upload: &models::TusUpload,
+) -> Result<(), AppError> {
+    let storage = get_specific_storage(conn, upload.s3_bucket_id)
+        .await
+        .ok_or_else(|| storage_unavailable(upload.s3_bucket_id))?;
 
-    if delete_result.is_ok() {
-        let _ = actions::delete_file(conn, file.file).await;
+    match storage
+        .abort_upload(&upload.file.to_string(), &upload.storage_upload_id)
+        .await
+    {
+        Ok(()) | Err(StorageError::NotFound) => {}
+        Err(e) => return Err(e.into()),
     }
 
-    Ok(())
+    Ok(actions::delete_tus_upload(conn, upload.file).await?)
 }
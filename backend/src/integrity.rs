@@ -0,0 +1,283 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    crypt::{
+        plaintext_size, segment_count, segment_offset, StreamDecryptor, ENCRYPTED_SEGMENT_SIZE,
+    },
+    database::models,
+    error::AppError,
+    keyring, metrics,
+    storage::StorageBackend,
+};
+
+/// Objects are read back this many segments at a time, so memory stays
+/// bounded while checking large files.
+const SEGMENTS_PER_READ: u64 = 64;
+
+/// SHA-256 of a whole object, hex encoded.
+async fn object_sha256(storage: &dyn StorageBackend, key: &str) -> Result<String, AppError> {
+    // Reads stop at the size, S3 refuses a range that starts past the end
+    let size = storage.head(key).await?.size;
+    let mut hasher = Sha256::new();
+    let mut from = 0;
+
+    while from < size {
+        let to = (from + SEGMENTS_PER_READ * ENCRYPTED_SEGMENT_SIZE as u64).min(size);
+        let data = storage.get_range(key, from, to).await?;
+        hasher.update(&data);
+
+        if (data.len() as u64) < to - from {
+            break;
+        }
+        from = to;
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Works out why `file` didn't open. An object that no longer matches the
+/// digest taken when it was stored was damaged by storage, otherwise the key
+/// doesn't fit. Files stored before digests were recorded can't tell.
+pub async fn diagnose(
+    storage: &dyn StorageBackend,
+    file: &models::File,
+    cause: String,
+) -> AppError {
+    let error = match &file.stored_sha256 {
+        Some(expected) => match object_sha256(storage, &file.file.to_string()).await {
+            Ok(digest) if &digest != expected => AppError::Corrupted(format!(
+                "{}, object of file {} has digest {} instead of {}",
+                cause, file.id, digest, expected
+            )),
+            Ok(_) => AppError::DecryptFailed(cause),
+            Err(e) => return e,
+        },
+        None => AppError::DecryptFailed(cause),
+    };
+
+    metrics::record(&error);
+    error
+}
+
+/// Reads back the object of `file` and checks it against both digests: the
+/// one taken when it was stored, and the one the uploader gave of what it
+/// sent, which has to match what the object decrypts to.
+pub async fn verify(storage: &dyn StorageBackend, file: &models::File) -> Result<(), AppError> {
+    let expected = match &file.stored_sha256 {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    let key = file.file.to_string();
+    let object_size = storage.head(&key).await?.size;
+    let segments = plaintext_size(object_size).map(segment_count);
+    let decryptor = keyring::file_key(file).and_then(|key| StreamDecryptor::new(&key, &file.nonce));
+
+    let mut stored = Sha256::new();
+    let mut received = Sha256::new();
+    // Keeps reading to finish the stored digest once a segment didn't open
+    let mut open_failure = match (&decryptor, segments) {
+        (Some(_), Some(_)) => None,
+        _ => Some(format!("File {} has an invalid key or size", file.id)),
+    };
+    let mut segment = 0;
+
+    loop {
+        let from = match segment {
+            0 => 0,
+            _ => segment_offset(segment),
+        };
+        // Like in `object_sha256`, never read from past the end
+        if from >= object_size {
+            break;
+        }
+        let to = segment_offset(segment + SEGMENTS_PER_READ).min(object_size);
+        let data = storage.get_range(&key, from, to).await?;
+        stored.update(&data);
+
+        if let (None, Some(decryptor), Some(segments)) = (&open_failure, &decryptor, segments) {
+            let body = &data[(segment_offset(segment) - from).min(data.len() as u64) as usize..];
+            for (offset, encrypted) in body.chunks(ENCRYPTED_SEGMENT_SIZE).enumerate() {
+                let index = segment + offset as u64;
+                match decryptor.open_segment(index as u32, index + 1 == segments, encrypted) {
+                    Some(plaintext) => received.update(&plaintext),
+                    None => {
+                        open_failure = Some(format!("Segment {} doesn't open", index));
+                        break;
+                    }
+                }
+            }
+        }
+
+        if (data.len() as u64) < to - from {
+            break;
+        }
+        segment += SEGMENTS_PER_READ;
+    }
+
+    let stored = hex::encode(stored.finalize());
+    let error = if &stored != expected {
+        AppError::Corrupted(format!(
+            "Object of file {} has digest {} instead of {}",
+            file.id, stored, expected
+        ))
+    } else if let Some(cause) = open_failure {
+        AppError::DecryptFailed(cause)
+    } else {
+        match &file.client_sha256 {
+            Some(client_sha256) if &hex::encode(received.finalize()) != client_sha256 => {
+                AppError::Corrupted(format!(
+                    "File {} doesn't decrypt to what was uploaded",
+                    file.id
+                ))
+            }
+            _ => return Ok(()),
+        }
+    };
+
+    metrics::record(&error);
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use crate::{
+        crypt::{StreamEncryptor, SEGMENT_SIZE, STREAM_VERSION},
+        storage::{ListedObject, MemoryStorage, ObjectInfo, StorageError, UploadedPart},
+    };
+
+    use super::*;
+
+    /// Refuses ranges that start at or past the end of the object, like S3.
+    #[derive(Default)]
+    struct StrictStorage(MemoryStorage);
+
+    #[async_trait]
+    impl StorageBackend for StrictStorage {
+        async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+            self.0.put(key, data).await
+        }
+
+        async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+            self.0.get(key).await
+        }
+
+        async fn get_range(
+            &self,
+            key: &str,
+            start: u64,
+            end: u64,
+        ) -> Result<Vec<u8>, StorageError> {
+            if start >= self.0.head(key).await?.size {
+                return Err(StorageError::Backend("Range not satisfiable".to_string()));
+            }
+            self.0.get_range(key, start, end).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StorageError> {
+            self.0.delete(key).await
+        }
+
+        async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+            self.0.head(key).await
+        }
+
+        async fn list(&self) -> Result<Vec<ListedObject>, StorageError> {
+            self.0.list().await
+        }
+
+        async fn start_upload(&self, _: &str) -> Result<String, StorageError> {
+            unimplemented!()
+        }
+
+        async fn upload_part(
+            &self,
+            _: &str,
+            _: &str,
+            _: u32,
+            _: Vec<u8>,
+        ) -> Result<UploadedPart, StorageError> {
+            unimplemented!()
+        }
+
+        async fn complete_upload(
+            &self,
+            _: &str,
+            _: &str,
+            _: Vec<UploadedPart>,
+        ) -> Result<(), StorageError> {
+            unimplemented!()
+        }
+
+        async fn abort_upload(&self, _: &str, _: &str) -> Result<(), StorageError> {
+            unimplemented!()
+        }
+    }
+
+    fn file(key: String, nonce: String, stored: &[u8], received: &[u8]) -> models::File {
+        let now = Utc::now().naive_utc();
+        models::File {
+            id: 1,
+            file: Uuid::new_v4(),
+            file_name: String::new(),
+            file_type: String::new(),
+            key,
+            nonce,
+            available_till: now,
+            date_created: now,
+            s3_bucket_id: 1,
+            encryption_version: STREAM_VERSION,
+            size: stored.len() as i64,
+            kek_id: None,
+            max_downloads: None,
+            download_count: 0,
+            delete_token_hash: None,
+            stored_sha256: Some(hex::encode(Sha256::digest(stored))),
+            client_sha256: Some(hex::encode(Sha256::digest(received))),
+            date_verified: None,
+            password_salt: None,
+            password_verifier: None,
+            password_key_mix: false,
+            password_failures: 0,
+            password_locked_till: None,
+            bundle_id: None,
+            metadata: None,
+            metadata_version: 0,
+            padded: false,
+            date_downloaded: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn hashes_object_exactly_one_read_long() {
+        let storage = StrictStorage::default();
+        let data = vec![7; SEGMENTS_PER_READ as usize * ENCRYPTED_SEGMENT_SIZE];
+        storage.put("object", data.clone()).await.unwrap();
+
+        assert_eq!(
+            object_sha256(&storage, "object").await.unwrap(),
+            hex::encode(Sha256::digest(&data))
+        );
+    }
+
+    #[actix_web::test]
+    async fn verifies_object_exactly_one_read_long() {
+        let storage = StrictStorage::default();
+        let plaintext = vec![7; SEGMENTS_PER_READ as usize * SEGMENT_SIZE];
+
+        let mut encryptor = StreamEncryptor::new();
+        let mut sealed = encryptor.update(&plaintext);
+        let encrypted = encryptor.finalize();
+        sealed.extend_from_slice(&encrypted.result);
+        assert_eq!(sealed.len() as u64, segment_offset(SEGMENTS_PER_READ));
+
+        let file = file(encrypted.key, encrypted.nonce, &sealed, &plaintext);
+        storage.put(&file.file.to_string(), sealed).await.unwrap();
+
+        assert!(verify(&storage, &file).await.is_ok());
+    }
+}
@@ -1,33 +1,461 @@
-use std::{sync::Arc, time::Duration};
+use std::time::Duration;
 
+use chrono::Utc;
 use diesel_async::AsyncPgConnection;
-use tokio::time::interval;
+use tokio::{
+    task::JoinHandle,
+    time::{interval, sleep},
+};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    database::{actions, DbError},
-    files::delete_file,
-    DbPool,
+    config,
+    database::{actions, models, DbError},
+    error::AppError,
+    files::{abandon_upload, delete_file},
+    integrity, keyring, metrics, reconcile, storage, DbConn, DbPool,
 };
 
-async fn delete_expired_files(conn: &mut AsyncPgConnection) -> Result<(), DbError> {
-    let files = actions::get_expired_files(conn).await?;
-    for file in files {
-        let _ = delete_file(conn, &file).await;
+/// Advisory lock of a job is this plus the job's number.
+const LOCK_BASE: i64 = 0x4344_0000;
+/// Jobs check at least this often whether they're due, so another instance
+/// takes over soon after the one running them goes away.
+const CHECK_INTERVAL: u64 = 60;
+/// Storage failures are retried after 1, 2, 4 and 8 seconds.
+const STORAGE_RETRIES: u32 = 4;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Files that used up their downloads are only cleaned up this long after the
+/// last one was counted, it may still be streaming until then.
+const LAST_DOWNLOAD_GRACE: i64 = config::DAY;
+
+/// The background jobs. Every instance runs them, but the advisory lock makes
+/// sure only one works on a job at a time, and the last successful run stored
+/// in the database keeps them from running more often than their interval.
+#[derive(Clone, Copy)]
+pub enum Job {
+    Cleanup,
+    Rewrap,
+    Reconcile,
+    Scrub,
+}
+
+impl Job {
+    pub const ALL: [Job; 4] = [Job::Cleanup, Job::Rewrap, Job::Reconcile, Job::Scrub];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::Cleanup => "cleanup",
+            Job::Rewrap => "rewrap",
+            Job::Reconcile => "reconcile",
+            Job::Scrub => "scrub",
+        }
     }
 
-    Ok(())
+    pub fn interval(self) -> i64 {
+        let jobs = &config::config().jobs;
+        match self {
+            Job::Cleanup => jobs.cleanup_interval,
+            Job::Rewrap => jobs.rewrap_interval,
+            Job::Reconcile => jobs.reconcile_interval,
+            Job::Scrub => jobs.scrub_interval,
+        }
+    }
+
+    async fn run(
+        self,
+        conn: &mut AsyncPgConnection,
+        shutdown: &CancellationToken,
+    ) -> Result<(), DbError> {
+        match self {
+            Job::Cleanup => {
+                delete_expired_files(conn, shutdown).await?;
+                delete_expired_bundles(conn).await?;
+                delete_abandoned_uploads(conn, shutdown).await
+            }
+            Job::Rewrap => rewrap_file_keys(conn, shutdown).await.map(|_| ()),
+            Job::Reconcile => reconcile_buckets(conn, shutdown).await,
+            Job::Scrub => scrub_files(conn, shutdown).await,
+        }
+    }
+}
+
+/// Starts every job. Once `shutdown` is cancelled they stop after the batch
+/// they're working on.
+pub fn start(pool: DbPool, shutdown: CancellationToken) -> Vec<JoinHandle<()>> {
+    Job::ALL
+        .into_iter()
+        .map(|job| tokio::spawn(run_job(job, pool.clone(), shutdown.clone())))
+        .collect()
 }
 
-pub async fn cleanup_job(conn_pool: Arc<DbPool>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut interval = interval(Duration::from_secs(3600)); // 1 hour
+async fn run_job(job: Job, pool: DbPool, shutdown: CancellationToken) {
+    let seconds = (job.interval() as u64).min(CHECK_INTERVAL);
+    let mut interval = interval(Duration::from_secs(seconds));
+
+    while shutdown
+        .run_until_cancelled(interval.tick())
+        .await
+        .is_some()
+    {
+        let conn = match pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!(
+                    "Couldn't get a connection for the {} job: {}",
+                    job.name(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        if let Err(e) = run_if_due(job, conn, &shutdown).await {
+            eprintln!("Failed to run the {} job: {}", job.name(), e);
+        }
+    }
+}
+
+/// A job's session level advisory lock, along with the connection holding it.
+/// When it's dropped without being released, because the job panicked or its
+/// task was dropped mid-run, the connection is closed instead of going back
+/// to the pool still holding the lock.
+struct JobLock {
+    conn: Option<DbConn>,
+    key: i64,
+}
+
+impl JobLock {
+    async fn acquire(mut conn: DbConn, key: i64) -> Result<Option<Self>, DbError> {
+        match actions::try_lock(&mut conn, key).await? {
+            true => Ok(Some(JobLock {
+                conn: Some(conn),
+                key,
+            })),
+            false => Ok(None),
+        }
+    }
+
+    fn conn(&mut self) -> &mut AsyncPgConnection {
+        self.conn.as_mut().expect("Held until released")
+    }
+
+    async fn release(mut self) -> Result<(), DbError> {
+        let mut conn = self.conn.take().expect("Held until released");
+
+        let result = actions::unlock(&mut conn, self.key).await;
+        if result.is_err() {
+            drop(DbConn::take(conn));
+        }
+
+        result.map(|_| ())
+    }
+}
+
+impl Drop for JobLock {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(DbConn::take(conn));
+        }
+    }
+}
+
+async fn run_if_due(job: Job, conn: DbConn, shutdown: &CancellationToken) -> Result<(), DbError> {
+    // Another instance is running it
+    let mut lock = match JobLock::acquire(conn, LOCK_BASE + job as i64).await? {
+        Some(lock) => lock,
+        None => return Ok(()),
+    };
+
+    let result = run_locked(job, lock.conn(), shutdown).await;
+    lock.release().await?;
+    result
+}
+
+async fn run_locked(
+    job: Job,
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<(), DbError> {
+    let started = Utc::now().naive_utc();
+    let last_success = actions::get_job_last_success(conn, job.name()).await?;
+    if let Some(last_success) = last_success {
+        if started < last_success + chrono::Duration::seconds(job.interval()) {
+            return Ok(());
+        }
+    }
+
+    job.run(conn, shutdown).await?;
+
+    // Cut short, so it's still due
+    if shutdown.is_cancelled() {
+        return Ok(());
+    }
+
+    actions::set_job_last_success(conn, job.name(), started).await
+}
+
+/// Deletes a file, retrying with exponential backoff while its storage is
+/// unavailable.
+async fn delete_with_retries(
+    conn: &mut AsyncPgConnection,
+    file: &models::File,
+    shutdown: &CancellationToken,
+) -> Result<(), AppError> {
+    let mut delay = RETRY_DELAY;
+    let mut retries = 0;
 
     loop {
-        interval.tick().await;
+        match delete_file(conn, file).await {
+            Err(AppError::StorageUnavailable(e)) if retries < STORAGE_RETRIES => {
+                if shutdown.run_until_cancelled(sleep(delay)).await.is_none() {
+                    return Err(AppError::StorageUnavailable(e));
+                }
+                retries += 1;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Deletes expired files a batch at a time. Files in a bucket whose storage
+/// stays unavailable are left for the next run.
+async fn delete_expired_files(
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<(), DbError> {
+    let batch_size = config::config().jobs.cleanup_batch_size;
+    let used_up_before = Utc::now().naive_utc() - chrono::Duration::seconds(LAST_DOWNLOAD_GRACE);
+    let mut failed_buckets = Vec::new();
+    let mut deleted = 0;
+
+    while !shutdown.is_cancelled() {
+        let files =
+            actions::get_expired_files(conn, &failed_buckets, used_up_before, batch_size).await?;
+        let mut progress = false;
+
+        for file in &files {
+            if failed_buckets.contains(&file.s3_bucket_id) {
+                continue;
+            }
+
+            match delete_with_retries(conn, file, shutdown).await {
+                Ok(()) => {
+                    progress = true;
+                    deleted += 1;
+                }
+                Err(AppError::StorageUnavailable(e)) => {
+                    eprintln!(
+                        "Skipping expired files in bucket {}: {}",
+                        file.s3_bucket_id, e
+                    );
+                    failed_buckets.push(file.s3_bucket_id);
+                }
+                Err(e) => eprintln!("Failed to delete expired file {}: {:?}", file.file, e),
+            }
+        }
+
+        if (files.len() as i64) < batch_size || !progress {
+            break;
+        }
+    }
+
+    if deleted > 0 {
+        println!("Deleted {} expired files", deleted);
+    }
+
+    Ok(())
+}
+
+/// Deletes expired bundles once their files are gone.
+async fn delete_expired_bundles(conn: &mut AsyncPgConnection) -> Result<(), DbError> {
+    let deleted = actions::delete_expired_bundles(conn).await?;
+    if deleted > 0 {
+        println!("Deleted {} expired bundles", deleted);
+    }
+
+    Ok(())
+}
 
-        let mut conn = conn_pool.get().await.expect("Failed to get connection");
+/// Re-wraps keys that aren't wrapped with the current KEK, a batch at a time.
+async fn rewrap_file_keys(
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<usize, DbError> {
+    let current = match keyring::current_kek() {
+        Some(current) => current,
+        None => return Ok(0),
+    };
 
-        if let Err(e) = delete_expired_files(&mut *conn).await {
-            eprintln!("Failed to delete expired files: {}", e);
+    let batch_size = config::config().jobs.rewrap_batch_size;
+    let retired = keyring::retired_keks();
+    let mut rewrapped = 0;
+
+    while !shutdown.is_cancelled() {
+        let files = actions::get_files_to_rewrap(conn, &retired, batch_size).await?;
+        let mut progress = false;
+
+        for file in &files {
+            let (key, kek_id) = match keyring::rewrap_key(file) {
+                Some(wrapped) => wrapped,
+                None => {
+                    eprintln!("Couldn't re-wrap the key of file {}", file.id);
+                    continue;
+                }
+            };
+
+            // Zero rows means the file was deleted or re-wrapped meanwhile
+            actions::set_file_key(conn, file.id, &file.key, &key, kek_id).await?;
+            progress = true;
+            rewrapped += 1;
         }
+
+        if (files.len() as i64) < batch_size || !progress {
+            break;
+        }
+    }
+
+    if rewrapped > 0 {
+        println!("Re-wrapped {} file keys with KEK {}", rewrapped, current);
     }
+
+    Ok(rewrapped)
+}
+
+/// Gives up on resumable uploads that stopped receiving data, a batch at a
+/// time. Uploads in a bucket whose storage can't abort them are left for the
+/// next run.
+async fn delete_abandoned_uploads(
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<(), DbError> {
+    let jobs = &config::config().jobs;
+    let before = Utc::now().naive_utc() - chrono::Duration::seconds(jobs.abandoned_upload_after);
+    let mut failed_buckets = Vec::new();
+
+    while !shutdown.is_cancelled() {
+        let uploads = actions::get_abandoned_tus_uploads(
+            conn,
+            &failed_buckets,
+            before,
+            jobs.cleanup_batch_size,
+        )
+        .await?;
+        let mut progress = false;
+
+        for upload in &uploads {
+            if failed_buckets.contains(&upload.s3_bucket_id) {
+                continue;
+            }
+
+            match abandon_upload(conn, upload).await {
+                Ok(()) => progress = true,
+                Err(AppError::StorageUnavailable(e)) => {
+                    eprintln!(
+                        "Skipping abandoned uploads in bucket {}: {}",
+                        upload.s3_bucket_id, e
+                    );
+                    failed_buckets.push(upload.s3_bucket_id);
+                }
+                Err(e) => eprintln!("Failed to abandon upload {}: {:?}", upload.file, e),
+            }
+        }
+
+        if (uploads.len() as i64) < jobs.cleanup_batch_size || !progress {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares every bucket with the files table, see `reconcile.rs`.
+async fn reconcile_buckets(
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<(), DbError> {
+    let jobs = &config::config().jobs;
+    let action = match jobs.reconcile_dry_run {
+        true => "Found",
+        false => "Deleted",
+    };
+
+    for bucket in actions::get_s3_buckets(conn).await? {
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        let id = bucket.id;
+        match reconcile::reconcile_bucket(
+            conn,
+            bucket,
+            jobs.reconcile_grace,
+            jobs.reconcile_dry_run,
+        )
+        .await
+        {
+            Ok(report) if !report.is_empty() => println!(
+                "{} {} orphaned objects and {} files without object in bucket {}",
+                action,
+                report.orphan_objects.len(),
+                report.missing_objects.len(),
+                id
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to reconcile bucket {}: {}", id, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads back a batch of the files checked longest ago and verifies them
+/// against their digests. Damaged files are only reported, someone has to
+/// decide whether they can be restored.
+async fn scrub_files(
+    conn: &mut AsyncPgConnection,
+    shutdown: &CancellationToken,
+) -> Result<(), DbError> {
+    let jobs = &config::config().jobs;
+    let now = Utc::now().naive_utc();
+    let before = now - chrono::Duration::seconds(jobs.scrub_every);
+    let mut damaged = 0;
+
+    for file in actions::get_files_to_scrub(conn, before, jobs.scrub_batch_size).await? {
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        let storage = match storage::get_specific_storage(conn, file.s3_bucket_id).await {
+            Some(storage) => storage,
+            None => continue,
+        };
+
+        match integrity::verify(storage.as_ref(), &file).await {
+            Ok(()) => {}
+            // Checked again next run
+            Err(AppError::StorageUnavailable(e)) => {
+                eprintln!("Couldn't scrub file {}: {}", file.file, e);
+                continue;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Scrubbing file {} failed with {}: {:?}",
+                    file.file,
+                    e.code(),
+                    e
+                );
+                damaged += 1;
+            }
+        }
+
+        metrics::record_scrubbed();
+        actions::set_file_verified(conn, file.id, now).await?;
+    }
+
+    if damaged > 0 {
+        println!("Found {} damaged files while scrubbing", damaged);
+    }
+
+    Ok(())
 }
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use aes_kw::KekAes256;
+use base64::{engine::general_purpose, Engine};
+use lazy_static::lazy_static;
+
+use crate::database::models;
+
+lazy_static! {
+    static ref KEYRING: Result<Keyring, String> = Keyring::from_env();
+}
+
+/// Key-encryption keys that per-file keys are wrapped with (AES key wrap,
+/// RFC 3394) before they're stored, so the database alone can't remove the
+/// server-side encryption layer.
+///
+/// Every configured KEK can unwrap, only the current one wraps. Files wrapped
+/// with any other KEK are re-wrapped by the rewrap job.
+struct Keyring {
+    keks: BTreeMap<i32, KekAes256>,
+    current: i32,
+}
+
+impl Keyring {
+    /// `FILE_KEKS` (or the file named in `FILE_KEKS_FILE`) lists the KEKs as
+    /// `id:base64 key`, separated by commas or newlines. `FILE_KEK_CURRENT`
+    /// picks the one new keys are wrapped with and defaults to the highest id.
+    fn from_env() -> Result<Self, String> {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let list = match (env("FILE_KEKS"), env("FILE_KEKS_FILE")) {
+            (Some(list), _) => list,
+            (_, Some(path)) => std::fs::read_to_string(&path)
+                .map_err(|e| format!("Couldn't read FILE_KEKS_FILE {}: {}", path, e))?,
+            _ => return Err("FILE_KEKS or FILE_KEKS_FILE should be set".to_string()),
+        };
+
+        let mut keks = BTreeMap::new();
+        for entry in list
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| "FILE_KEKS entries should look like id:key".to_string())?;
+            let id: i32 = id
+                .trim()
+                .parse()
+                .map_err(|_| format!("FILE_KEKS id {} should be a number", id))?;
+            let key = general_purpose::STANDARD
+                .decode(key.trim())
+                .map_err(|_| format!("FILE_KEKS key {} should be base64", id))?;
+            let kek = KekAes256::try_from(key.as_slice())
+                .map_err(|_| format!("FILE_KEKS key {} should be 32 bytes", id))?;
+
+            if keks.insert(id, kek).is_some() {
+                return Err(format!("FILE_KEKS lists key {} twice", id));
+            }
+        }
+
+        let current = match env("FILE_KEK_CURRENT") {
+            Some(current) => current
+                .parse()
+                .map_err(|_| "FILE_KEK_CURRENT should be a number".to_string())?,
+            None => *keks
+                .keys()
+                .next_back()
+                .ok_or_else(|| "FILE_KEKS should list at least one key".to_string())?,
+        };
+
+        if !keks.contains_key(&current) {
+            return Err(format!("FILE_KEK_CURRENT {} isn't in FILE_KEKS", current));
+        }
+
+        Ok(Keyring { keks, current })
+    }
+
+    /// Wraps a base64 encoded file key with the current KEK, returning the
+    /// base64 encoded wrapped key and the id of the KEK.
+    fn wrap(&self, key: &str) -> Option<(String, i32)> {
+        let key = general_purpose::STANDARD.decode(key).ok()?;
+        let wrapped = self.keks[&self.current].wrap_vec(&key).ok()?;
+
+        Some((general_purpose::STANDARD.encode(wrapped), self.current))
+    }
+
+    fn unwrap(&self, wrapped: &str, kek_id: i32) -> Option<String> {
+        let wrapped = general_purpose::STANDARD.decode(wrapped).ok()?;
+        let key = self.keks.get(&kek_id)?.unwrap_vec(&wrapped).ok()?;
+
+        Some(general_purpose::STANDARD.encode(key))
+    }
+}
+
+/// Makes sure the KEKs are configured, so a broken setup fails at startup
+/// instead of on the first upload.
+pub fn check_keyring() -> Result<(), String> {
+    KEYRING.as_ref().map(|_| ()).map_err(|e| e.clone())
+}
+
+fn keyring() -> Option<&'static Keyring> {
+    KEYRING.as_ref().ok()
+}
+
+pub fn wrap_key(key: &str) -> Option<(String, i32)> {
+    keyring()?.wrap(key)
+}
+
+pub fn unwrap_key(wrapped: &str, kek_id: i32) -> Option<String> {
+    keyring()?.unwrap(wrapped, kek_id)
+}
+
+/// The plain base64 encoded key of `file`. Files stored before keys were
+/// wrapped have no KEK id and hold the key as it is.
+pub fn file_key(file: &models::File) -> Option<String> {
+    match file.kek_id {
+        Some(kek_id) => unwrap_key(&file.key, kek_id),
+        None => Some(file.key.clone()),
+    }
+}
+
+/// The id of the KEK new keys are wrapped with.
+pub fn current_kek() -> Option<i32> {
+    keyring().map(|keyring| keyring.current)
+}
+
+/// Ids of the configured KEKs other than the current one, whose files should
+/// be re-wrapped.
+pub fn retired_keks() -> Vec<i32> {
+    keyring()
+        .map(|keyring| {
+            keyring
+                .keks
+                .keys()
+                .copied()
+                .filter(|id| *id != keyring.current)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Re-wraps the key of `file` with the current KEK.
+pub fn rewrap_key(file: &models::File) -> Option<(String, i32)> {
+    wrap_key(&file_key(file)?)
+}
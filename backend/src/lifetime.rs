@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, duration, durations, parse_duration, DAY, HOUR, MINUTE, WEEK};
+
+/// How long files are kept, and whether downloading them keeps them around
+/// for longer. Durations are in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Lifetimes offered on the upload page. Any other lifetime between `min`
+    /// and `max` is accepted as well.
+    #[serde(deserialize_with = "durations")]
+    pub options: Vec<i64>,
+    #[serde(deserialize_with = "duration")]
+    pub min: i64,
+    #[serde(deserialize_with = "duration")]
+    pub max: i64,
+    /// Lifetime of uploads that don't ask for one.
+    #[serde(deserialize_with = "duration")]
+    pub default: i64,
+    pub extend_on_access: bool,
+    /// Accessing a file keeps it available for at least this long.
+    #[serde(deserialize_with = "duration")]
+    pub extend_by: i64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            options: vec![DAY, 7 * DAY, 28 * DAY],
+            min: HOUR,
+            max: 28 * DAY,
+            default: 28 * DAY,
+            extend_on_access: true,
+            extend_by: DAY,
+        }
+    }
+}
+
+impl Policy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min > self.max {
+            return Err("The minimum lifetime is longer than the maximum".to_string());
+        }
+
+        if !self.allows(self.default) {
+            return Err("The default lifetime is outside the minimum and maximum".to_string());
+        }
+
+        if let Some(option) = self.options.iter().find(|option| !self.allows(**option)) {
+            return Err(format!(
+                "Lifetime option {} is outside the minimum and maximum",
+                format_duration(*option)
+            ));
+        }
+
+        if self.extend_on_access && self.extend_by > self.max {
+            return Err("Access extends files beyond the maximum lifetime".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn allows(&self, lifetime: i64) -> bool {
+        (self.min..=self.max).contains(&lifetime)
+    }
+
+    /// Seconds to keep an upload for, from the lifetime it asked for.
+    pub fn lifetime(&self, requested: Option<&str>) -> Result<i64, String> {
+        let lifetime = match requested.filter(|requested| !requested.is_empty()) {
+            Some(requested) => parse_duration(requested)
+                .ok_or_else(|| format!("Lifetime {} isn't a duration", requested))?,
+            None => return Ok(self.default),
+        };
+
+        match self.allows(lifetime) {
+            true => Ok(lifetime),
+            false => Err(format!(
+                "Lifetime should be between {} and {}",
+                describe_duration(self.min),
+                describe_duration(self.max)
+            )),
+        }
+    }
+}
+
+pub fn policy() -> &'static Policy {
+    &config::config().lifetime
+}
+
+/// The shortest form [`parse_duration`] reads back as `seconds`.
+pub fn format_duration(seconds: i64) -> String {
+    match largest_unit(seconds) {
+        (WEEK, count) => format!("{}w", count),
+        (DAY, count) => format!("{}d", count),
+        (HOUR, count) => format!("{}h", count),
+        (MINUTE, count) => format!("{}m", count),
+        (_, count) => format!("{}", count),
+    }
+}
+
+/// `seconds` for humans, like "4 weeks".
+pub fn describe_duration(seconds: i64) -> String {
+    let (unit, count) = largest_unit(seconds);
+    let name = match unit {
+        WEEK => "week",
+        DAY => "day",
+        HOUR => "hour",
+        MINUTE => "minute",
+        _ => "second",
+    };
+
+    match count {
+        1 => format!("1 {}", name),
+        count => format!("{} {}s", count, name),
+    }
+}
+
+fn largest_unit(seconds: i64) -> (i64, i64) {
+    [WEEK, DAY, HOUR, MINUTE]
+        .into_iter()
+        .find(|unit| seconds != 0 && seconds % unit == 0)
+        .map(|unit| (unit, seconds / unit))
+        .unwrap_or((1, seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_requested_or_default_lifetime() {
+        let policy = Policy::default();
+
+        assert_eq!(policy.lifetime(None), Ok(28 * DAY));
+        assert_eq!(policy.lifetime(Some("")), Ok(28 * DAY));
+        assert_eq!(policy.lifetime(Some("7d")), Ok(7 * DAY));
+        assert_eq!(policy.lifetime(Some("3h")), Ok(3 * HOUR));
+        assert_eq!(policy.lifetime(Some("3600")), Ok(HOUR));
+
+        assert!(policy.lifetime(Some("59m")).is_err());
+        assert!(policy.lifetime(Some("5w")).is_err());
+        assert!(policy.lifetime(Some("soon")).is_err());
+    }
+
+    #[test]
+    fn validates_policy() {
+        assert!(Policy::default().validate().is_ok());
+
+        let invalid = [
+            Policy {
+                min: 2 * DAY,
+                max: DAY,
+                ..Policy::default()
+            },
+            Policy {
+                default: 5 * WEEK,
+                ..Policy::default()
+            },
+            Policy {
+                options: vec![DAY, MINUTE],
+                ..Policy::default()
+            },
+            Policy {
+                extend_by: 5 * WEEK,
+                ..Policy::default()
+            },
+        ];
+        for policy in invalid {
+            assert!(policy.validate().is_err(), "{:?}", policy);
+        }
+
+        let policy = Policy {
+            extend_on_access: false,
+            extend_by: 5 * WEEK,
+            ..Policy::default()
+        };
+        assert!(policy.validate().is_ok());
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(2 * WEEK), "2w");
+        assert_eq!(format_duration(3 * DAY), "3d");
+        assert_eq!(format_duration(90 * MINUTE), "90m");
+        assert_eq!(format_duration(61), "61");
+        assert_eq!(format_duration(0), "0");
+
+        assert_eq!(describe_duration(4 * WEEK), "4 weeks");
+        assert_eq!(describe_duration(HOUR), "1 hour");
+        assert_eq!(describe_duration(1), "1 second");
+
+        for seconds in [30, 5 * MINUTE, 25 * HOUR, 8 * DAY, 3 * WEEK] {
+            assert_eq!(parse_duration(&format_duration(seconds)), Some(seconds));
+        }
+    }
+}
@@ -1,58 +1,148 @@
-use std::sync::Arc;
+use std::time::Duration;
 
 use actix_files::Files;
 use actix_web::{web, App, HttpServer};
+use admin::{Cli, Command};
+use clap::Parser;
 use deadpool::managed::Pool;
 use diesel_async::{pooled_connection::AsyncDieselConnectionManager, AsyncPgConnection};
-use jobs::cleanup_job;
-use lazy_static::lazy_static;
-use routes::{download_file::download_file, file_html::file_html, upload::upload};
+use error::AppError;
+use futures_util::future::join_all;
+use routes::{
+    bundle_html::bundle_html,
+    create_bundle::create_bundle,
+    delete_bundle::delete_bundle,
+    delete_file::delete_file,
+    download_file::{download_bundle_file, download_file},
+    file_html::file_html,
+    file_info::file_info,
+    metrics::metrics as metrics_route,
+    policy::policy,
+    tus::{tus_create, tus_options, tus_patch, tus_status, tus_terminate},
+    upload::upload,
+};
 use tera::Tera;
+use tokio_util::sync::CancellationToken;
 
+mod admin;
+mod bundles;
+mod checksum;
+mod config;
 mod crypt;
 mod database;
+mod error;
 mod files;
+mod integrity;
 mod jobs;
+mod keyring;
+mod lifetime;
+mod metadata;
+mod metrics;
+mod padding;
+mod password;
+mod placement;
+mod reconcile;
 mod routes;
-mod s3;
 mod schema;
+mod secrets;
+mod storage;
 
 type DbPool = deadpool::managed::Pool<AsyncDieselConnectionManager<AsyncPgConnection>>;
+type DbConn = deadpool::managed::Object<AsyncDieselConnectionManager<AsyncPgConnection>>;
 
-lazy_static! {
-    static ref TEMPLATES: Tera = Tera::new("./../frontend/templates/**/*.html").unwrap();
-}
+/// How long jobs get to stop after the server has, same as actix gives
+/// requests that are still running.
+const JOBS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
 
-    let conn_spec = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
-    let config = AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(conn_spec);
-    let pool: DbPool = Pool::builder(config)
-        .build()
-        .expect("Failed creating database pool");
+    // Parsed first, so `--help` works without a valid config
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
 
-    let cleanup_pool = pool.clone();
-    tokio::spawn(async move {
-        let _ = cleanup_job(Arc::new(cleanup_pool)).await;
-    });
+    // Printed as is, config errors can span several lines
+    if let Err(e) = config::check_config() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let config = config::config();
+
+    let manager =
+        AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(&config.database.url);
+    let mut builder = Pool::builder(manager);
+    if let Some(pool_size) = config.database.pool_size {
+        builder = builder.max_size(pool_size);
+    }
+    let pool: DbPool = builder.build().expect("Failed creating database pool");
+
+    secrets::check_master_key().map_err(std::io::Error::other)?;
+
+    let result = match command {
+        Command::Serve => return serve(pool).await,
+        Command::Bucket(command) => admin::buckets::run(&pool, command).await,
+        Command::Secrets(command) => admin::secrets::run(&pool, command).await,
+        Command::Migrate(command) => admin::migrations::run(&config.database.url, command).await,
+        Command::Jobs => admin::jobs::run(&pool).await,
+    };
+
+    result.map_err(std::io::Error::other)
+}
+
+async fn serve(pool: DbPool) -> std::io::Result<()> {
+    keyring::check_keyring().map_err(std::io::Error::other)?;
 
-    HttpServer::new(move || {
+    let config = config::config();
+    config.validate_serve().map_err(std::io::Error::other)?;
+    database::migrations::check_schema(&config.database.url, config.database.migrate_on_startup)
+        .await
+        .map_err(std::io::Error::other)?;
+    let templates = Tera::new(&config.paths.templates)
+        .map_err(|e| std::io::Error::other(format!("Couldn't load templates: {}", e)))?;
+
+    let shutdown = CancellationToken::new();
+    let jobs = jobs::start(pool.clone(), shutdown.clone());
+
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(TEMPLATES.clone()))
+            .app_data(web::Data::new(templates.clone()))
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                AppError::BadRequest(format!("Invalid request body: {}", e)).into()
+            }))
             .service(upload)
             .service(download_file)
+            .service(file_info)
+            .service(delete_file)
+            .service(create_bundle)
+            .service(download_bundle_file)
+            .service(delete_bundle)
+            .service(tus_options)
+            .service(tus_create)
+            .service(tus_status)
+            .service(tus_patch)
+            .service(tus_terminate)
+            .service(policy)
+            .service(metrics_route)
             .service(file_html)
+            .service(bundle_html)
             .service(
-                Files::new("/", "./../frontend")
+                Files::new("/", &config.paths.static_dir)
                     .index_file("index.html")
                     .use_last_modified(true)
                     .use_etag(true),
             )
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+    });
+
+    for address in &config.server.listen {
+        server = server.bind(address)?;
+    }
+
+    let result = server.run().await;
+
+    // Let the jobs finish the batch they're working on
+    shutdown.cancel();
+    let _ = tokio::time::timeout(JOBS_SHUTDOWN_TIMEOUT, join_all(jobs)).await;
+
+    result
 }
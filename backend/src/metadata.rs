@@ -0,0 +1,93 @@
+use base64::{engine::general_purpose, Engine};
+
+use crate::{database::models, error::AppError};
+
+/// Files whose name and type are stored as the uploader sent them.
+pub const LEGACY_VERSION: i16 = 0;
+/// Files whose name and type are sealed by the uploader, see [`Metadata`].
+pub const SEALED_VERSION: i16 = 1;
+/// Longest sealed metadata an upload can send, base64url encoded.
+pub const MAX_SEALED_SIZE: usize = 1024;
+/// IV and GCM tag around the sealed JSON.
+const MIN_SEALED_BYTES: usize = 12 + 16;
+
+/// The name and type of an upload.
+///
+/// Clients seal them with a key derived from the file's key, so the server
+/// only ever stores and serves an opaque blob: base64url of a random 12 byte
+/// IV followed by the AES-256-GCM encrypted JSON `{"name", "type"}`. Older
+/// clients send them in plain, which is still accepted.
+pub enum Metadata {
+    Plain {
+        file_name: String,
+        file_type: String,
+    },
+    Sealed(String),
+}
+
+impl Metadata {
+    /// What's stored of it: the plain name and type, the sealed blob and the
+    /// version telling them apart.
+    pub fn columns(&self) -> (&str, &str, Option<&str>, i16) {
+        match self {
+            Metadata::Plain {
+                file_name,
+                file_type,
+            } => (file_name, file_type, None, LEGACY_VERSION),
+            Metadata::Sealed(sealed) => ("", "", Some(sealed), SEALED_VERSION),
+        }
+    }
+
+    /// The metadata of a file as it was stored.
+    pub fn of(file: &models::File) -> Metadata {
+        match (&file.metadata, file.metadata_version) {
+            (Some(sealed), SEALED_VERSION) => Metadata::Sealed(sealed.clone()),
+            _ => Metadata::Plain {
+                file_name: file.file_name.clone(),
+                file_type: file.file_type.clone(),
+            },
+        }
+    }
+}
+
+/// Reads the metadata fields of an upload, either `metadata` or both
+/// `file_name` and `file_type`.
+pub fn parse(
+    file_name: Option<String>,
+    file_type: Option<String>,
+    sealed: Option<String>,
+) -> Result<Metadata, AppError> {
+    let sealed = match sealed.filter(|sealed| !sealed.is_empty()) {
+        Some(sealed) => sealed,
+        None => {
+            return match (file_name, file_type) {
+                (Some(file_name), Some(file_type)) => Ok(Metadata::Plain {
+                    file_name,
+                    file_type,
+                }),
+                _ => Err(AppError::BadRequest("Missing metadata".to_string())),
+            }
+        }
+    };
+
+    // A sealed name next to a plain one would give away what sealing hides
+    if file_name.is_some_and(|name| !name.is_empty())
+        || file_type.is_some_and(|file_type| !file_type.is_empty())
+    {
+        return Err(AppError::BadRequest(
+            "Send either metadata or file_name and file_type".to_string(),
+        ));
+    }
+
+    if sealed.len() > MAX_SEALED_SIZE {
+        return Err(AppError::BadRequest(format!(
+            "Metadata is over {} bytes",
+            MAX_SEALED_SIZE
+        )));
+    }
+
+    match general_purpose::URL_SAFE_NO_PAD.decode(&sealed) {
+        Ok(bytes) if bytes.len() > MIN_SEALED_BYTES => Ok(Metadata::Sealed(sealed)),
+        _ => Err(AppError::BadRequest("Invalid metadata".to_string())),
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::error::AppError;
+
+/// Files whose object no longer matched its digest.
+static CORRUPTED_FILES: AtomicU64 = AtomicU64::new(0);
+/// Files whose intact object didn't open with their key.
+static KEY_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+static SCRUBBED_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Counts `error` if it's one of the integrity failures.
+pub fn record(error: &AppError) {
+    match error {
+        AppError::Corrupted(_) => CORRUPTED_FILES.fetch_add(1, Ordering::Relaxed),
+        AppError::DecryptFailed(_) => KEY_MISMATCHES.fetch_add(1, Ordering::Relaxed),
+        _ => return,
+    };
+}
+
+pub fn record_scrubbed() {
+    SCRUBBED_FILES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The counters in the Prometheus text format. They count since this
+/// instance started.
+pub fn render() -> String {
+    let counters = [
+        (
+            "cipherdrop_corrupted_files_total",
+            "Files whose stored object no longer matched its digest",
+            &CORRUPTED_FILES,
+        ),
+        (
+            "cipherdrop_key_mismatches_total",
+            "Files that didn't decrypt although their object was intact",
+            &KEY_MISMATCHES,
+        ),
+        (
+            "cipherdrop_scrubbed_files_total",
+            "Files verified by the scrub job",
+            &SCRUBBED_FILES,
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, help, counter) in counters {
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} counter", name);
+        let _ = writeln!(text, "{} {}", name, counter.load(Ordering::Relaxed));
+    }
+    text
+}
@@ -0,0 +1,47 @@
+use crate::error::AppError;
+
+/// GCM tag the client's encryption adds to the padded plaintext.
+const CLIENT_TAG_SIZE: u64 = 16;
+
+/// Padmé, from "Reducing Metadata Leakage from Encrypted Files and
+/// Communication with PURBs": rounds `size` up so only the top bits of its
+/// binary length are kept, which hides the exact size at no more than 12%
+/// overhead. Padded sizes map to themselves.
+pub fn padme(size: u64) -> u64 {
+    if size < 2 {
+        return size;
+    }
+
+    let exponent = size.ilog2();
+    let size_bits = exponent.ilog2() + 1;
+    let mask = (1u64 << (exponent - size_bits)) - 1;
+    (size + mask) & !mask
+}
+
+/// Reads the `padded` field of an upload.
+///
+/// Padded files end their plaintext with a 0x80 byte and as many zeros as it
+/// takes to reach a Padmé size, before the client encrypts them. Only the
+/// client strips it again, the server stores and serves padded sizes.
+pub fn parse(padded: &str) -> Result<bool, AppError> {
+    match padded {
+        "" | "false" | "0" => Ok(false),
+        "true" | "1" => Ok(true),
+        _ => Err(AppError::BadRequest("Invalid padding".to_string())),
+    }
+}
+
+/// Rejects padded uploads of `size` bytes whose plaintext wasn't padded to a
+/// Padmé size, which would give away the exact size after all.
+pub fn check(padded: bool, size: u64) -> Result<(), AppError> {
+    let plaintext_size = size.checked_sub(CLIENT_TAG_SIZE);
+    match (padded, plaintext_size) {
+        (false, _) => Ok(()),
+        (true, Some(plaintext_size))
+            if plaintext_size > 0 && padme(plaintext_size) == plaintext_size =>
+        {
+            Ok(())
+        }
+        _ => Err(AppError::BadRequest("Size isn't padded".to_string())),
+    }
+}
@@ -0,0 +1,124 @@
+use actix_web::HttpRequest;
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use diesel_async::AsyncPgConnection;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    checksum::is_sha256,
+    config::{DAY, MINUTE},
+    database::{actions, models},
+    error::AppError,
+};
+
+/// Header a client proves it knows the password of a file with.
+pub const PROOF_HEADER: &str = "Password-Proof";
+/// Wrong passwords allowed before attempts are locked out.
+pub const FREE_ATTEMPTS: i32 = 5;
+/// The first lockout in seconds, every further wrong password doubles it.
+pub const LOCKOUT: i64 = MINUTE;
+pub const MAX_LOCKOUT: i64 = DAY;
+const SALT_SIZE: usize = 16;
+
+/// Password protection asked for by an upload.
+///
+/// Passwords never reach the server. The client derives 64 bytes from the
+/// password and `salt` with Argon2id, and the first 32 are the proof it sends
+/// in `Password-Proof` to download the file. `verifier` is the SHA-256 of that
+/// proof, so the database doesn't hold anything a download can be made with.
+/// With `key_mix` the other 32 bytes went into the file's key as well, which
+/// only the client knows about.
+pub struct Password {
+    pub salt: String,
+    pub verifier: String,
+    pub key_mix: bool,
+}
+
+/// Reads the password fields of an upload. No salt and verifier means the
+/// file isn't protected.
+pub fn parse(salt: &str, verifier: &str, key_mix: &str) -> Result<Option<Password>, AppError> {
+    let key_mix = match key_mix {
+        "" | "false" | "0" => false,
+        "true" | "1" => true,
+        _ => return Err(AppError::BadRequest("Invalid password key mix".to_string())),
+    };
+
+    if salt.is_empty() && verifier.is_empty() {
+        return match key_mix {
+            true => Err(AppError::BadRequest(
+                "Password key mix needs a password".to_string(),
+            )),
+            false => Ok(None),
+        };
+    }
+
+    let salt_size = general_purpose::URL_SAFE_NO_PAD
+        .decode(salt)
+        .map(|salt| salt.len())
+        .ok();
+    if salt_size != Some(SALT_SIZE) {
+        return Err(AppError::BadRequest("Invalid password salt".to_string()));
+    }
+
+    let verifier = verifier.to_lowercase();
+    if !is_sha256(&verifier) {
+        return Err(AppError::BadRequest(
+            "Invalid password verifier".to_string(),
+        ));
+    }
+
+    Ok(Some(Password {
+        salt: salt.to_string(),
+        verifier,
+        key_mix,
+    }))
+}
+
+/// The proof sent with the request, if any.
+pub fn proof(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(PROOF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+/// Makes sure `proof` is the proof of the password of `file`, when it has one.
+///
+/// Every attempt is counted as a failure before the proof is even looked at,
+/// and the count is only reset once it turns out right, so parallel guesses
+/// can't get past the lockout. See `actions::claim_password_attempt`.
+pub async fn check(
+    conn: &mut AsyncPgConnection,
+    file: &models::File,
+    proof: Option<&str>,
+) -> Result<(), AppError> {
+    let verifier = match &file.password_verifier {
+        Some(verifier) => verifier,
+        None => return Ok(()),
+    };
+
+    let now = Utc::now().naive_utc();
+    if file.password_locked_till.is_some_and(|till| till > now) {
+        return Err(AppError::TooManyAttempts);
+    }
+
+    let proof = proof.ok_or(AppError::PasswordRequired)?;
+
+    if actions::claim_password_attempt(conn, file.id, now)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::TooManyAttempts);
+    }
+
+    // Both sides are hashes of secrets, comparing them leaks nothing useful
+    let matches = general_purpose::URL_SAFE_NO_PAD
+        .decode(proof)
+        .is_ok_and(|proof| hex::encode(Sha256::digest(proof)) == *verifier);
+    if !matches {
+        return Err(AppError::WrongPassword);
+    }
+
+    actions::reset_password_failures(conn, file.id).await?;
+    Ok(())
+}
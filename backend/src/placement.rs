@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use diesel_async::AsyncPgConnection;
+use lazy_static::lazy_static;
+use rand::{distributions::WeightedIndex, prelude::Distribution, thread_rng};
+use serde::Deserialize;
+
+use crate::{
+    config,
+    database::{actions, models, DbError},
+};
+
+const UNHEALTHY_FOR: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Every active bucket in turn.
+    RoundRobin,
+    /// Randomly, in proportion to each bucket's `weight`.
+    Weighted,
+    /// The bucket holding the fewest stored bytes.
+    LeastUsed,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(Strategy::RoundRobin),
+            "weighted" => Ok(Strategy::Weighted),
+            "least_used" => Ok(Strategy::LeastUsed),
+            _ => Err(format!("Unknown placement strategy {}", s)),
+        }
+    }
+}
+
+lazy_static! {
+    static ref UNHEALTHY: Mutex<HashMap<i32, Instant>> = Mutex::new(HashMap::new());
+}
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Remembers that writing to a bucket failed, so uploads avoid it for a while.
+pub fn mark_unhealthy(bucket_id: i32) {
+    UNHEALTHY
+        .lock()
+        .unwrap()
+        .insert(bucket_id, Instant::now() + UNHEALTHY_FOR);
+}
+
+pub fn mark_healthy(bucket_id: i32) {
+    UNHEALTHY.lock().unwrap().remove(&bucket_id);
+}
+
+fn is_healthy(bucket_id: i32) -> bool {
+    match UNHEALTHY.lock().unwrap().get(&bucket_id) {
+        Some(until) => *until <= Instant::now(),
+        None => true,
+    }
+}
+
+/// Picks the bucket a new upload should go to. Only active buckets are
+/// considered. Unhealthy ones are skipped too, unless that leaves nothing.
+pub async fn choose_bucket(
+    conn: &mut AsyncPgConnection,
+) -> Result<Option<models::S3Bucket>, DbError> {
+    let mut buckets = actions::get_writable_s3_buckets(conn).await?;
+
+    let config = &config::config().placement;
+    if config.skip_unhealthy {
+        skip_unhealthy(&mut buckets);
+    }
+
+    if buckets.is_empty() {
+        return Ok(None);
+    }
+
+    // Only needed, and only worth loading, for the least used bucket
+    let usage = match config.strategy {
+        Strategy::LeastUsed => actions::get_s3_bucket_usage(conn)
+            .await?
+            .into_iter()
+            .map(|(id, _, bytes)| (id, bytes))
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    Ok(pick(config.strategy, &buckets, &usage).map(|index| buckets.swap_remove(index)))
+}
+
+fn skip_unhealthy(buckets: &mut Vec<models::S3Bucket>) {
+    if buckets.iter().any(|bucket| is_healthy(bucket.id)) {
+        buckets.retain(|bucket| is_healthy(bucket.id));
+    }
+}
+
+/// Index of the bucket `strategy` picks. `usage` holds the bytes stored in
+/// each bucket, by id.
+fn pick(
+    strategy: Strategy,
+    buckets: &[models::S3Bucket],
+    usage: &HashMap<i32, i64>,
+) -> Option<usize> {
+    if buckets.is_empty() {
+        return None;
+    }
+
+    match strategy {
+        Strategy::RoundRobin => Some(NEXT.fetch_add(1, Ordering::Relaxed) % buckets.len()),
+        Strategy::Weighted => {
+            let weights = buckets.iter().map(|bucket| bucket.weight.max(0));
+            let distribution = WeightedIndex::new(weights).ok()?;
+            Some(distribution.sample(&mut thread_rng()))
+        }
+        Strategy::LeastUsed => buckets
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, bucket)| usage.get(&bucket.id).copied().unwrap_or_default())
+            .map(|(index, _)| index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn bucket(id: i32, weight: i32) -> models::S3Bucket {
+        models::S3Bucket {
+            id,
+            bucket_name: format!("bucket-{}", id),
+            region: String::new(),
+            endpoint: String::new(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            backend: "memory".to_string(),
+            weight,
+            status: "active".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let buckets = [bucket(1, 1), bucket(2, 1), bucket(3, 1)];
+        let usage = HashMap::new();
+
+        let picked: HashSet<usize> = (0..3)
+            .map(|_| pick(Strategy::RoundRobin, &buckets, &usage).unwrap())
+            .collect();
+        assert_eq!(picked.len(), 3);
+        assert_eq!(pick(Strategy::RoundRobin, &[], &usage), None);
+    }
+
+    #[test]
+    fn weighted_follows_weights() {
+        let usage = HashMap::new();
+
+        let buckets = [bucket(1, 0), bucket(2, 5), bucket(3, -1)];
+        for _ in 0..50 {
+            assert_eq!(pick(Strategy::Weighted, &buckets, &usage), Some(1));
+        }
+
+        let buckets = [bucket(1, 0), bucket(2, 0)];
+        assert_eq!(pick(Strategy::Weighted, &buckets, &usage), None);
+    }
+
+    #[test]
+    fn least_used_takes_emptiest() {
+        let buckets = [bucket(1, 1), bucket(2, 1), bucket(3, 1)];
+
+        let usage = HashMap::from([(1, 100), (2, 10), (3, 50)]);
+        assert_eq!(pick(Strategy::LeastUsed, &buckets, &usage), Some(1));
+
+        // A bucket without files doesn't show up in the usage
+        let usage = HashMap::from([(1, 100), (2, 10)]);
+        assert_eq!(pick(Strategy::LeastUsed, &buckets, &usage), Some(2));
+    }
+
+    #[test]
+    fn skips_unhealthy_unless_all_are() {
+        mark_unhealthy(901);
+
+        let mut buckets = vec![bucket(901, 1), bucket(902, 1)];
+        skip_unhealthy(&mut buckets);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].id, 902);
+
+        let mut buckets = vec![bucket(901, 1)];
+        skip_unhealthy(&mut buckets);
+        assert_eq!(buckets.len(), 1);
+
+        mark_healthy(901);
+        let mut buckets = vec![bucket(901, 1), bucket(902, 1)];
+        skip_unhealthy(&mut buckets);
+        assert_eq!(buckets.len(), 2);
+    }
+}
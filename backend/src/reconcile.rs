@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel_async::AsyncPgConnection;
+use uuid::Uuid;
+
+use crate::{
+    database::{actions, models},
+    storage::{self, ListedObject, StorageBackend, StorageError},
+};
+
+/// What was found out of sync in a bucket.
+#[derive(Default)]
+pub struct Report {
+    /// Objects that no file or upload refers to, with their size.
+    pub orphan_objects: Vec<(String, u64)>,
+    /// Files whose object is gone.
+    pub missing_objects: Vec<Uuid>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.orphan_objects.is_empty() && self.missing_objects.is_empty()
+    }
+}
+
+/// Compares the objects in `bucket` with the files that should be in it.
+/// Orphaned objects are deleted, as are files whose object is gone, unless
+/// `dry_run` is set. Anything younger than `grace` seconds is left alone,
+/// since an upload may be between storing its object and adding its record.
+///
+/// Objects whose key isn't a UUID weren't stored by us and are ignored. An
+/// object or file that fails to check or delete is logged and skipped, and
+/// left out of the report.
+pub async fn reconcile_bucket(
+    conn: &mut AsyncPgConnection,
+    bucket: models::S3Bucket,
+    grace: i64,
+    dry_run: bool,
+) -> Result<Report, String> {
+    let id = bucket.id;
+    let storage =
+        storage::backend_for(bucket).ok_or_else(|| format!("Couldn't load bucket {}", id))?;
+    let before = Utc::now().naive_utc() - Duration::seconds(grace);
+
+    let objects = storage
+        .list()
+        .await
+        .map_err(|e| format!("Couldn't list bucket {}: {}", id, e))?;
+
+    // Loaded after listing, so a listed object whose record was just added
+    // isn't mistaken for an orphan
+    let files = actions::get_files_in_s3_bucket(conn, id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let uploads: HashSet<Uuid> = actions::get_tus_uploads_in_s3_bucket(conn, id)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+
+    let known: HashSet<Uuid> = files.iter().map(|(file, _)| *file).collect();
+    let listed: HashSet<Uuid> = objects
+        .iter()
+        .filter_map(|object| Uuid::parse_str(&object.key).ok())
+        .collect();
+    let candidates: Vec<Uuid> = files
+        .into_iter()
+        .filter(|(file, date_created)| !listed.contains(file) && *date_created < before)
+        .map(|(file, _)| file)
+        .collect();
+
+    let mut report = Report {
+        orphan_objects: remove_orphans(
+            &*storage,
+            id,
+            objects,
+            |file| known.contains(&file) || uploads.contains(&file),
+            before,
+            dry_run,
+        )
+        .await,
+        missing_objects: find_missing(&*storage, id, candidates).await,
+    };
+
+    if !dry_run {
+        let mut deleted = Vec::with_capacity(report.missing_objects.len());
+        for file in report.missing_objects {
+            match actions::delete_file(conn, file).await {
+                Ok(_) => deleted.push(file),
+                Err(e) => eprintln!("Bucket {}: couldn't delete file {}: {}", id, file, e),
+            }
+        }
+        report.missing_objects = deleted;
+    }
+
+    Ok(report)
+}
+
+/// Deletes the objects older than `before` whose key is a UUID that `is_known`
+/// rejects, and returns them with their size. Objects that fail to delete are
+/// logged and left out.
+async fn remove_orphans(
+    storage: &dyn StorageBackend,
+    id: i32,
+    objects: Vec<ListedObject>,
+    is_known: impl Fn(Uuid) -> bool,
+    before: NaiveDateTime,
+    dry_run: bool,
+) -> Vec<(String, u64)> {
+    let mut orphans = Vec::new();
+
+    for object in objects {
+        let is_orphan = match Uuid::parse_str(&object.key) {
+            Ok(file) => !is_known(file),
+            Err(_) => false,
+        };
+        if !is_orphan || object.last_modified >= before {
+            continue;
+        }
+
+        if !dry_run {
+            match storage.delete(&object.key).await {
+                Ok(()) | Err(StorageError::NotFound) => {}
+                Err(e) => {
+                    eprintln!(
+                        "Bucket {}: couldn't delete object {}: {}",
+                        id, object.key, e
+                    );
+                    continue;
+                }
+            }
+        }
+        orphans.push((object.key, object.size));
+    }
+
+    orphans
+}
+
+/// The `files` whose object is really gone. Files that can't be checked are
+/// logged and left out.
+async fn find_missing(storage: &dyn StorageBackend, id: i32, files: Vec<Uuid>) -> Vec<Uuid> {
+    let mut missing = Vec::new();
+
+    for file in files {
+        // Listing isn't a snapshot, make sure it's really gone
+        match storage.head(&file.to_string()).await {
+            Err(StorageError::NotFound) => missing.push(file),
+            Ok(_) => {}
+            Err(e) => eprintln!("Bucket {}: couldn't check object {}: {}", id, file, e),
+        }
+    }
+
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn finds_records_without_an_object() {
+        let storage = MemoryStorage::default();
+        let stored = Uuid::new_v4();
+        let gone = Uuid::new_v4();
+        storage.put(&stored.to_string(), vec![1]).await.unwrap();
+
+        assert_eq!(find_missing(&storage, 1, vec![stored, gone]).await, [gone]);
+    }
+
+    #[actix_web::test]
+    async fn removes_only_old_unknown_objects() {
+        let storage = MemoryStorage::default();
+        let known = Uuid::new_v4();
+        let orphan = Uuid::new_v4();
+        for key in [
+            known.to_string(),
+            orphan.to_string(),
+            "not-ours".to_string(),
+        ] {
+            storage.put(&key, vec![0; 3]).await.unwrap();
+        }
+        let is_known = |file| file == known;
+        let earlier = Utc::now().naive_utc() - Duration::seconds(60);
+        let later = Utc::now().naive_utc() + Duration::seconds(1);
+
+        // Still within the grace period
+        let objects = storage.list().await.unwrap();
+        let removed = remove_orphans(&storage, 1, objects, is_known, earlier, false).await;
+        assert!(removed.is_empty());
+
+        let objects = storage.list().await.unwrap();
+        let removed = remove_orphans(&storage, 1, objects, is_known, later, true).await;
+        assert_eq!(removed, [(orphan.to_string(), 3)]);
+        assert!(storage.head(&orphan.to_string()).await.is_ok());
+
+        let objects = storage.list().await.unwrap();
+        let removed = remove_orphans(&storage, 1, objects, is_known, later, false).await;
+        assert_eq!(removed, [(orphan.to_string(), 3)]);
+        assert!(matches!(
+            storage.head(&orphan.to_string()).await,
+            Err(StorageError::NotFound)
+        ));
+        assert!(storage.head(&known.to_string()).await.is_ok());
+        assert!(storage.head("not-ours").await.is_ok());
+    }
+}
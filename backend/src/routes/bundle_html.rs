@@ -0,0 +1,59 @@
+use actix_web::{get, web, Error, HttpResponse};
+use tera::{Context, Tera};
+
+use crate::{
+    bundles::{available_files, get_bundle},
+    lifetime::{self, describe_duration},
+    routes::{parse_uuid, render, render_error},
+    DbPool,
+};
+
+#[get("/bundle/{bundle_uuid}")]
+pub async fn bundle_html(
+    path: web::Path<(String,)>,
+    pool: web::Data<DbPool>,
+    tmpl: web::Data<Tera>,
+) -> Result<HttpResponse, Error> {
+    let bundle_uuid = match parse_uuid(path) {
+        Ok(bundle_uuid) => bundle_uuid,
+        Err(e) => return render_error(&tmpl, "bundle.html", e),
+    };
+
+    let mut conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => return render_error(&tmpl, "bundle.html", e.into()),
+    };
+
+    let bundle = match get_bundle(&mut conn, bundle_uuid).await {
+        Ok(bundle) => bundle,
+        Err(e) => return render_error(&tmpl, "bundle.html", e),
+    };
+
+    let files = match available_files(&mut conn, &bundle).await {
+        Ok(files) => files,
+        Err(e) => return render_error(&tmpl, "bundle.html", e),
+    };
+
+    // The names are in the manifest, which only the browser can open. The
+    // page lists what it finds there, these are the ones still around.
+    let available: Vec<String> = files.iter().map(|file| file.file.to_string()).collect();
+
+    let mut ctx = Context::new();
+    ctx.insert("success", &true);
+    ctx.insert("uuid", &bundle.bundle.to_string());
+    ctx.insert(
+        "available_till",
+        &bundle.available_till.and_utc().timestamp(),
+    );
+    ctx.insert("manifest", &bundle.manifest);
+    ctx.insert("available", &available.join(","));
+
+    let policy = lifetime::policy();
+    if policy.extend_on_access {
+        ctx.insert("extend_by", &describe_duration(policy.extend_by));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(render(&tmpl, "bundle.html", &ctx)?))
+}
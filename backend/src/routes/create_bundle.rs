@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+
+use actix_web::{post, web, HttpResponse};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{
+    bundles::{self, MAX_FILES, MAX_MANIFEST_SIZE},
+    error::AppError,
+    files::get_owned_file,
+    lifetime,
+    routes::{uuid, HttpFileUploadApiResponse},
+    DbPool,
+};
+
+#[derive(Deserialize)]
+struct BundleFile {
+    uuid: String,
+    delete_token: String,
+}
+
+#[derive(Deserialize)]
+struct NewBundle {
+    /// Uploaded files to put in the bundle, each with its delete token to
+    /// show they're the uploader's.
+    files: Vec<BundleFile>,
+    /// Names, types and keys of the files, encrypted with the bundle's key.
+    /// Base64url, the server never sees inside.
+    manifest: String,
+    /// Shared by all files, replacing the ones they were uploaded with.
+    lifetime: Option<String>,
+}
+
+/// Puts files that were already uploaded under one link. They share the
+/// lifetime of the bundle from then on.
+#[post("/api/bundle")]
+pub async fn create_bundle(
+    pool: web::Data<DbPool>,
+    body: web::Json<NewBundle>,
+) -> Result<HttpResponse, AppError> {
+    let body = body.into_inner();
+
+    if body.files.is_empty() || body.files.len() > MAX_FILES {
+        return Err(AppError::BadRequest(format!(
+            "A bundle holds between 1 and {} files",
+            MAX_FILES
+        )));
+    }
+    if body.manifest.len() > MAX_MANIFEST_SIZE {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Manifest is over {} bytes",
+            MAX_MANIFEST_SIZE
+        )));
+    }
+    if !general_purpose::URL_SAFE_NO_PAD
+        .decode(&body.manifest)
+        .is_ok_and(|manifest| !manifest.is_empty())
+    {
+        return Err(AppError::BadRequest("Invalid manifest".to_string()));
+    }
+    let lifetime = lifetime::policy()
+        .lifetime(body.lifetime.as_deref())
+        .map_err(AppError::BadRequest)?;
+
+    let mut conn = pool.get().await?;
+
+    let now = Utc::now().naive_utc();
+    let mut file_ids = Vec::with_capacity(body.files.len());
+    let mut seen = HashSet::new();
+    for file in &body.files {
+        let file_uuid = uuid(&file.uuid)?;
+        if !seen.insert(file_uuid) {
+            return Err(AppError::BadRequest(format!(
+                "File {} is listed twice",
+                file_uuid
+            )));
+        }
+
+        let file = get_owned_file(&mut conn, file_uuid, &file.delete_token)
+            .await?
+            .ok_or(AppError::NotFound("file"))?;
+        if file.available_till <= now {
+            return Err(AppError::Expired);
+        }
+        // The bundle page has no way to ask for passwords
+        if file.password_verifier.is_some() {
+            return Err(AppError::BadRequest(
+                "Password protected files can't be bundled".to_string(),
+            ));
+        }
+        file_ids.push(file.id);
+    }
+
+    let (bundle, delete_token) =
+        bundles::create_bundle(&mut conn, &body.manifest, lifetime, &file_ids).await?;
+
+    Ok(HttpResponse::Ok().json(HttpFileUploadApiResponse {
+        success: true,
+        uuid: bundle.to_string(),
+        delete_token,
+    }))
+}
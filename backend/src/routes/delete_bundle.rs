@@ -0,0 +1,34 @@
+use actix_web::{delete, web, HttpRequest, HttpResponse};
+
+use crate::{
+    bundles::{self, get_owned_bundle},
+    error::AppError,
+    routes::{delete_token, parse_uuid, HttpApiResponse},
+    DbPool,
+};
+
+/// Deletes a bundle with all its files before it expires. Takes the token
+/// returned when it was created as `Authorization: Bearer <token>`.
+#[delete("/api/bundle/{bundle_uuid}")]
+pub async fn delete_bundle(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, AppError> {
+    let bundle_uuid = parse_uuid(path)?;
+    let delete_token = delete_token(&req)?;
+
+    let mut conn = pool.get().await?;
+
+    // Same answer for a wrong token as for a missing bundle, like files
+    let bundle = get_owned_bundle(&mut conn, bundle_uuid, delete_token)
+        .await?
+        .ok_or(AppError::NotFound("bundle"))?;
+
+    bundles::delete_bundle(&mut conn, &bundle).await?;
+
+    Ok(HttpResponse::Ok().json(HttpApiResponse {
+        success: true,
+        message: "Bundle deleted".to_string(),
+    }))
+}
@@ -0,0 +1,36 @@
+use actix_web::{delete, web, HttpRequest, HttpResponse};
+
+use crate::{
+    error::AppError,
+    files::{self, get_owned_file},
+    routes::{delete_token, parse_uuid, HttpApiResponse},
+    DbPool,
+};
+
+/// Deletes a file before it expires. Takes the token returned by the upload
+/// as `Authorization: Bearer <token>`.
+#[delete("/api/file/{file_uuid}")]
+pub async fn delete_file(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, AppError> {
+    let file_uuid = parse_uuid(path)?;
+
+    let delete_token = delete_token(&req)?;
+
+    let mut conn = pool.get().await?;
+
+    // A wrong token gets the same answer as a missing file, so the endpoint
+    // can't be used to find out which files exist
+    let file = get_owned_file(&mut conn, file_uuid, delete_token)
+        .await?
+        .ok_or(AppError::NotFound("file"))?;
+
+    files::delete_file(&mut conn, &file).await?;
+
+    Ok(HttpResponse::Ok().json(HttpApiResponse {
+        success: true,
+        message: "File deleted".to_string(),
+    }))
+}
@@ -1,83 +1,394 @@
-use actix_web::{get, web, Error, HttpResponse};
+use std::{sync::Arc, time::SystemTime};
+
+use actix_web::{
+    body::SizedStream,
+    http::header::{
+        ByteRangeSpec, ContentRange, ContentRangeSpec, ETag, EntityTag, Header, HttpDate, IfRange,
+        LastModified, Range, ACCEPT_RANGES,
+    },
+    http::Method,
+    route, web, Error, HttpRequest, HttpResponse,
+};
+use diesel_async::AsyncPgConnection;
+use futures_util::{stream, Stream};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    crypt::{decrypt, Encrypted},
-    files::get_file,
-    routes::HttpApiResponse,
-    s3, DbPool,
+    bundles::get_bundle,
+    crypt::{
+        decrypt, plaintext_size, segment_count, segment_offset, Encrypted, StreamDecryptor,
+        ENCRYPTED_SEGMENT_SIZE, HEADER, LEGACY_VERSION, SEGMENT_SIZE,
+    },
+    database::models,
+    error::AppError,
+    files::{claim_download, delete_file, get_file, storage_unavailable},
+    integrity, keyring, metrics, password,
+    routes::{parse_uuid, uuid},
+    storage::{self, StorageBackend},
+    DbPool,
 };
 
-#[get("/api/file/{file_uuid}/download")]
+const SEGMENTS_PER_FETCH: u64 = 16;
+
+#[route("/api/file/{file_uuid}/download", method = "GET", method = "HEAD")]
 pub async fn download_file(
+    req: HttpRequest,
     pool: web::Data<DbPool>,
     path: web::Path<(String,)>,
-) -> Result<HttpResponse, Error> {
-    let file_uuid = match uuid::Uuid::try_parse(path.into_inner().0.as_str()) {
-        Ok(uuid) => uuid,
+) -> Result<HttpResponse, AppError> {
+    let file_uuid = parse_uuid(path)?;
+
+    let mut conn = pool.get().await?;
+    let file = get_file(&mut conn, file_uuid).await?;
+    password::check(&mut conn, &file, password::proof(&req)).await?;
+
+    serve_file(&req, &pool, &mut conn, file).await
+}
+
+/// Downloads a file of a bundle, which is only found through the bundle it's
+/// in.
+#[route(
+    "/api/bundle/{bundle_uuid}/file/{file_uuid}",
+    method = "GET",
+    method = "HEAD"
+)]
+pub async fn download_bundle_file(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (bundle_uuid, file_uuid) = path.into_inner();
+    let (bundle_uuid, file_uuid) = (uuid(&bundle_uuid)?, uuid(&file_uuid)?);
+
+    let mut conn = pool.get().await?;
+    let bundle = get_bundle(&mut conn, bundle_uuid).await?;
+    let file = get_file(&mut conn, file_uuid).await?;
+    if file.bundle_id != Some(bundle.id) {
+        return Err(AppError::NotFound("file"));
+    }
+    password::check(&mut conn, &file, password::proof(&req)).await?;
+
+    serve_file(&req, &pool, &mut conn, file).await
+}
+
+/// Streams `file` once the request may have it.
+async fn serve_file(
+    req: &HttpRequest,
+    pool: &DbPool,
+    conn: &mut AsyncPgConnection,
+    mut file: models::File,
+) -> Result<HttpResponse, AppError> {
+    let storage = storage::get_specific_storage(conn, file.s3_bucket_id)
+        .await
+        .ok_or_else(|| storage_unavailable(file.s3_bucket_id))?;
+
+    file.key = keyring::file_key(&file).ok_or_else(|| {
+        let error = AppError::DecryptFailed(format!("Couldn't unwrap key of file {}", file.id));
+        metrics::record(&error);
+        error
+    })?;
+
+    // Download limits came after the stream format, so legacy files never
+    // have one
+    if file.encryption_version == LEGACY_VERSION {
+        return download_legacy(storage.as_ref(), &file).await;
+    }
+
+    let object_path = file.file.to_string();
+    let object_size = storage.head(&object_path).await?.size;
+
+    let (decryptor, size) = match (
+        StreamDecryptor::new(&file.key, &file.nonce),
+        plaintext_size(object_size),
+    ) {
+        (Some(decryptor), Some(size)) => (decryptor, size),
         _ => {
-            return Ok(HttpResponse::ExpectationFailed().json(HttpApiResponse {
-                success: false,
-                message: "Invalid UUID".to_string(),
-            }))
+            let cause = format!("File {} has an invalid key or size", file.id);
+            return Err(integrity::diagnose(storage.as_ref(), &file, cause).await);
         }
     };
 
-    let mut conn = match pool.get().await {
-        Ok(conn) => conn,
-        _ => {
-            return Ok(HttpResponse::InternalServerError().json(HttpApiResponse {
-                success: false,
-                message: "Internal error, please try again later".to_string(),
-            }))
+    // Only counted once the file is known to be readable, so a storage
+    // hiccup doesn't use up a download
+    let limited = file.max_downloads.is_some();
+    let last_download = match *req.method() {
+        Method::GET => claim_download(conn, &file)
+            .await?
+            .ok_or(AppError::DownloadLimitReached)?,
+        _ if file
+            .max_downloads
+            .is_some_and(|max| file.download_count >= max) =>
+        {
+            return Err(AppError::DownloadLimitReached)
         }
+        _ => false,
     };
 
-    let file = match get_file(&mut *conn, file_uuid).await {
-        Ok(file) => file,
-        _ => {
-            return Ok(HttpResponse::InternalServerError().json(HttpApiResponse {
-                success: false,
-                message: "Internal error, please try again later".to_string(),
-            }))
-        }
+    let etag = EntityTag::new_strong(object_path.clone());
+    let last_modified = HttpDate::from(SystemTime::from(file.date_created.and_utc()));
+
+    // Limited files are served whole, every request counts as a download
+    let range = match limited {
+        true => None,
+        false => requested_range(req, &etag, &last_modified),
     };
 
-    let bucket = match s3::get_s3_specific_bucket(&mut *conn, file.s3_bucket_id).await {
-        Some(bucket) => bucket,
-        None => {
-            return Ok(HttpResponse::ServiceUnavailable().json(HttpApiResponse {
-                success: false,
-                message: "Couldn't receive storage".to_string(),
-            }))
+    let (mut response, start, end) = match range {
+        Some(range) => match range.to_satisfiable_range(size) {
+            Some((start, end)) => {
+                let mut response = HttpResponse::PartialContent();
+                response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)),
+                    instance_length: Some(size),
+                }));
+                (response, start, end + 1)
+            }
+            None => {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: None,
+                        instance_length: Some(size),
+                    }))
+                    .finish())
+            }
+        },
+        None => (HttpResponse::Ok(), 0, size),
+    };
+
+    response
+        .content_type("application/octet-stream")
+        .insert_header((ACCEPT_RANGES, if limited { "none" } else { "bytes" }))
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified));
+
+    // Whole downloads are checked against the digests as they're streamed
+    let digests = match (&file.stored_sha256, start, end) {
+        (Some(_), 0, end) if end == size && size > 0 => {
+            // The header isn't fetched, every segment is authenticated
+            // against the one we know
+            let mut stored = Sha256::new();
+            stored.update(HEADER);
+            Some((stored, Sha256::new()))
         }
+        _ => None,
     };
 
-    let bytes = match bucket.get_object(format!("{}", file_uuid)).await {
-        Ok(file) => file,
-        _ => {
-            return Ok(HttpResponse::ServiceUnavailable().json(HttpApiResponse {
-                success: false,
-                message: "Couldn't find file".to_string(),
-            }))
+    let download = Download {
+        storage,
+        path: object_path,
+        decryptor,
+        object_size,
+        segments: segment_count(size),
+        digests,
+        burn: match last_download {
+            true => Some(pool.clone()),
+            false => None,
+        },
+        file,
+    };
+
+    // The first segments are opened before answering, so a file that's damaged
+    // or doesn't open gets an error instead of a cut off body
+    let first_segment = start / SEGMENT_SIZE as u64;
+    let (download, segment, first) = match *req.method() {
+        Method::GET => match read_segments(download, first_segment, start, end).await? {
+            Some((first, download, segment)) => (download, segment, Some(first)),
+            None => return Ok(response.finish()),
+        },
+        _ => (download, first_segment, None),
+    };
+
+    Ok(response.body(SizedStream::new(
+        end - start,
+        decrypt_range(download, segment, first, start, end),
+    )))
+}
+
+/// The single byte range the client asked for, if it should be honoured.
+/// Multiple ranges and ranges guarded by an outdated `If-Range` get the whole
+/// file instead, which is always allowed.
+fn requested_range(
+    req: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: &HttpDate,
+) -> Option<ByteRangeSpec> {
+    let mut ranges = match Range::parse(req) {
+        Ok(Range::Bytes(ranges)) if ranges.len() == 1 => ranges,
+        _ => return None,
+    };
+
+    if req.headers().contains_key(IfRange::name()) {
+        let still_valid = match IfRange::parse(req) {
+            Ok(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+            Ok(IfRange::Date(date)) => &date == last_modified,
+            Err(_) => false,
+        };
+
+        if !still_valid {
+            return None;
         }
     }
-    .to_vec();
+
+    ranges.pop()
+}
+
+struct Download {
+    storage: Arc<dyn StorageBackend>,
+    path: String,
+    decryptor: StreamDecryptor,
+    object_size: u64,
+    segments: u64,
+    file: models::File,
+    /// Digests of the object and of the plaintext sent so far.
+    digests: Option<(Sha256, Sha256)>,
+    /// Set when this was the last download the file had left. The file is
+    /// deleted once it has been sent.
+    burn: Option<DbPool>,
+}
+
+/// Streams plaintext bytes `start..end`, fetching and opening a few segments
+/// at a time so memory stays bounded no matter how large the range is. `first`
+/// is what was already read from `segment` on.
+fn decrypt_range(
+    download: Download,
+    segment: u64,
+    first: Option<web::Bytes>,
+    start: u64,
+    end: u64,
+) -> impl Stream<Item = Result<web::Bytes, Error>> {
+    stream::try_unfold(
+        (download, segment, first),
+        move |(download, segment, first)| async move {
+            if let Some(first) = first {
+                return Ok(Some((first, (download, segment, None))));
+            }
+
+            // The response has started, so the error can only end the body
+            let next = read_segments(download, segment, start, end)
+                .await
+                .inspect_err(AppError::log)?;
+            Ok(next.map(|(bytes, download, segment)| (bytes, (download, segment, None))))
+        },
+    )
+}
+
+/// The plaintext of the next few segments from `segment` on, with the segment
+/// to continue at. `None` once the range has been sent.
+async fn read_segments(
+    mut download: Download,
+    segment: u64,
+    start: u64,
+    end: u64,
+) -> Result<Option<(web::Bytes, Download, u64)>, AppError> {
+    if segment * SEGMENT_SIZE as u64 >= end {
+        if let Some((stored, received)) = download.digests.take() {
+            verify_digests(&download.file, stored, received)?;
+        }
+
+        if let Some(pool) = &download.burn {
+            if let Ok(mut conn) = pool.get().await {
+                let _ = delete_file(&mut conn, &download.file).await;
+            }
+        }
+
+        return Ok(None);
+    }
+
+    let last_segment = ((end - 1) / SEGMENT_SIZE as u64)
+        .min(segment + SEGMENTS_PER_FETCH - 1)
+        .min(download.segments - 1);
+
+    let from = segment_offset(segment);
+    let to = segment_offset(last_segment + 1).min(download.object_size);
+
+    let encrypted_segments = download.storage.get_range(&download.path, from, to).await?;
+
+    if let Some((stored, _)) = &mut download.digests {
+        stored.update(&encrypted_segments);
+    }
+
+    let mut result = Vec::new();
+
+    for (offset, encrypted) in encrypted_segments
+        .chunks(ENCRYPTED_SEGMENT_SIZE)
+        .enumerate()
+    {
+        let index = segment + offset as u64;
+        let plaintext = match download.decryptor.open_segment(
+            index as u32,
+            index + 1 == download.segments,
+            encrypted,
+        ) {
+            Some(plaintext) => plaintext,
+            None => {
+                let cause = format!("Segment {} doesn't open", index);
+                let storage = download.storage.as_ref();
+                return Err(integrity::diagnose(storage, &download.file, cause).await);
+            }
+        };
+
+        if let Some((_, received)) = &mut download.digests {
+            received.update(&plaintext);
+        }
+
+        let segment_start = index * SEGMENT_SIZE as u64;
+        let from = start.saturating_sub(segment_start) as usize;
+        let to = ((end - segment_start) as usize).min(plaintext.len());
+        result.extend_from_slice(&plaintext[from..to]);
+    }
+
+    Ok(Some((web::Bytes::from(result), download, last_segment + 1)))
+}
+
+/// Compares what a whole download read and sent with the digests of the file.
+fn verify_digests(file: &models::File, stored: Sha256, received: Sha256) -> Result<(), AppError> {
+    let stored = hex::encode(stored.finalize());
+    let error = if file
+        .stored_sha256
+        .as_ref()
+        .is_some_and(|expected| expected != &stored)
+    {
+        AppError::Corrupted(format!(
+            "Object of file {} has digest {} while downloading",
+            file.id, stored
+        ))
+    } else if file
+        .client_sha256
+        .as_ref()
+        .is_some_and(|expected| expected != &hex::encode(received.finalize()))
+    {
+        AppError::Corrupted(format!(
+            "File {} didn't decrypt to what was uploaded",
+            file.id
+        ))
+    } else {
+        return Ok(());
+    };
+
+    metrics::record(&error);
+    Err(error)
+}
+
+async fn download_legacy(
+    storage: &dyn StorageBackend,
+    file: &models::File,
+) -> Result<HttpResponse, AppError> {
+    let bytes = storage.get(&file.file.to_string()).await?;
 
     let encrypted_file = Encrypted {
-        key: file.key,
-        nonce: file.nonce,
+        key: file.key.clone(),
+        nonce: file.nonce.clone(),
+        version: file.encryption_version,
         result: bytes,
     };
 
-    let file = match decrypt(encrypted_file) {
-        Some(file) => file,
-        _ => {
-            return Ok(HttpResponse::ServiceUnavailable().json(HttpApiResponse {
-                success: false,
-                message: "Couldn't decrypt file".to_string(),
-            }))
-        }
-    };
+    let plaintext = decrypt(encrypted_file).ok_or_else(|| {
+        let error = AppError::DecryptFailed(format!("Legacy file {} doesn't open", file.id));
+        metrics::record(&error);
+        error
+    })?;
 
-    return Ok(HttpResponse::Ok().body(file));
+    Ok(HttpResponse::Ok()
+        .insert_header((ACCEPT_RANGES, "none"))
+        .body(plaintext))
 }
@@ -2,7 +2,14 @@ use actix_web::{get, web, Error, HttpResponse};
 use serde::Deserialize;
 use tera::{Context, Tera};
 
-use crate::{files::get_file, DbPool};
+use crate::{
+    error::AppError,
+    files::get_file,
+    lifetime::{self, describe_duration},
+    metadata::Metadata,
+    routes::{parse_uuid, render, render_error},
+    DbPool,
+};
 
 #[derive(Deserialize)]
 struct SearchParams {
@@ -17,54 +24,66 @@ pub async fn file_html(
     tmpl: web::Data<Tera>,
     search_params: web::Query<SearchParams>,
 ) -> Result<HttpResponse, Error> {
-    let mut ctx = Context::new();
-
-    let file_uuid = match uuid::Uuid::try_parse(path.into_inner().0.as_str()) {
-        Ok(uuid) => uuid,
-        _ => {
-            ctx.insert("success", &false);
-            ctx.insert("msg", "Invalid UUID");
-            let rendered = tmpl.render("file.html", &ctx).map_err(|_| {
-                actix_web::error::ErrorInternalServerError("Template rendering error")
-            })?;
-            return Ok(HttpResponse::Ok().content_type("text/html").body(rendered));
-        }
+    let file_uuid = match parse_uuid(path) {
+        Ok(file_uuid) => file_uuid,
+        Err(e) => return render_error(&tmpl, "file.html", e),
     };
 
     let mut conn = match pool.get().await {
         Ok(conn) => conn,
-        _ => {
-            ctx.insert("success", &false);
-            ctx.insert("msg", "Internal error, please try again later");
-            let rendered = tmpl.render("file.html", &ctx).map_err(|_| {
-                actix_web::error::ErrorInternalServerError("Template rendering error")
-            })?;
-            return Ok(HttpResponse::Ok().content_type("text/html").body(rendered));
-        }
+        Err(e) => return render_error(&tmpl, "file.html", e.into()),
     };
 
-    let file = match get_file(&mut *conn, file_uuid).await {
+    let file = match get_file(&mut conn, file_uuid).await {
         Ok(file) => file,
-        _ => {
-            ctx.insert("success", &false);
-            ctx.insert("msg", "Internal error, please try again later");
-            let rendered = tmpl.render("file.html", &ctx).map_err(|_| {
-                actix_web::error::ErrorInternalServerError("Template rendering error")
-            })?;
-            return Ok(HttpResponse::Ok().content_type("text/html").body(rendered));
-        }
+        Err(e) => return render_error(&tmpl, "file.html", e),
     };
 
+    let downloads_left = file
+        .max_downloads
+        .map(|max_downloads| (max_downloads - file.download_count).max(0));
+
+    if downloads_left == Some(0) {
+        return render_error(&tmpl, "file.html", AppError::DownloadLimitReached);
+    }
+
+    let mut ctx = Context::new();
     ctx.insert("success", &true);
     ctx.insert("uuid", &file_uuid.to_string());
     ctx.insert("available_till", &file.available_till.and_utc().timestamp());
-    ctx.insert("file_name", &file.file_name);
-    ctx.insert("mime_type", &file.file_type);
+    // The name and type of a password protected file are only told once the
+    // password is proven, through `/api/file/{uuid}`. Sealed ones are opened
+    // by the page.
+    match (&file.password_salt, Metadata::of(&file)) {
+        (Some(salt), _) => {
+            ctx.insert("password_salt", salt);
+            ctx.insert("password_key_mix", &file.password_key_mix);
+        }
+        (
+            None,
+            Metadata::Plain {
+                file_name,
+                file_type,
+            },
+        ) => {
+            ctx.insert("file_name", &file_name);
+            ctx.insert("mime_type", &file_type);
+        }
+        (None, Metadata::Sealed(sealed)) => ctx.insert("metadata", &sealed),
+    }
+    ctx.insert("padded", &file.padded);
     ctx.insert("iv", &search_params.v);
     ctx.insert("key", &search_params.k);
+    if let Some(downloads_left) = downloads_left {
+        ctx.insert("downloads_left", &downloads_left);
+    }
+
+    let policy = lifetime::policy();
+    if policy.extend_on_access {
+        ctx.insert("extend_by", &describe_duration(policy.extend_by));
+    }
 
-    let rendered: String = tmpl
-        .render("file.html", &ctx)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Template rendering error"))?;
-    Ok(HttpResponse::Ok().content_type("text/html").body(rendered))
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(render(&tmpl, "file.html", &ctx)?))
 }
@@ -0,0 +1,100 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::Serialize;
+
+use crate::{
+    error::AppError,
+    files::get_file,
+    metadata::{self, Metadata},
+    password,
+    routes::parse_uuid,
+    DbPool,
+};
+
+#[derive(Serialize)]
+struct PasswordInfo<'a> {
+    salt: &'a str,
+    key_mix: bool,
+}
+
+#[derive(Serialize)]
+struct FileInfoResponse<'a> {
+    success: bool,
+    /// Left out for password protected files, unless the request proves the
+    /// password.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_type: Option<&'a str>,
+    /// Name and type sealed by the uploader, in place of `file_name` and
+    /// `file_type` when `metadata_version` is 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a str>,
+    metadata_version: i16,
+    /// The file was padded to hide its size, clients strip it after
+    /// decrypting.
+    padded: bool,
+    available_till: i64,
+    downloads_left: Option<i32>,
+    /// What a client needs to derive the proof of the password, `null` when
+    /// the file has none.
+    password: Option<PasswordInfo<'a>>,
+}
+
+/// What a client needs to know to download a file. Counts as an access, like
+/// opening the file's page.
+#[get("/api/file/{file_uuid}")]
+pub async fn file_info(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, AppError> {
+    let file_uuid = parse_uuid(path)?;
+
+    let mut conn = pool.get().await?;
+    let file = get_file(&mut conn, file_uuid).await?;
+
+    // Without a proof only what's needed to derive one is told
+    let proof = password::proof(&req);
+    let revealed = match (&file.password_salt, proof) {
+        (Some(_), None) => false,
+        _ => {
+            password::check(&mut conn, &file, proof).await?;
+            true
+        }
+    };
+
+    let metadata = Metadata::of(&file);
+    let (file_name, file_type, sealed) = match (&metadata, revealed) {
+        (_, false) => (None, None, None),
+        (
+            Metadata::Plain {
+                file_name,
+                file_type,
+            },
+            true,
+        ) => (Some(file_name.as_str()), Some(file_type.as_str()), None),
+        (Metadata::Sealed(sealed), true) => (None, None, Some(sealed.as_str())),
+    };
+
+    let downloads_left = file
+        .max_downloads
+        .map(|max_downloads| (max_downloads - file.download_count).max(0));
+
+    Ok(HttpResponse::Ok().json(FileInfoResponse {
+        success: true,
+        file_name,
+        file_type,
+        metadata: sealed,
+        metadata_version: match metadata {
+            Metadata::Plain { .. } => metadata::LEGACY_VERSION,
+            Metadata::Sealed(_) => metadata::SEALED_VERSION,
+        },
+        padded: file.padded,
+        available_till: file.available_till.and_utc().timestamp(),
+        downloads_left,
+        password: file.password_salt.as_deref().map(|salt| PasswordInfo {
+            salt,
+            key_mix: file.password_key_mix,
+        }),
+    }))
+}
@@ -0,0 +1,9 @@
+use actix_web::{get, HttpResponse};
+
+/// Integrity counters in the Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(crate::metrics::render())
+}
@@ -1,7 +1,22 @@
+use actix_web::{
+    http::header::AUTHORIZATION, web, Error, HttpRequest, HttpResponse, ResponseError,
+};
 use serde::Serialize;
+use tera::{Context, Tera};
+use uuid::Uuid;
 
+use crate::error::AppError;
+
+pub mod bundle_html;
+pub mod create_bundle;
+pub mod delete_bundle;
+pub mod delete_file;
 pub mod download_file;
 pub mod file_html;
+pub mod file_info;
+pub mod metrics;
+pub mod policy;
+pub mod tus;
 pub mod upload;
 
 #[derive(Serialize)]
@@ -14,4 +29,43 @@ struct HttpApiResponse {
 struct HttpFileUploadApiResponse {
     success: bool,
     uuid: String,
+    /// Lets the uploader delete the file with `DELETE /api/file/{uuid}`, or
+    /// the bundle with `DELETE /api/bundle/{uuid}`
+    delete_token: String,
+}
+
+fn parse_uuid(path: web::Path<(String,)>) -> Result<Uuid, AppError> {
+    uuid(path.into_inner().0.as_str())
+}
+
+fn uuid(value: &str) -> Result<Uuid, AppError> {
+    Uuid::try_parse(value).map_err(|_| AppError::BadRequest("Invalid UUID".to_string()))
+}
+
+/// The delete token sent as `Authorization: Bearer <token>`.
+fn delete_token(req: &HttpRequest) -> Result<&str, AppError> {
+    Ok(req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AppError::Unauthorized("Missing delete token"))?
+        .trim())
+}
+
+fn render(tmpl: &Tera, template: &str, ctx: &Context) -> Result<String, Error> {
+    tmpl.render(template, ctx)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Template rendering error"))
+}
+
+/// The page `template` showing the error `e`, with its status code.
+fn render_error(tmpl: &Tera, template: &str, e: AppError) -> Result<HttpResponse, Error> {
+    let mut ctx = Context::new();
+    ctx.insert("success", &false);
+    ctx.insert("code", e.code());
+    ctx.insert("msg", &e.to_string());
+
+    Ok(HttpResponse::build(e.status_code())
+        .content_type("text/html")
+        .body(render(tmpl, template, &ctx)?))
 }
@@ -0,0 +1,22 @@
+use actix_web::{get, HttpResponse};
+use serde::Serialize;
+
+use crate::{config, lifetime};
+
+#[derive(Serialize)]
+struct PolicyResponse {
+    #[serde(flatten)]
+    lifetime: &'static lifetime::Policy,
+    max_upload_size: u64,
+}
+
+/// The lifetime policy uploads are checked against, in seconds, and the upload
+/// size limit in bytes, so clients can offer the same choices the server
+/// accepts.
+#[get("/api/policy")]
+pub async fn policy() -> HttpResponse {
+    HttpResponse::Ok().json(PolicyResponse {
+        lifetime: lifetime::policy(),
+        max_upload_size: config::config().limits.max_upload_size,
+    })
+}
@@ -0,0 +1,779 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use actix_web::{
+    delete,
+    http::{
+        header::{HeaderName, HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    },
+    patch, post, route, web, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError,
+};
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use diesel_async::AsyncPgConnection;
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use uuid::Uuid;
+
+use crate::{
+    checksum::Sha256,
+    config,
+    crypt::{Encrypted, StreamEncryptor},
+    database::{
+        actions,
+        models::{NewTusUpload, TusProgress, TusUpload},
+        DbError,
+    },
+    error::AppError,
+    files::{abandon_upload, create_file, storage_unavailable, FileUpload},
+    keyring, lifetime,
+    metadata::{self, Metadata},
+    padding, password,
+    password::Password,
+    placement,
+    routes::upload::{parse_max_downloads, parse_sha256, too_large, PART_SIZE},
+    storage::{self, StorageBackend, StorageError, UploadedPart},
+    DbConn, DbPool,
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+lazy_static! {
+    /// Uploads a PATCH request is currently writing to.
+    static ref ACTIVE_UPLOADS: Mutex<HashSet<Uuid>> = Mutex::new(HashSet::new());
+}
+
+/// Keeps other requests from writing to an upload while it's held.
+struct UploadLock(Uuid);
+
+impl UploadLock {
+    fn acquire(file_uuid: Uuid) -> Option<Self> {
+        match ACTIVE_UPLOADS.lock().unwrap().insert(file_uuid) {
+            true => Some(UploadLock(file_uuid)),
+            false => None,
+        }
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        ACTIVE_UPLOADS.lock().unwrap().remove(&self.0);
+    }
+}
+
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Tus-Resumable", TUS_VERSION));
+    response
+}
+
+/// Errors are sent as usual, but every tus response has to say which version
+/// of the protocol it speaks.
+fn tus_error(e: AppError) -> HttpResponse {
+    let mut response = e.error_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static("tus-resumable"),
+        HeaderValue::from_static(TUS_VERSION),
+    );
+
+    if matches!(e, AppError::PreconditionFailed(_)) {
+        headers.insert(
+            HeaderName::from_static("tus-version"),
+            HeaderValue::from_static(TUS_VERSION),
+        );
+    }
+
+    response
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Every request but OPTIONS has to say which version of the protocol it
+/// speaks.
+fn check_version(req: &HttpRequest) -> Result<(), AppError> {
+    match header(req, "Tus-Resumable") {
+        Some(TUS_VERSION) => Ok(()),
+        _ => Err(AppError::PreconditionFailed("Unsupported tus version")),
+    }
+}
+
+fn parse_uuid(path: web::Path<(String,)>) -> Result<Uuid, AppError> {
+    Uuid::try_parse(path.into_inner().0.as_str()).map_err(|_| AppError::NotFound("upload"))
+}
+
+/// `Upload-Metadata` is a comma separated list of keys, each followed by a
+/// space and its base64 encoded value.
+fn parse_metadata(metadata: &str) -> Option<Vec<(&str, String)>> {
+    metadata
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once(' ') {
+            Some((key, value)) => {
+                let value = general_purpose::STANDARD.decode(value.trim()).ok()?;
+                Some((key, String::from_utf8(value).ok()?))
+            }
+            None => Some((pair, String::new())),
+        })
+        .collect()
+}
+
+#[route("/api/tus", method = "OPTIONS")]
+pub async fn tus_options() -> HttpResponse {
+    tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", "creation,termination"))
+        .insert_header((
+            "Tus-Max-Size",
+            config::config().limits.max_upload_size.to_string(),
+        ))
+        .finish()
+}
+
+/// Starts a resumable upload. The file's name and type, lifetime, download
+/// limit, checksum, password and padding are passed in `Upload-Metadata` with
+/// the same values `/api/upload` takes as form fields.
+#[post("/api/tus")]
+pub async fn tus_create(req: HttpRequest, pool: web::Data<DbPool>) -> HttpResponse {
+    create(req, pool).await.unwrap_or_else(tus_error)
+}
+
+async fn create(req: HttpRequest, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    check_version(&req)?;
+
+    let upload_length = match header(&req, "Upload-Length").map(str::parse::<u64>) {
+        Some(Ok(upload_length)) => upload_length,
+        _ => return Err(AppError::BadRequest("Missing Upload-Length".to_string())),
+    };
+
+    if upload_length > config::config().limits.max_upload_size {
+        return Err(too_large());
+    }
+
+    let metadata = parse_metadata(header(&req, "Upload-Metadata").unwrap_or_default())
+        .ok_or_else(|| AppError::BadRequest("Invalid Upload-Metadata".to_string()))?;
+    let field = |name: &str| {
+        metadata
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let metadata = metadata::parse(
+        field("file_name").map(str::to_string),
+        field("file_type").map(str::to_string),
+        field("metadata").map(str::to_string),
+    )?;
+    let (file_name, file_type, sealed_metadata, _) = metadata.columns();
+
+    let lifetime = lifetime::policy()
+        .lifetime(field("lifetime"))
+        .map_err(AppError::BadRequest)?;
+    let max_downloads = parse_max_downloads(field("max_downloads").unwrap_or_default())?;
+    let client_sha256 = parse_sha256(field("sha256").unwrap_or_default())?;
+    let password = password::parse(
+        field("password_salt").unwrap_or_default(),
+        field("password_verifier").unwrap_or_default(),
+        field("password_key_mix").unwrap_or_default(),
+    )?;
+    // Checked up front, the length is known before any data arrives
+    let padded = padding::parse(field("padded").unwrap_or_default())?;
+    padding::check(padded, upload_length)?;
+
+    let mut conn = pool.get().await?;
+
+    let storage = storage::get_storage(&mut conn)
+        .await
+        .ok_or_else(|| AppError::StorageUnavailable("No writable bucket".to_string()))?;
+
+    let file_uuid = Uuid::new_v4();
+    let path = file_uuid.to_string();
+
+    let storage_upload_id = match storage.backend.start_upload(&path).await {
+        Ok(storage_upload_id) => storage_upload_id,
+        Err(e) => {
+            placement::mark_unhealthy(storage.id);
+            return Err(e.into());
+        }
+    };
+
+    let mut encryptor = StreamEncryptor::new();
+    let header = encryptor.update(&[]);
+    let mut stored_sha256 = Sha256::new();
+    stored_sha256.update(&header);
+
+    let (key, kek_id) = match keyring::wrap_key(encryptor.key()) {
+        Some(wrapped) => wrapped,
+        None => {
+            let _ = storage
+                .backend
+                .abort_upload(&path, &storage_upload_id)
+                .await;
+            return Err(AppError::Internal("Couldn't wrap file key".to_string()));
+        }
+    };
+
+    let new_upload = NewTusUpload {
+        file: &file_uuid,
+        s3_bucket_id: storage.id,
+        storage_upload_id: &storage_upload_id,
+        upload_length: upload_length as i64,
+        file_name,
+        file_type,
+        lifetime,
+        max_downloads,
+        key: &key,
+        kek_id,
+        nonce: encryptor.nonce(),
+        pending_ciphertext: &header,
+        stored_size: header.len() as i64,
+        stored_sha256_state: &stored_sha256.save(),
+        received_sha256_state: &Sha256::new().save(),
+        client_sha256: client_sha256.as_deref(),
+        password_salt: password.as_ref().map(|password| password.salt.as_str()),
+        password_verifier: password.as_ref().map(|password| password.verifier.as_str()),
+        password_key_mix: password.as_ref().is_some_and(|password| password.key_mix),
+        metadata: sealed_metadata,
+        padded,
+    };
+
+    if let Err(e) = actions::add_tus_upload(&mut conn, new_upload).await {
+        let _ = storage
+            .backend
+            .abort_upload(&path, &storage_upload_id)
+            .await;
+        return Err(e.into());
+    }
+
+    let mut response = tus_response(StatusCode::CREATED);
+    response
+        .insert_header(("Location", format!("/api/tus/{}", file_uuid)))
+        .insert_header(("Upload-Offset", "0"));
+
+    // Nothing will ever be sent for an empty file, so it's done right away
+    if upload_length == 0 {
+        let upload = actions::get_tus_upload(&mut conn, file_uuid)
+            .await?
+            .ok_or(AppError::NotFound("upload"))?;
+        let progress = Progress::resume(upload)?;
+
+        let delete_token =
+            complete(&mut conn, storage.id, storage.backend.as_ref(), progress).await?;
+        response.insert_header(("Delete-Token", delete_token));
+    }
+
+    Ok(response.finish())
+}
+
+#[route("/api/tus/{file_uuid}", method = "HEAD")]
+pub async fn tus_status(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> HttpResponse {
+    status(req, pool, path).await.unwrap_or_else(tus_error)
+}
+
+async fn status(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, AppError> {
+    check_version(&req)?;
+    let file_uuid = parse_uuid(path)?;
+
+    let (_, upload) = get_upload(&pool, file_uuid).await?;
+
+    Ok(tus_response(StatusCode::OK)
+        .insert_header(("Upload-Offset", upload.upload_offset.to_string()))
+        .insert_header(("Upload-Length", upload.upload_length.to_string()))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish())
+}
+
+/// Appends to an upload. Whatever arrives is kept even if the connection
+/// drops halfway, so the client can continue from the offset `HEAD` reports.
+/// The request that brings the upload to its full length turns it into a
+/// file, and returns the file's delete token in `Delete-Token`.
+#[patch("/api/tus/{file_uuid}")]
+pub async fn tus_patch(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+    payload: web::Payload,
+) -> HttpResponse {
+    append(req, pool, path, payload)
+        .await
+        .unwrap_or_else(tus_error)
+}
+
+async fn append(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, AppError> {
+    check_version(&req)?;
+    let file_uuid = parse_uuid(path)?;
+
+    if header(&req, CONTENT_TYPE.as_str()) != Some(TUS_CONTENT_TYPE) {
+        return Err(AppError::UnsupportedMediaType(
+            "Content-Type should be application/offset+octet-stream",
+        ));
+    }
+
+    let offset = match header(&req, "Upload-Offset").map(str::parse::<i64>) {
+        Some(Ok(offset)) => offset,
+        _ => return Err(AppError::BadRequest("Missing Upload-Offset".to_string())),
+    };
+
+    let _lock = UploadLock::acquire(file_uuid).ok_or(AppError::Locked)?;
+
+    let (mut conn, upload) = get_upload(&pool, file_uuid).await?;
+
+    if offset != upload.upload_offset {
+        return Err(AppError::Conflict("Upload-Offset doesn't match"));
+    }
+
+    let bucket_id = upload.s3_bucket_id;
+    let storage = storage::get_specific_storage(&mut conn, bucket_id)
+        .await
+        .ok_or_else(|| storage_unavailable(bucket_id))?;
+
+    let mut progress = Progress::resume(upload)?;
+
+    let mut failure = None;
+    while let Some(chunk) = payload.next().await {
+        // The client went away, keep what made it here
+        let Ok(data) = chunk else { break };
+
+        if progress.offset + data.len() as i64 > progress.upload.upload_length {
+            failure = Some(AppError::BadRequest(
+                "Upload is longer than Upload-Length".to_string(),
+            ));
+            break;
+        }
+
+        progress.write(&data);
+        if progress.pending.len() < PART_SIZE {
+            continue;
+        }
+
+        // Saved before the part leaves the server. Otherwise a retry of this
+        // offset after a crash could seal different data under the same
+        // segment nonces.
+        if !progress.save(&mut conn).await? {
+            return Err(AppError::Conflict("Upload was changed by another request"));
+        }
+
+        if let Err(e) = progress.put_part(storage.as_ref()).await {
+            placement::mark_unhealthy(bucket_id);
+            failure = Some(e.into());
+            break;
+        }
+    }
+
+    if !progress.save(&mut conn).await? {
+        return Err(AppError::Conflict("Upload was changed by another request"));
+    }
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    let mut response = tus_response(StatusCode::NO_CONTENT);
+    response.insert_header(("Upload-Offset", progress.offset.to_string()));
+
+    if progress.offset == progress.upload.upload_length {
+        let delete_token = complete(&mut conn, bucket_id, storage.as_ref(), progress).await?;
+        response.insert_header(("Delete-Token", delete_token));
+    }
+
+    Ok(response.finish())
+}
+
+#[delete("/api/tus/{file_uuid}")]
+pub async fn tus_terminate(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> HttpResponse {
+    terminate(req, pool, path).await.unwrap_or_else(tus_error)
+}
+
+async fn terminate(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, AppError> {
+    check_version(&req)?;
+    let file_uuid = parse_uuid(path)?;
+
+    let _lock = UploadLock::acquire(file_uuid).ok_or(AppError::Locked)?;
+
+    let (mut conn, upload) = get_upload(&pool, file_uuid).await?;
+    abandon_upload(&mut conn, &upload).await?;
+
+    Ok(tus_response(StatusCode::NO_CONTENT).finish())
+}
+
+async fn get_upload(pool: &DbPool, file_uuid: Uuid) -> Result<(DbConn, TusUpload), AppError> {
+    let mut conn = pool.get().await?;
+
+    match actions::get_tus_upload(&mut conn, file_uuid).await? {
+        Some(upload) => Ok((conn, upload)),
+        None => Err(AppError::NotFound("upload")),
+    }
+}
+
+/// Seals the last segment, completes the storage upload and stores the file.
+/// Returns the file's delete token.
+async fn complete(
+    conn: &mut AsyncPgConnection,
+    bucket_id: i32,
+    storage: &dyn StorageBackend,
+    progress: Progress,
+) -> Result<String, AppError> {
+    let Finished {
+        upload,
+        encrypted,
+        stored_size,
+        stored_sha256,
+        received_sha256,
+    } = match progress.finish(storage).await {
+        Ok(finished) => finished,
+        Err(e) => {
+            placement::mark_unhealthy(bucket_id);
+            return Err(e.into());
+        }
+    };
+
+    placement::mark_healthy(bucket_id);
+
+    let checksum_matches = match (&upload.client_sha256, &received_sha256) {
+        (Some(client_sha256), Some(received_sha256)) => client_sha256 == received_sha256,
+        _ => true,
+    };
+
+    let file_upload = FileUpload {
+        unique_id: upload.file,
+        metadata: match upload.metadata {
+            Some(sealed) => Metadata::Sealed(sealed),
+            None => Metadata::Plain {
+                file_name: upload.file_name,
+                file_type: upload.file_type,
+            },
+        },
+        lifetime: upload.lifetime,
+        s3_bucket_id: upload.s3_bucket_id,
+        size: stored_size,
+        max_downloads: upload.max_downloads,
+        stored_sha256,
+        client_sha256: upload.client_sha256,
+        password: match (upload.password_salt, upload.password_verifier) {
+            (Some(salt), Some(verifier)) => Some(Password {
+                salt,
+                verifier,
+                key_mix: upload.password_key_mix,
+            }),
+            _ => None,
+        },
+        padded: upload.padded,
+    };
+
+    let result = match checksum_matches {
+        true => create_file(conn, encrypted, file_upload).await,
+        false => Err(AppError::ChecksumMismatch),
+    };
+    let _ = actions::delete_tus_upload(conn, upload.file).await;
+
+    if result.is_err() {
+        let _ = storage.delete(&upload.file.to_string()).await;
+    }
+
+    result
+}
+
+/// An upload picked up from the state saved by the previous request.
+struct Progress {
+    upload: TusUpload,
+    encryptor: StreamEncryptor,
+    pending: Vec<u8>,
+    part_etags: Vec<String>,
+    offset: i64,
+    stored_size: i64,
+    /// `None` for uploads started before digests were kept.
+    stored_sha256: Option<Sha256>,
+    received_sha256: Option<Sha256>,
+}
+
+/// A completed upload, with the digests of what was stored and received.
+struct Finished {
+    upload: TusUpload,
+    encrypted: Encrypted,
+    stored_size: i64,
+    stored_sha256: Option<String>,
+    received_sha256: Option<String>,
+}
+
+impl Progress {
+    fn resume(upload: TusUpload) -> Result<Self, AppError> {
+        let key = keyring::unwrap_key(&upload.key, upload.kek_id).ok_or_else(|| {
+            AppError::DecryptFailed(format!("Couldn't unwrap key of upload {}", upload.file))
+        })?;
+        Self::restore(upload, &key)
+    }
+
+    /// Like `resume`, with the key already unwrapped.
+    fn restore(mut upload: TusUpload, key: &str) -> Result<Self, AppError> {
+        let encryptor = StreamEncryptor::resume(
+            key,
+            &upload.nonce,
+            upload.segment_index as u32,
+            std::mem::take(&mut upload.pending_plaintext),
+        )
+        .ok_or_else(|| AppError::Internal(format!("Couldn't resume upload {}", upload.file)))?;
+
+        Ok(Progress {
+            encryptor,
+            pending: std::mem::take(&mut upload.pending_ciphertext),
+            part_etags: std::mem::take(&mut upload.part_etags),
+            offset: upload.upload_offset,
+            stored_size: upload.stored_size,
+            stored_sha256: Sha256::resume(&upload.stored_sha256_state),
+            received_sha256: Sha256::resume(&upload.received_sha256_state),
+            upload,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let sealed = self.encryptor.update(data);
+        if let Some(received_sha256) = &mut self.received_sha256 {
+            received_sha256.update(data);
+        }
+        if let Some(stored_sha256) = &mut self.stored_sha256 {
+            stored_sha256.update(&sealed);
+        }
+        self.stored_size += sealed.len() as i64;
+        self.pending.extend_from_slice(&sealed);
+        self.offset += data.len() as i64;
+    }
+
+    async fn put_part(&mut self, storage: &dyn StorageBackend) -> Result<(), StorageError> {
+        let part_number = self.part_etags.len() as u32 + 1;
+        let part = put_part(storage, &self.upload, part_number, self.pending.clone()).await?;
+
+        self.part_etags.push(part.etag);
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Stores how far the upload got. `false` when another request got there
+    /// first.
+    async fn save(&mut self, conn: &mut AsyncPgConnection) -> Result<bool, DbError> {
+        let (segment_index, pending_plaintext) = self.encryptor.state();
+        let stored_sha256_state = self.stored_sha256.as_ref().map(Sha256::save);
+        let received_sha256_state = self.received_sha256.as_ref().map(Sha256::save);
+
+        let saved = actions::set_tus_progress(
+            conn,
+            self.upload.file,
+            self.upload.upload_offset,
+            TusProgress {
+                upload_offset: self.offset,
+                segment_index: segment_index as i32,
+                pending_plaintext,
+                pending_ciphertext: &self.pending,
+                part_etags: &self.part_etags,
+                stored_size: self.stored_size,
+                date_updated: Utc::now().naive_utc(),
+                stored_sha256_state: stored_sha256_state.as_deref().unwrap_or_default(),
+                received_sha256_state: received_sha256_state.as_deref().unwrap_or_default(),
+            },
+        )
+        .await?;
+
+        if saved {
+            self.upload.upload_offset = self.offset;
+        }
+
+        Ok(saved)
+    }
+
+    async fn finish(self, storage: &dyn StorageBackend) -> Result<Finished, StorageError> {
+        let Progress {
+            upload,
+            encryptor,
+            mut pending,
+            part_etags,
+            stored_size,
+            mut stored_sha256,
+            received_sha256,
+            ..
+        } = self;
+
+        let encrypted = encryptor.finalize();
+        pending.extend_from_slice(&encrypted.result);
+        if let Some(stored_sha256) = &mut stored_sha256 {
+            stored_sha256.update(&encrypted.result);
+        }
+
+        let mut parts: Vec<UploadedPart> = part_etags
+            .into_iter()
+            .enumerate()
+            .map(|(index, etag)| UploadedPart {
+                part_number: index as u32 + 1,
+                etag,
+            })
+            .collect();
+        parts.push(put_part(storage, &upload, parts.len() as u32 + 1, pending).await?);
+
+        storage
+            .complete_upload(&upload.file.to_string(), &upload.storage_upload_id, parts)
+            .await?;
+
+        Ok(Finished {
+            upload,
+            stored_size: stored_size + encrypted.result.len() as i64,
+            encrypted: Encrypted {
+                result: Vec::new(),
+                ..encrypted
+            },
+            stored_sha256: stored_sha256.map(Sha256::finalize),
+            received_sha256: received_sha256.map(Sha256::finalize),
+        })
+    }
+}
+
+async fn put_part(
+    storage: &dyn StorageBackend,
+    upload: &TusUpload,
+    part_number: u32,
+    chunk: Vec<u8>,
+) -> Result<UploadedPart, StorageError> {
+    storage
+        .upload_part(
+            &upload.file.to_string(),
+            &upload.storage_upload_id,
+            part_number,
+            chunk,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Digest;
+
+    use crate::{
+        crypt::{StreamDecryptor, HEADER},
+        storage::MemoryStorage,
+    };
+
+    use super::*;
+
+    /// What `save` would leave in the database for `progress`.
+    fn saved(progress: &Progress) -> TusUpload {
+        let (segment_index, pending_plaintext) = progress.encryptor.state();
+
+        TusUpload {
+            upload_offset: progress.offset,
+            segment_index: segment_index as i32,
+            pending_plaintext: pending_plaintext.to_vec(),
+            pending_ciphertext: progress.pending.clone(),
+            part_etags: progress.part_etags.clone(),
+            stored_size: progress.stored_size,
+            stored_sha256_state: progress.stored_sha256.as_ref().unwrap().save(),
+            received_sha256_state: progress.received_sha256.as_ref().unwrap().save(),
+            ..progress.upload.clone()
+        }
+    }
+
+    #[actix_web::test]
+    async fn resumed_upload_stores_what_was_sent() {
+        let storage = MemoryStorage::default();
+        let plaintext: Vec<u8> = (0..200000).map(|i| (i % 251) as u8).collect();
+        let file = Uuid::new_v4();
+        let storage_upload_id = storage.start_upload(&file.to_string()).await.unwrap();
+
+        // As `create` stores it
+        let mut encryptor = StreamEncryptor::new();
+        let header = encryptor.update(&[]);
+        let mut stored_sha256 = Sha256::new();
+        stored_sha256.update(&header);
+        let now = Utc::now().naive_utc();
+        let mut upload = TusUpload {
+            id: 1,
+            file,
+            s3_bucket_id: 1,
+            storage_upload_id,
+            upload_length: plaintext.len() as i64,
+            upload_offset: 0,
+            file_name: String::new(),
+            file_type: String::new(),
+            lifetime: 3600,
+            max_downloads: None,
+            key: String::new(),
+            kek_id: 1,
+            nonce: encryptor.nonce().to_string(),
+            segment_index: 0,
+            pending_plaintext: Vec::new(),
+            pending_ciphertext: header.clone(),
+            part_etags: Vec::new(),
+            stored_size: header.len() as i64,
+            date_created: now,
+            date_updated: now,
+            stored_sha256_state: stored_sha256.save(),
+            received_sha256_state: Sha256::new().save(),
+            client_sha256: None,
+            password_salt: None,
+            password_verifier: None,
+            password_key_mix: false,
+            metadata: None,
+            padded: false,
+        };
+
+        // Three PATCH requests, the second one sending a part
+        let mut sent = 0;
+        for (request, end) in [70001, 70002, plaintext.len()].into_iter().enumerate() {
+            let mut progress = Progress::restore(upload, encryptor.key()).unwrap();
+            assert_eq!(progress.offset, sent as i64);
+
+            let data = &plaintext[sent..end];
+            sent = end;
+
+            for chunk in data.chunks(30000) {
+                progress.write(chunk);
+            }
+            if request == 1 {
+                progress.put_part(&storage).await.unwrap();
+            }
+            upload = saved(&progress);
+        }
+
+        let progress = Progress::restore(upload, encryptor.key()).unwrap();
+        assert_eq!(progress.offset, plaintext.len() as i64);
+        let finished = progress.finish(&storage).await.unwrap();
+
+        let object = storage.get(&file.to_string()).await.unwrap();
+        assert_eq!(finished.stored_size, object.len() as i64);
+        assert_eq!(
+            finished.stored_sha256.unwrap(),
+            hex::encode(sha2::Sha256::digest(&object))
+        );
+        assert_eq!(
+            finished.received_sha256.unwrap(),
+            hex::encode(sha2::Sha256::digest(&plaintext))
+        );
+        assert!(object.starts_with(&HEADER));
+
+        let decryptor = StreamDecryptor::new(encryptor.key(), encryptor.nonce()).unwrap();
+        assert_eq!(decryptor.decrypt_all(&object).unwrap(), plaintext);
+    }
+}
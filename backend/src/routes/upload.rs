@@ -1,47 +1,178 @@
-use actix_multipart::Multipart;
-use actix_web::{post, web, Error, HttpResponse};
+use actix_multipart::{Field, Multipart};
+use actix_web::{post, web, HttpResponse};
 use futures_util::StreamExt;
 use uuid::Uuid;
 
 use crate::{
-    crypt::encrypt,
-    files::create_file,
-    routes::{HttpApiResponse, HttpFileUploadApiResponse},
-    s3, DbPool,
+    checksum::{self, Sha256},
+    config,
+    crypt::{Encrypted, StreamEncryptor},
+    error::AppError,
+    files::{create_file, FileUpload},
+    lifetime, metadata, padding, password, placement,
+    routes::HttpFileUploadApiResponse,
+    storage::{self, StorageBackend, UploadedPart},
+    DbPool,
 };
 
-const MAX_SIZE: usize = 1_073_741_824; // 1GB in bytes
+pub const PART_SIZE: usize = 8 * 1024 * 1024; // S3 wants at least 5MB for every part but the last
 
-#[post("/api/upload")]
-async fn upload(pool: web::Data<DbPool>, mut payload: Multipart) -> Result<HttpResponse, Error> {
-    let mut file_name = None;
-    let mut file_type = None;
-    let mut unique_id = None;
-    let mut encrypted_file = None;
-    let mut lifetime: Option<i64> = None;
-
-    let mut conn = match pool.get().await {
-        Ok(conn) => conn,
-        _ => {
-            return Ok(HttpResponse::InternalServerError().json(HttpApiResponse {
-                success: false,
-                message: "Internal error, please try again later".to_string(),
-            }))
+/// Error for files over the configured upload limit.
+pub fn too_large() -> AppError {
+    let max_size = config::config().limits.max_upload_size;
+    let size = [("GB", 30), ("MB", 20), ("KB", 10)]
+        .into_iter()
+        .find(|(_, shift)| max_size.trailing_zeros() >= *shift)
+        .map(|(name, shift)| format!("{}{}", max_size >> shift, name))
+        .unwrap_or_else(|| format!("{} bytes", max_size));
+
+    AppError::PayloadTooLarge(format!("File size exceeds {}", size))
+}
+
+/// The download limit picked on upload, empty meaning no limit.
+pub fn parse_max_downloads(max_downloads: &str) -> Result<Option<i32>, AppError> {
+    match max_downloads {
+        "" => Ok(None),
+        limit => match limit.parse::<i32>() {
+            Ok(limit) if limit > 0 => Ok(Some(limit)),
+            _ => Err(AppError::BadRequest("Invalid download limit".to_string())),
+        },
+    }
+}
+
+/// The checksum a client sent along, a hex encoded SHA-256 of the (client side
+/// encrypted) file. Empty when it didn't send one.
+pub fn parse_sha256(sha256: &str) -> Result<Option<String>, AppError> {
+    let sha256 = sha256.to_ascii_lowercase();
+    match sha256.as_str() {
+        "" => Ok(None),
+        sha256 if checksum::is_sha256(sha256) => Ok(Some(sha256.to_string())),
+        _ => Err(AppError::BadRequest("Invalid checksum".to_string())),
+    }
+}
+
+/// What ended up in storage, with digests of the stored object and of the
+/// file as it was received.
+struct Stored {
+    encrypted: Encrypted,
+    size: u64,
+    received_size: u64,
+    stored_sha256: String,
+    received_sha256: String,
+}
+
+/// Encrypts the file field while it is being received and pushes it to
+/// storage as a multipart upload, so only one part is ever held in memory.
+async fn store_encrypted(
+    storage: &dyn StorageBackend,
+    path: &str,
+    field: &mut Field,
+) -> Result<Stored, AppError> {
+    let upload_id = storage.start_upload(path).await?;
+
+    let result = write_parts(storage, path, &upload_id, field).await;
+    if result.is_err() {
+        let _ = storage.abort_upload(path, &upload_id).await;
+    }
+
+    result
+}
+
+async fn write_parts(
+    storage: &dyn StorageBackend,
+    path: &str,
+    upload_id: &str,
+    field: &mut Field,
+) -> Result<Stored, AppError> {
+    let mut encryptor = StreamEncryptor::new();
+    let mut stored_sha256 = Sha256::new();
+    let mut received_sha256 = Sha256::new();
+    let mut parts = Vec::new();
+    let mut buffer = Vec::with_capacity(PART_SIZE);
+    let mut total_size: u64 = 0;
+    let mut stored_size: u64 = 0;
+
+    while let Some(chunk) = field.next().await {
+        let data = chunk?;
+        total_size += data.len() as u64;
+
+        if total_size > config::config().limits.max_upload_size {
+            return Err(too_large());
         }
-    };
 
-    let bucket = match s3::get_s3_bucket_info(&mut *conn).await {
-        Some(bucket) => bucket,
-        None => {
-            return Ok(HttpResponse::ServiceUnavailable().json(HttpApiResponse {
-                success: false,
-                message: "Couldn't receive storage".to_string(),
-            }))
+        received_sha256.update(&data);
+        let sealed = encryptor.update(&data);
+        stored_sha256.update(&sealed);
+        buffer.extend_from_slice(&sealed);
+
+        if buffer.len() >= PART_SIZE {
+            stored_size += buffer.len() as u64;
+            let chunk = std::mem::replace(&mut buffer, Vec::with_capacity(PART_SIZE));
+            parts.push(put_part(storage, path, upload_id, parts.len() as u32 + 1, chunk).await?);
         }
-    };
+    }
+
+    let encrypted = encryptor.finalize();
+    stored_sha256.update(&encrypted.result);
+    buffer.extend_from_slice(&encrypted.result);
+    stored_size += buffer.len() as u64;
+    parts.push(put_part(storage, path, upload_id, parts.len() as u32 + 1, buffer).await?);
+
+    storage.complete_upload(path, upload_id, parts).await?;
+
+    Ok(Stored {
+        encrypted: Encrypted {
+            result: Vec::new(),
+            ..encrypted
+        },
+        size: stored_size,
+        received_size: total_size,
+        stored_sha256: stored_sha256.finalize(),
+        received_sha256: received_sha256.finalize(),
+    })
+}
+
+async fn put_part(
+    storage: &dyn StorageBackend,
+    path: &str,
+    upload_id: &str,
+    part_number: u32,
+    chunk: Vec<u8>,
+) -> Result<UploadedPart, AppError> {
+    Ok(storage
+        .upload_part(path, upload_id, part_number, chunk)
+        .await?)
+}
+
+async fn read_text(field: &mut Field) -> Result<String, AppError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        value.extend_from_slice(&chunk?);
+    }
+
+    Ok(String::from_utf8(value).unwrap_or_default())
+}
 
-    let bucket_id = bucket.id;
-    let bucket = bucket.bucket;
+/// Reads the form, storing the file as soon as it arrives. `stored` is set
+/// once there is an object in storage, so it can be removed if the upload
+/// fails after that.
+async fn receive_upload(
+    storage: &dyn StorageBackend,
+    bucket_id: i32,
+    payload: &mut Multipart,
+    stored: &mut Option<Uuid>,
+) -> Result<(Encrypted, FileUpload), AppError> {
+    let mut file_name = None;
+    let mut file_type = None;
+    let mut sealed_metadata = None;
+    let mut stored_file = None;
+    let mut client_sha256 = None;
+    let mut password_salt = String::new();
+    let mut password_verifier = String::new();
+    let mut password_key_mix = String::new();
+    let mut padded = String::new();
+    let mut lifetime = lifetime::policy().default;
+    let mut max_downloads: Option<i32> = None;
 
     while let Some(item) = payload.next().await {
         let mut field = item?;
@@ -52,145 +183,114 @@ async fn upload(pool: web::Data<DbPool>, mut payload: Multipart) -> Result<HttpR
             .unwrap_or("");
 
         match name {
-            "file_name" => {
-                let mut value = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let data = chunk?;
-                    value.extend_from_slice(&data);
-                }
-                file_name = Some(String::from_utf8(value).unwrap_or_default());
-            }
-            "file_type" => {
-                let mut value = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let data = chunk?;
-                    value.extend_from_slice(&data);
-                }
-                file_type = Some(String::from_utf8(value).unwrap_or_default());
-            }
+            "file_name" => file_name = Some(read_text(&mut field).await?),
+            "file_type" => file_type = Some(read_text(&mut field).await?),
+            "metadata" => sealed_metadata = Some(read_text(&mut field).await?),
             "lifetime" => {
-                let mut value = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let data = chunk?;
-                    value.extend_from_slice(&data);
-                }
-
-                let lifetime_str = String::from_utf8(value).unwrap_or_default();
-                lifetime = match lifetime_str.as_str() {
-                    "1d" => Some(86400),
-                    "7d" => Some(86400 * 7),
-                    "28d" => Some(86400 * 28),
-                    _ => None,
-                };
+                let lifetime_str = read_text(&mut field).await?;
+                lifetime = lifetime::policy()
+                    .lifetime(Some(&lifetime_str))
+                    .map_err(AppError::BadRequest)?;
             }
-            "file" => {
-                let temp_unique_id = Uuid::new_v4();
-                let safe_file_name = Some(format!("{}", temp_unique_id));
-                let mut value = Vec::new();
-                let mut total_size: usize = 0;
-
-                while let Some(chunk) = field.next().await {
-                    let data = chunk?;
-                    total_size += data.len();
-
-                    if total_size > MAX_SIZE {
-                        return Ok(HttpResponse::PayloadTooLarge().json(HttpApiResponse {
-                            success: false,
-                            message: "File size exceeds 1GB".to_string(),
-                        }));
-                    }
-
-                    value.extend_from_slice(&data);
-                }
+            "max_downloads" => {
+                max_downloads = parse_max_downloads(&read_text(&mut field).await?)?;
+            }
+            "sha256" => client_sha256 = parse_sha256(&read_text(&mut field).await?)?,
+            "password_salt" => password_salt = read_text(&mut field).await?,
+            "password_verifier" => password_verifier = read_text(&mut field).await?,
+            "password_key_mix" => password_key_mix = read_text(&mut field).await?,
+            "padded" => padded = read_text(&mut field).await?,
+            "file" if stored.is_none() => {
+                let unique_id = Uuid::new_v4();
 
-                let temp_encrypted_file = match encrypt(value) {
-                    Some(bytes) => bytes,
-                    None => {
-                        return Ok(HttpResponse::ServiceUnavailable().json(HttpApiResponse {
-                            success: false,
-                            message: "Failed encrypting file".to_string(),
-                        }))
+                match store_encrypted(storage, &unique_id.to_string(), &mut field).await {
+                    Ok(file) => {
+                        placement::mark_healthy(bucket_id);
+                        stored_file = Some(file);
+                        *stored = Some(unique_id);
                     }
-                };
-
-                let bucket_result = bucket
-                    .put_object(safe_file_name.clone().unwrap(), &temp_encrypted_file.result)
-                    .await;
-                match bucket_result {
-                    Ok(result) => {
-                        if result.status_code() != 200 {
-                            return Ok(HttpResponse::InternalServerError().json(HttpApiResponse {
-                                success: false,
-                                message: "File upload errored".to_string(),
-                            }));
-                        }
-                    }
-                    Err(e) => {
-                        println!("{}", e.to_string());
-                        return Ok(HttpResponse::InternalServerError().json(HttpApiResponse {
-                            success: false,
-                            message: "File upload errored".to_string(),
-                        }));
+                    Err(e @ AppError::StorageUnavailable(_)) => {
+                        placement::mark_unhealthy(bucket_id);
+                        return Err(e);
                     }
+                    Err(e) => return Err(e),
                 }
-
-                encrypted_file = Some(temp_encrypted_file);
-                unique_id = Some(temp_unique_id);
-            }
-            _ => {
-                return Ok(HttpResponse::ExpectationFailed().json(HttpApiResponse {
-                    success: false,
-                    message: "Too many form fields".to_string(),
-                }));
             }
+            _ => return Err(AppError::BadRequest("Too many form fields".to_string())),
         }
     }
 
-    if unique_id.is_none() {
-        return Ok(HttpResponse::BadRequest().json(HttpApiResponse {
-            success: false,
-            message: "Missing form fields".to_string(),
-        }));
-    }
+    let (stored_file, unique_id) = match (stored_file, *stored) {
+        (Some(stored_file), Some(unique_id)) => (stored_file, unique_id),
+        _ => return Err(AppError::BadRequest("Missing form fields".to_string())),
+    };
+    let metadata = metadata::parse(file_name, file_type, sealed_metadata)?;
+    let padded = padding::parse(&padded)?;
+    padding::check(padded, stored_file.received_size)?;
 
-    if let (
-        Some(file_name),
-        Some(file_type),
-        Some(encrypted_file),
-        Some(unique_id),
-        Some(lifetime),
-    ) = (file_name, file_type, encrypted_file, unique_id, lifetime)
+    if client_sha256
+        .as_ref()
+        .is_some_and(|sha256| *sha256 != stored_file.received_sha256)
     {
-        let result = create_file(
-            &mut *conn,
-            encrypted_file,
+        return Err(AppError::ChecksumMismatch);
+    }
+
+    let password = password::parse(&password_salt, &password_verifier, &password_key_mix)?;
+
+    Ok((
+        stored_file.encrypted,
+        FileUpload {
             unique_id,
-            file_name,
-            file_type,
+            metadata,
             lifetime,
-            bucket_id,
-        )
-        .await;
-
-        if result.is_err() {
-            let _ = bucket.delete_object(format!("{}", unique_id)).await;
-            return Ok(HttpResponse::BadRequest().json(HttpApiResponse {
-                success: false,
-                message: "Failed saving file".to_string(),
-            }));
+            s3_bucket_id: bucket_id,
+            size: stored_file.size as i64,
+            max_downloads,
+            stored_sha256: Some(stored_file.stored_sha256),
+            client_sha256,
+            password,
+            padded,
+        },
+    ))
+}
+
+#[post("/api/upload")]
+async fn upload(pool: web::Data<DbPool>, mut payload: Multipart) -> Result<HttpResponse, AppError> {
+    let mut conn = pool.get().await?;
+
+    let storage = storage::get_storage(&mut conn)
+        .await
+        .ok_or_else(|| AppError::StorageUnavailable("No writable bucket".to_string()))?;
+
+    let bucket_id = storage.id;
+    let storage = storage.backend;
+
+    let mut stored = None;
+    let result = match receive_upload(storage.as_ref(), bucket_id, &mut payload, &mut stored).await
+    {
+        Ok((encrypted_file, upload)) => {
+            let unique_id = upload.unique_id;
+            create_file(&mut conn, encrypted_file, upload)
+                .await
+                .map(|delete_token| (unique_id, delete_token))
         }
+        Err(e) => Err(e),
+    };
 
-        return Ok(HttpResponse::Ok().json(HttpFileUploadApiResponse {
-            success: true,
-            uuid: unique_id.to_string(),
-        }));
-    } else {
-        let _ = bucket
-            .delete_object(format!("{}", unique_id.unwrap()))
-            .await;
-        return Ok(HttpResponse::BadRequest().json(HttpApiResponse {
-            success: false,
-            message: "Missing form fields".to_string(),
-        }));
-    }
+    let (unique_id, delete_token) = match result {
+        Ok(created) => created,
+        Err(e) => {
+            // Nothing points to the stored object anymore
+            if let Some(unique_id) = stored {
+                let _ = storage.delete(&unique_id.to_string()).await;
+            }
+            return Err(e);
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(HttpFileUploadApiResponse {
+        success: true,
+        uuid: unique_id.to_string(),
+        delete_token,
+    }))
 }
@@ -1,5 +1,17 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    bundles (id) {
+        id -> Int4,
+        bundle -> Uuid,
+        manifest -> Text,
+        available_till -> Timestamp,
+        date_created -> Timestamp,
+        #[max_length = 64]
+        delete_token_hash -> Varchar,
+    }
+}
+
 diesel::table! {
     files (id) {
         id -> Int4,
@@ -8,13 +20,45 @@ diesel::table! {
         file_name -> Varchar,
         #[max_length = 96]
         file_type -> Varchar,
-        #[max_length = 44]
+        #[max_length = 64]
         key -> Varchar,
         #[max_length = 16]
         nonce -> Varchar,
         available_till -> Timestamp,
         date_created -> Timestamp,
         s3_bucket_id -> Int4,
+        encryption_version -> Int2,
+        size -> Int8,
+        kek_id -> Nullable<Int4>,
+        max_downloads -> Nullable<Int4>,
+        download_count -> Int4,
+        #[max_length = 64]
+        delete_token_hash -> Nullable<Varchar>,
+        #[max_length = 64]
+        stored_sha256 -> Nullable<Varchar>,
+        #[max_length = 64]
+        client_sha256 -> Nullable<Varchar>,
+        date_verified -> Nullable<Timestamp>,
+        #[max_length = 24]
+        password_salt -> Nullable<Varchar>,
+        #[max_length = 64]
+        password_verifier -> Nullable<Varchar>,
+        password_key_mix -> Bool,
+        password_failures -> Int4,
+        password_locked_till -> Nullable<Timestamp>,
+        bundle_id -> Nullable<Int4>,
+        metadata -> Nullable<Text>,
+        metadata_version -> Int2,
+        padded -> Bool,
+        date_downloaded -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    job_runs (name) {
+        #[max_length = 32]
+        name -> Varchar,
+        last_success -> Timestamp,
     }
 }
 
@@ -31,12 +75,57 @@ diesel::table! {
         access_key -> Varchar,
         #[max_length = 1028]
         secret_key -> Varchar,
+        #[max_length = 16]
+        backend -> Varchar,
+        weight -> Int4,
+        #[max_length = 16]
+        status -> Varchar,
+    }
+}
+
+diesel::table! {
+    tus_uploads (id) {
+        id -> Int4,
+        file -> Uuid,
+        s3_bucket_id -> Int4,
+        #[max_length = 1024]
+        storage_upload_id -> Varchar,
+        upload_length -> Int8,
+        upload_offset -> Int8,
+        #[max_length = 96]
+        file_name -> Varchar,
+        #[max_length = 96]
+        file_type -> Varchar,
+        lifetime -> Int8,
+        max_downloads -> Nullable<Int4>,
+        #[max_length = 64]
+        key -> Varchar,
+        kek_id -> Int4,
+        #[max_length = 16]
+        nonce -> Varchar,
+        segment_index -> Int4,
+        pending_plaintext -> Bytea,
+        pending_ciphertext -> Bytea,
+        part_etags -> Array<Text>,
+        stored_size -> Int8,
+        date_created -> Timestamp,
+        date_updated -> Timestamp,
+        stored_sha256_state -> Bytea,
+        received_sha256_state -> Bytea,
+        #[max_length = 64]
+        client_sha256 -> Nullable<Varchar>,
+        #[max_length = 24]
+        password_salt -> Nullable<Varchar>,
+        #[max_length = 64]
+        password_verifier -> Nullable<Varchar>,
+        password_key_mix -> Bool,
+        metadata -> Nullable<Text>,
+        padded -> Bool,
     }
 }
 
+diesel::joinable!(files -> bundles (bundle_id));
 diesel::joinable!(files -> s3_buckets (s3_bucket_id));
+diesel::joinable!(tus_uploads -> s3_buckets (s3_bucket_id));
 
-diesel::allow_tables_to_appear_in_same_query!(
-    files,
-    s3_buckets,
-);
+diesel::allow_tables_to_appear_in_same_query!(bundles, files, job_runs, s3_buckets, tus_uploads,);
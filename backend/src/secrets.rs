@@ -0,0 +1,137 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, OsRng, Payload},
+    AeadCore, Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose, Engine};
+use lazy_static::lazy_static;
+
+/// Prefix of every sealed value. Anything without it is a credential that
+/// was stored before sealing existed.
+const SEALED_PREFIX: &str = "sealed-v1:";
+const NONCE_SIZE: usize = 12;
+
+lazy_static! {
+    static ref MASTER_KEY: Result<Option<MasterKey>, String> = MasterKey::from_env("MASTER_KEY");
+}
+
+/// Key that bucket credentials are sealed with before they're written to the
+/// database, so a database dump alone doesn't give access to storage.
+pub struct MasterKey {
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    /// Reads the base64 encoded key from `<var>`, or from the file named in
+    /// `<var>_FILE`. `None` when neither is set.
+    pub fn from_env(var: &str) -> Result<Option<Self>, String> {
+        // Compose passes unset variables through as empty strings
+        let env = |name: String| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let encoded = match (env(var.to_string()), env(format!("{}_FILE", var))) {
+            (Some(key), _) => key,
+            (_, Some(path)) => std::fs::read_to_string(&path)
+                .map_err(|e| format!("Couldn't read {}_FILE {}: {}", var, path, e))?,
+            _ => return Ok(None),
+        };
+
+        let key = general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| format!("{} should be base64", var))?;
+        let cipher =
+            Aes256Gcm::new_from_slice(&key).map_err(|_| format!("{} should be 32 bytes", var))?;
+
+        Ok(Some(MasterKey { cipher }))
+    }
+
+    /// `field` is bound to the result, so sealed values can't be swapped
+    /// between columns.
+    pub fn seal(&self, field: &str, value: &str) -> String {
+        if value.is_empty() {
+            return String::new();
+        }
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(
+            &self
+                .cipher
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: value.as_bytes(),
+                        aad: field.as_bytes(),
+                    },
+                )
+                .expect("Credentials are always small enough to seal"),
+        );
+
+        format!(
+            "{}{}",
+            SEALED_PREFIX,
+            general_purpose::STANDARD.encode(sealed)
+        )
+    }
+
+    pub fn open(&self, field: &str, value: &str) -> Option<String> {
+        let sealed = value.strip_prefix(SEALED_PREFIX)?;
+        let sealed = general_purpose::STANDARD.decode(sealed).ok()?;
+        if sealed.len() < NONCE_SIZE {
+            return None;
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: field.as_bytes(),
+                },
+            )
+            .ok()?;
+
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+/// Makes sure the configured master key is usable, so a broken setup fails at
+/// startup instead of on the first upload.
+pub fn check_master_key() -> Result<(), String> {
+    MASTER_KEY.as_ref().map(|_| ()).map_err(|e| e.clone())
+}
+
+pub fn master_key() -> Result<&'static MasterKey, String> {
+    match MASTER_KEY.as_ref() {
+        Ok(Some(key)) => Ok(key),
+        Ok(None) => Err("MASTER_KEY or MASTER_KEY_FILE should be set".to_string()),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Seals `value` with the configured master key. Empty values stay empty, so
+/// backends without credentials work without a master key.
+pub fn seal(field: &str, value: &str) -> Result<String, String> {
+    if value.is_empty() {
+        return Ok(String::new());
+    }
+
+    Ok(master_key()?.seal(field, value))
+}
+
+/// Opens a value sealed with the configured master key. Values stored before
+/// sealing existed are passed through until `backend secrets rotate` seals
+/// them.
+pub fn open(field: &str, value: &str) -> Result<String, String> {
+    if !is_sealed(value) {
+        return Ok(value.to_string());
+    }
+
+    master_key()?
+        .open(field, value)
+        .ok_or_else(|| format!("Couldn't open {} with the master key", field))
+}
@@ -0,0 +1,184 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::PathBuf,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+use super::{ListedObject, ObjectInfo, StorageBackend, StorageError, UploadedPart};
+
+const UPLOADS_DIR: &str = ".uploads";
+
+/// Keeps objects as plain files in a directory, for instances that don't have
+/// object storage. Unfinished uploads live in `.uploads` inside it.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn object_path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        Ok(self.root.join(checked_name(key)?))
+    }
+
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf, StorageError> {
+        Ok(self.root.join(UPLOADS_DIR).join(checked_name(upload_id)?))
+    }
+}
+
+/// Keys end up in paths, so anything that could leave the directory is refused.
+fn checked_name(name: &str) -> Result<&str, StorageError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(StorageError::Backend(format!(
+            "Invalid object name {}",
+            name
+        )));
+    }
+
+    Ok(name)
+}
+
+fn io_error(e: std::io::Error) -> StorageError {
+    match e.kind() {
+        ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        fs::create_dir_all(&self.root).await.map_err(io_error)?;
+        fs::write(self.object_path(key)?, data)
+            .await
+            .map_err(io_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        fs::read(self.object_path(key)?).await.map_err(io_error)
+    }
+
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> Result<Vec<u8>, StorageError> {
+        let mut file = fs::File::open(self.object_path(key)?)
+            .await
+            .map_err(io_error)?;
+        file.seek(SeekFrom::Start(start)).await.map_err(io_error)?;
+
+        let mut data = Vec::new();
+        file.take(end.saturating_sub(start))
+            .read_to_end(&mut data)
+            .await
+            .map_err(io_error)?;
+        Ok(data)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        fs::remove_file(self.object_path(key)?)
+            .await
+            .map_err(io_error)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+        let metadata = fs::metadata(self.object_path(key)?)
+            .await
+            .map_err(io_error)?;
+        Ok(ObjectInfo {
+            size: metadata.len(),
+        })
+    }
+
+    async fn list(&self) -> Result<Vec<ListedObject>, StorageError> {
+        let mut entries = match fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            // Nothing has been stored yet
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut objects = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let key = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata().await.map_err(io_error)?;
+            if checked_name(&key).is_err() || !metadata.is_file() {
+                continue;
+            }
+
+            let last_modified: DateTime<Utc> = metadata.modified().map_err(io_error)?.into();
+            objects.push(ListedObject {
+                key,
+                size: metadata.len(),
+                last_modified: last_modified.naive_utc(),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    async fn start_upload(&self, key: &str) -> Result<String, StorageError> {
+        checked_name(key)?;
+
+        let upload_id = Uuid::new_v4().to_string();
+        fs::create_dir_all(self.upload_dir(&upload_id)?)
+            .await
+            .map_err(io_error)?;
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Vec<u8>,
+    ) -> Result<UploadedPart, StorageError> {
+        let path = self.upload_dir(upload_id)?.join(part_number.to_string());
+        fs::write(path, data).await.map_err(io_error)?;
+
+        Ok(UploadedPart {
+            part_number,
+            etag: part_number.to_string(),
+        })
+    }
+
+    async fn complete_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut parts: Vec<UploadedPart>,
+    ) -> Result<(), StorageError> {
+        let upload_dir = self.upload_dir(upload_id)?;
+        let assembled = upload_dir.join("object");
+        parts.sort_by_key(|part| part.part_number);
+
+        let mut object = fs::File::create(&assembled).await.map_err(io_error)?;
+        for part in parts {
+            let mut data = fs::File::open(upload_dir.join(part.part_number.to_string()))
+                .await
+                .map_err(io_error)?;
+            tokio::io::copy(&mut data, &mut object)
+                .await
+                .map_err(io_error)?;
+        }
+        object.flush().await.map_err(io_error)?;
+
+        fs::rename(&assembled, self.object_path(key)?)
+            .await
+            .map_err(io_error)?;
+        fs::remove_dir_all(upload_dir).await.map_err(io_error)
+    }
+
+    async fn abort_upload(&self, _key: &str, upload_id: &str) -> Result<(), StorageError> {
+        fs::remove_dir_all(self.upload_dir(upload_id)?)
+            .await
+            .map_err(io_error)
+    }
+}
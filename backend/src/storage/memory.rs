@@ -0,0 +1,176 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use uuid::Uuid;
+
+use super::{ListedObject, ObjectInfo, StorageBackend, StorageError, UploadedPart};
+
+lazy_static! {
+    static ref SHARED: Mutex<HashMap<i32, Arc<MemoryStorage>>> = Mutex::new(HashMap::new());
+}
+
+/// Keeps objects in process memory. Everything is gone after a restart, so
+/// this is only meant for development and tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: Mutex<HashMap<String, Object>>,
+    uploads: Mutex<HashMap<String, BTreeMap<u32, Vec<u8>>>>,
+}
+
+struct Object {
+    data: Vec<u8>,
+    last_modified: NaiveDateTime,
+}
+
+impl Object {
+    fn new(data: Vec<u8>) -> Self {
+        Object {
+            data,
+            last_modified: Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl MemoryStorage {
+    /// The store belonging to bucket `id`, so every lookup of the same bucket
+    /// sees the same objects.
+    pub fn shared(id: i32) -> Arc<MemoryStorage> {
+        SHARED
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(|| Arc::new(MemoryStorage::default()))
+            .clone()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Object::new(data));
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|object| object.data.clone())
+            .ok_or(StorageError::NotFound)
+    }
+
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> Result<Vec<u8>, StorageError> {
+        let objects = self.objects.lock().unwrap();
+        let object = &objects.get(key).ok_or(StorageError::NotFound)?.data;
+
+        let end = (end as usize).min(object.len());
+        let start = (start as usize).min(end);
+        Ok(object[start..end].to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .remove(key)
+            .map(|_| ())
+            .ok_or(StorageError::NotFound)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|object| ObjectInfo {
+                size: object.data.len() as u64,
+            })
+            .ok_or(StorageError::NotFound)
+    }
+
+    async fn list(&self) -> Result<Vec<ListedObject>, StorageError> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, object)| ListedObject {
+                key: key.clone(),
+                size: object.data.len() as u64,
+                last_modified: object.last_modified,
+            })
+            .collect())
+    }
+
+    async fn start_upload(&self, _key: &str) -> Result<String, StorageError> {
+        let upload_id = Uuid::new_v4().to_string();
+        self.uploads
+            .lock()
+            .unwrap()
+            .insert(upload_id.clone(), BTreeMap::new());
+        Ok(upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        _key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Vec<u8>,
+    ) -> Result<UploadedPart, StorageError> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get_mut(upload_id)
+            .ok_or(StorageError::NotFound)?
+            .insert(part_number, data);
+
+        Ok(UploadedPart {
+            part_number,
+            etag: part_number.to_string(),
+        })
+    }
+
+    async fn complete_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<(), StorageError> {
+        let mut uploaded = self
+            .uploads
+            .lock()
+            .unwrap()
+            .remove(upload_id)
+            .ok_or(StorageError::NotFound)?;
+
+        let mut object = Vec::new();
+        for part in parts {
+            object.extend(
+                uploaded
+                    .remove(&part.part_number)
+                    .ok_or(StorageError::NotFound)?,
+            );
+        }
+
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Object::new(object));
+        Ok(())
+    }
+
+    async fn abort_upload(&self, _key: &str, upload_id: &str) -> Result<(), StorageError> {
+        self.uploads.lock().unwrap().remove(upload_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::{fmt, sync::Arc};
+
+use aes_gcm::aead::OsRng;
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel_async::AsyncPgConnection;
+use rand::RngCore;
+use uuid::Uuid;
+
+use crate::{
+    database::{actions::get_s3_bucket_by_id, models},
+    placement,
+};
+
+mod local;
+mod memory;
+mod s3;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::S3Storage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound,
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "Object not found"),
+            StorageError::Backend(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub struct ObjectInfo {
+    pub size: u64,
+}
+
+pub struct ListedObject {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: NaiveDateTime,
+}
+
+pub struct UploadedPart {
+    pub part_number: u32,
+    pub etag: String,
+}
+
+/// Somewhere encrypted objects can be kept.
+///
+/// Objects are written in parts through `start_upload`, `upload_part` and
+/// `complete_upload` so large files never have to be held in memory, and read
+/// back in ranges through `get_range`. Parts are numbered from 1 and every
+/// part but the last should be at least 5MB, which is what S3 requires.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Bytes `start..end` of the object. Fewer bytes are returned when the
+    /// object ends before `end`.
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> Result<Vec<u8>, StorageError>;
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError>;
+
+    /// Every object in the bucket. Unfinished uploads aren't objects yet, so
+    /// they aren't listed.
+    async fn list(&self) -> Result<Vec<ListedObject>, StorageError>;
+
+    async fn start_upload(&self, key: &str) -> Result<String, StorageError>;
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Vec<u8>,
+    ) -> Result<UploadedPart, StorageError>;
+
+    async fn complete_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<(), StorageError>;
+
+    async fn abort_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError>;
+}
+
+pub struct Storage {
+    pub id: i32,
+    pub backend: Arc<dyn StorageBackend>,
+}
+
+pub fn backend_for(bucket_info: models::S3Bucket) -> Option<Arc<dyn StorageBackend>> {
+    match bucket_info.backend.as_str() {
+        "s3" => Some(Arc::new(S3Storage::new(bucket_info)?)),
+        "local" => Some(Arc::new(LocalStorage::new(bucket_info.endpoint))),
+        "memory" => Some(MemoryStorage::shared(bucket_info.id)),
+        _ => None,
+    }
+}
+
+/// Storage for a new upload, as picked by the placement strategy.
+pub async fn get_storage(conn: &mut AsyncPgConnection) -> Option<Storage> {
+    let bucket_info = placement::choose_bucket(conn).await.unwrap_or_default()?;
+
+    Some(Storage {
+        id: bucket_info.id,
+        backend: backend_for(bucket_info)?,
+    })
+}
+
+pub async fn get_specific_storage(
+    conn: &mut AsyncPgConnection,
+    id: i32,
+) -> Option<Arc<dyn StorageBackend>> {
+    let bucket_info = get_s3_bucket_by_id(conn, id).await.ok()?;
+    backend_for(bucket_info)
+}
+
+/// Writes, reads back and deletes a small random object, to check a backend
+/// is reachable and usable before relying on it.
+pub async fn probe(backend: &dyn StorageBackend) -> Result<(), String> {
+    let key = format!("cipherdrop-probe-{}", Uuid::new_v4());
+    let mut data = vec![0u8; 32];
+    OsRng.fill_bytes(&mut data);
+
+    backend
+        .put(&key, data.clone())
+        .await
+        .map_err(|e| format!("Write failed: {}", e))?;
+
+    let read = backend.get(&key).await;
+    let deleted = backend.delete(&key).await;
+
+    match read {
+        Ok(read) if read == data => {}
+        Ok(_) => return Err("Read back different data than was written".to_string()),
+        Err(e) => return Err(format!("Read failed: {}", e)),
+    }
+
+    deleted.map_err(|e| format!("Delete failed: {}", e))
+}
@@ -0,0 +1,221 @@
+use ::s3::{error::S3Error, serde_types::Part, Bucket};
+use async_trait::async_trait;
+use chrono::DateTime;
+
+use crate::{database::models, secrets};
+
+use super::{ListedObject, ObjectInfo, StorageBackend, StorageError, UploadedPart};
+
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    pub fn new(bucket_info: models::S3Bucket) -> Option<Self> {
+        let credentials =
+            secrets::open("access_key", &bucket_info.access_key).and_then(|access_key| {
+                Ok((
+                    access_key,
+                    secrets::open("secret_key", &bucket_info.secret_key)?,
+                ))
+            });
+        let (access_key, secret_key) = match credentials {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                println!("Bucket {}: {}", bucket_info.id, e);
+                return None;
+            }
+        };
+
+        let bucket = Bucket::new(
+            &bucket_info.bucket_name,
+            ::s3::Region::Custom {
+                region: bucket_info.region,
+                endpoint: bucket_info.endpoint,
+            },
+            awscreds::Credentials {
+                access_key: Some(access_key),
+                secret_key: Some(secret_key),
+                security_token: None,
+                session_token: None,
+                expiration: None,
+            },
+        )
+        .ok()?;
+
+        Some(S3Storage { bucket })
+    }
+}
+
+// rust-s3 is built with `fail-on-err`, so error statuses arrive as `Err` here
+fn backend_error(e: S3Error) -> StorageError {
+    match e {
+        S3Error::HttpFailWithBody(404, _) => StorageError::NotFound,
+        e => StorageError::Backend(e.to_string()),
+    }
+}
+
+fn check_status(status: u16) -> Result<(), StorageError> {
+    match status {
+        200..=299 => Ok(()),
+        status => Err(StorageError::Backend(format!(
+            "Storage responded with status {}",
+            status
+        ))),
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let response = self
+            .bucket
+            .put_object(key, &data)
+            .await
+            .map_err(backend_error)?;
+        check_status(response.status_code())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let response = self.bucket.get_object(key).await.map_err(backend_error)?;
+        check_status(response.status_code())?;
+        Ok(response.to_vec())
+    }
+
+    async fn get_range(&self, key: &str, start: u64, end: u64) -> Result<Vec<u8>, StorageError> {
+        // rust-s3 refuses single byte ranges, so ask for one more and cut it off
+        let last = (end - 1).max(start + 1);
+        let response = self
+            .bucket
+            .get_object_range(key, start, Some(last))
+            .await
+            .map_err(backend_error)?;
+        check_status(response.status_code())?;
+
+        let mut data = response.to_vec();
+        data.truncate((end - start) as usize);
+        Ok(data)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let response = self
+            .bucket
+            .delete_object(key)
+            .await
+            .map_err(backend_error)?;
+        check_status(response.status_code())
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StorageError> {
+        let (head, status) = self.bucket.head_object(key).await.map_err(backend_error)?;
+        check_status(status)?;
+
+        Ok(ObjectInfo {
+            size: head.content_length.unwrap_or_default() as u64,
+        })
+    }
+
+    async fn list(&self) -> Result<Vec<ListedObject>, StorageError> {
+        let pages = self
+            .bucket
+            .list(String::new(), None)
+            .await
+            .map_err(backend_error)?;
+
+        pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .map(|object| {
+                let last_modified = DateTime::parse_from_rfc3339(&object.last_modified)
+                    .map_err(|e| StorageError::Backend(e.to_string()))?;
+
+                Ok(ListedObject {
+                    key: object.key,
+                    size: object.size,
+                    last_modified: last_modified.naive_utc(),
+                })
+            })
+            .collect()
+    }
+
+    async fn start_upload(&self, key: &str) -> Result<String, StorageError> {
+        let multipart = self
+            .bucket
+            .initiate_multipart_upload(key, CONTENT_TYPE)
+            .await
+            .map_err(backend_error)?;
+        Ok(multipart.upload_id)
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: Vec<u8>,
+    ) -> Result<UploadedPart, StorageError> {
+        let part = self
+            .bucket
+            .put_multipart_chunk(data, key, part_number, upload_id, CONTENT_TYPE)
+            .await
+            .map_err(backend_error)?;
+
+        Ok(UploadedPart {
+            part_number: part.part_number,
+            etag: part.etag,
+        })
+    }
+
+    async fn complete_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<(), StorageError> {
+        let parts = parts
+            .into_iter()
+            .map(|part| Part {
+                part_number: part.part_number,
+                etag: part.etag,
+            })
+            .collect();
+
+        let response = self
+            .bucket
+            .complete_multipart_upload(key, upload_id, parts)
+            .await
+            .map_err(backend_error)?;
+        check_status(response.status_code())
+    }
+
+    async fn abort_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        // NotFound if it was already aborted or completed
+        self.bucket
+            .abort_upload(key, upload_id)
+            .await
+            .map_err(backend_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_missing_objects_to_not_found() {
+        assert!(matches!(
+            backend_error(S3Error::HttpFailWithBody(404, String::new())),
+            StorageError::NotFound
+        ));
+        assert!(matches!(
+            backend_error(S3Error::HttpFailWithBody(403, "AccessDenied".to_string())),
+            StorageError::Backend(_)
+        ));
+        assert!(matches!(
+            backend_error(S3Error::HttpFailWithBody(416, String::new())),
+            StorageError::Backend(_)
+        ));
+    }
+}
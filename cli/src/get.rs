@@ -0,0 +1,79 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use cipherdrop_client::{Client, Error, ShareLink};
+use clap::Args;
+
+use crate::terminal;
+
+#[derive(Args)]
+pub struct Get {
+    /// Share link, including the part after the `#`
+    link: String,
+    /// Where to save the file, `-` for standard output. In a directory the
+    /// file keeps its name, which is also the default in the current one
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Overwrite the file if it already exists
+    #[arg(short, long)]
+    force: bool,
+    /// Password of a protected file, asked for when it's needed
+    #[arg(long, env = "CIPHERDROP_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+}
+
+/// Only the last part of the name the uploader picked, so a download can't
+/// end up outside the directory it's saved in.
+fn file_name(name: &str, link: &ShareLink) -> String {
+    Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| link.uuid.to_string())
+}
+
+pub async fn run(args: Get) -> Result<(), String> {
+    let link: ShareLink = args.link.parse().map_err(|e: Error| e.to_string())?;
+    let client = Client::new(link.server.as_str()).map_err(|e| e.to_string())?;
+
+    let mut password = args.password;
+    let bar = terminal::bar("Downloading");
+    let download = loop {
+        match client
+            .download_with_progress(&link, password.as_deref(), terminal::report(&bar))
+            .await
+        {
+            Err(Error::PasswordRequired) if password.is_none() => {
+                password = Some(terminal::ask_password(false)?)
+            }
+            result => break result,
+        }
+    };
+    bar.finish_and_clear();
+    let download = download.map_err(|e| e.to_string())?;
+
+    let path = match args.output {
+        Some(output) if output == Path::new("-") => {
+            return io::stdout()
+                .write_all(&download.data)
+                .map_err(|e| format!("Couldn't write to standard output: {}", e));
+        }
+        Some(output) if output.is_dir() => output.join(file_name(&download.file_name, &link)),
+        Some(output) => output,
+        None => PathBuf::from(file_name(&download.file_name, &link)),
+    };
+
+    if path.exists() && !args.force {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        ));
+    }
+
+    fs::write(&path, &download.data)
+        .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))?;
+    eprintln!("Saved {}", path.display());
+    Ok(())
+}
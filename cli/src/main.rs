@@ -0,0 +1,38 @@
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+mod get;
+mod send;
+mod terminal;
+
+#[derive(Parser)]
+#[command(about = "Share files end to end encrypted over CipherDrop")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt a file, upload it and print its share link
+    Send(send::Send),
+    /// Download the file behind a share link and decrypt it
+    Get(get::Get),
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Send(args) => send::run(args).await,
+        Command::Get(args) => get::run(args).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use cipherdrop_client::{Client, UploadOptions};
+use clap::Args;
+
+use crate::terminal;
+
+#[derive(Args)]
+pub struct Send {
+    /// File to send, standard input when left out or `-`
+    file: Option<PathBuf>,
+    /// Name to share the file under, defaults to its own name
+    #[arg(long)]
+    name: Option<String>,
+    /// MIME type, guessed from the name by default
+    #[arg(long = "type")]
+    file_type: Option<String>,
+    /// How long to keep the file, like 1h, 7d or 2w. The server's default
+    /// when left out
+    #[arg(long)]
+    lifetime: Option<String>,
+    /// Delete the file after this many downloads
+    #[arg(long)]
+    max_downloads: Option<u32>,
+    /// Protect the file with a password, asked for when no value is given
+    #[arg(
+        long,
+        env = "CIPHERDROP_PASSWORD",
+        hide_env_values = true,
+        num_args = 0..=1,
+        default_missing_value = ""
+    )]
+    password: Option<String>,
+    /// Also mix the password into the key, so the link alone can't decrypt
+    /// the file
+    #[arg(long)]
+    key_mix: bool,
+    /// Pad the file so its size doesn't give away what it is, at up to 12%
+    /// more
+    #[arg(long)]
+    pad: bool,
+    /// Server to upload to
+    #[arg(
+        long,
+        env = "CIPHERDROP_SERVER",
+        default_value = "https://cipherdrop.sh"
+    )]
+    server: String,
+}
+
+pub async fn run(args: Send) -> Result<(), String> {
+    let (data, own_name) = match args.file.as_deref().filter(|path| *path != Path::new("-")) {
+        Some(path) => (
+            fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?,
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        ),
+        None => {
+            let mut data = Vec::new();
+            io::stdin()
+                .read_to_end(&mut data)
+                .map_err(|e| format!("Couldn't read standard input: {}", e))?;
+            (data, None)
+        }
+    };
+
+    let file_name = args.name.or(own_name).unwrap_or_else(|| "file".to_string());
+    let file_type = args.file_type.unwrap_or_else(|| {
+        mime_guess::from_path(&file_name)
+            .first_raw()
+            .unwrap_or("")
+            .to_string()
+    });
+
+    let password = match args.password {
+        Some(password) if password.is_empty() => Some(terminal::ask_password(true)?),
+        password => password,
+    };
+    if args.key_mix && password.is_none() {
+        return Err("--key-mix needs a password".to_string());
+    }
+
+    let options = UploadOptions {
+        lifetime: args.lifetime,
+        max_downloads: args.max_downloads,
+        password,
+        key_mix: args.key_mix,
+        pad: args.pad,
+    };
+
+    let client = Client::new(&args.server).map_err(|e| e.to_string())?;
+    let bar = terminal::bar("Uploading");
+    let uploaded = client
+        .upload_with_progress(
+            &file_name,
+            &file_type,
+            &data,
+            &options,
+            terminal::report(&bar),
+        )
+        .await;
+    bar.finish_and_clear();
+    let uploaded = uploaded.map_err(|e| e.to_string())?;
+
+    println!("{}", uploaded.link);
+    eprintln!("Delete token: {}", uploaded.delete_token);
+    Ok(())
+}
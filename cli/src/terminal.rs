@@ -0,0 +1,49 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// A progress bar for a transfer on stderr, which stays hidden when stderr
+/// isn't a terminal so pipes and logs don't fill up with it. It only shows
+/// once the transfer starts, see [`report`].
+pub fn bar(message: &'static str) -> ProgressBar {
+    let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden());
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )
+        .expect("the template is valid")
+        .progress_chars("=> "),
+    );
+    bar.set_message(message);
+    bar
+}
+
+/// The progress callback of the client, moving `bar` along.
+pub fn report(bar: &ProgressBar) -> impl FnMut(u64, u64) + Send + 'static {
+    let bar = bar.clone();
+    let mut started = false;
+    move |done, total| {
+        if !started {
+            bar.set_draw_target(ProgressDrawTarget::stderr());
+            started = true;
+        }
+        bar.set_length(total);
+        bar.set_position(done);
+    }
+}
+
+/// Reads a password from the terminal without echoing it, twice when it's a
+/// new one.
+pub fn ask_password(confirm: bool) -> Result<String, String> {
+    let read = |prompt| {
+        rpassword::prompt_password(prompt).map_err(|e| format!("Couldn't read password: {}", e))
+    };
+
+    let password = read("Password: ")?;
+    if password.is_empty() {
+        return Err("Empty password".to_string());
+    }
+    if confirm && read("Repeat password: ")? != password {
+        return Err("Passwords don't match".to_string());
+    }
+
+    Ok(password)
+}
@@ -0,0 +1,320 @@
+use std::io;
+
+use bytes::Bytes;
+use futures_util::stream;
+use reqwest::{
+    multipart::{Form, Part},
+    Body, RequestBuilder, Response,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    crypt::Secret,
+    error::Error,
+    link::{server_url, ShareLink},
+    metadata::{self, SEALED_VERSION},
+    padding,
+    password::{self, Derived, PROOF_HEADER},
+};
+
+/// Pieces an upload is handed to the connection in, which is how often its
+/// progress is told.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Talks to one CipherDrop server. Files are encrypted before they're
+/// uploaded and decrypted after they're downloaded, the server only ever
+/// sees ciphertext.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    server: Url,
+}
+
+/// Choices for an upload, the server's defaults for anything left out.
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+    /// A duration like `7d` or `12h`, it has to be one the server allows.
+    pub lifetime: Option<String>,
+    pub max_downloads: Option<u32>,
+    pub password: Option<String>,
+    /// Mixes the password into the key as well, so the link alone can't
+    /// decrypt the file. Ignored without a password.
+    pub key_mix: bool,
+    /// Pads the file so its size doesn't give away what it is, which costs
+    /// up to 12% more.
+    pub pad: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Uploaded {
+    pub link: ShareLink,
+    /// Lets the uploader delete the file, see [`Client::delete`].
+    pub delete_token: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub file_name: String,
+    /// MIME type the uploader gave, can be empty.
+    pub file_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct UploadResponse {
+    uuid: Uuid,
+    delete_token: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    code: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct FileInfo {
+    file_name: Option<String>,
+    file_type: Option<String>,
+    /// Name and type sealed by the uploader, see `metadata.rs`.
+    metadata: Option<String>,
+    #[serde(default)]
+    metadata_version: i16,
+    #[serde(default)]
+    padded: bool,
+    password: Option<PasswordInfo>,
+}
+
+#[derive(Deserialize)]
+struct PasswordInfo {
+    salt: String,
+    key_mix: bool,
+}
+
+/// Turns error responses into [`Error::Api`].
+async fn check(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let (code, message) = match response.json::<ErrorResponse>().await {
+        Ok(error) => (error.code, error.message),
+        Err(_) => (
+            "unknown".to_string(),
+            status
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string(),
+        ),
+    };
+
+    Err(Error::Api {
+        status: status.as_u16(),
+        code,
+        message,
+    })
+}
+
+fn with_proof(request: RequestBuilder, password: Option<&Derived>) -> RequestBuilder {
+    match password {
+        Some(password) => request.header(PROOF_HEADER, password.proof()),
+        None => request,
+    }
+}
+
+impl Client {
+    /// `server` is where files are uploaded to, like `https://cipherdrop.sh`.
+    pub fn new(server: &str) -> Result<Self, Error> {
+        Ok(Client {
+            http: reqwest::Client::new(),
+            server: server_url(server)?,
+        })
+    }
+
+    pub fn server(&self) -> &Url {
+        &self.server
+    }
+
+    /// Encrypts `data` and uploads it. Files uploaded like this open in the
+    /// web UI, and the other way around. The name and type are encrypted as
+    /// well, the server never learns them.
+    pub async fn upload(
+        &self,
+        file_name: &str,
+        file_type: &str,
+        data: &[u8],
+        options: &UploadOptions,
+    ) -> Result<Uploaded, Error> {
+        self.upload_with_progress(file_name, file_type, data, options, |_, _| {})
+            .await
+    }
+
+    /// [`Client::upload`], calling `progress` with the bytes sent so far and
+    /// the total as the upload goes.
+    pub async fn upload_with_progress(
+        &self,
+        file_name: &str,
+        file_type: &str,
+        data: &[u8],
+        options: &UploadOptions,
+        mut progress: impl FnMut(u64, u64) + Send + 'static,
+    ) -> Result<Uploaded, Error> {
+        let secret = Secret::generate();
+        let mut form = Form::new();
+
+        let mut key_password = None;
+        if let Some(password) = &options.password {
+            let salt = password::new_salt();
+            let derived = password::derive(password, &salt)?;
+            form = form
+                .text("password_salt", salt)
+                .text("password_verifier", derived.verifier())
+                .text("password_key_mix", options.key_mix.to_string());
+            key_password = options.key_mix.then_some(derived);
+        }
+
+        if let Some(lifetime) = &options.lifetime {
+            form = form.text("lifetime", lifetime.clone());
+        }
+        if let Some(max_downloads) = options.max_downloads {
+            form = form.text("max_downloads", max_downloads.to_string());
+        }
+
+        form = form.text(
+            "metadata",
+            metadata::seal(&secret.key, key_password.as_ref(), file_name, file_type),
+        );
+
+        let encrypted = match options.pad {
+            true => {
+                form = form.text("padded", "true");
+                secret.encrypt(&padding::pad(data), key_password.as_ref())
+            }
+            false => secret.encrypt(data, key_password.as_ref()),
+        };
+        let encrypted = Bytes::from(encrypted);
+        form = form.text("sha256", hex::encode(Sha256::digest(&encrypted)));
+
+        let size = encrypted.len();
+        let chunks = (0..size).step_by(UPLOAD_CHUNK_SIZE).map(move |start| {
+            let end = (start + UPLOAD_CHUNK_SIZE).min(size);
+            progress(end as u64, size as u64);
+            Ok::<_, io::Error>(encrypted.slice(start..end))
+        });
+        form = form.part(
+            "file",
+            Part::stream_with_length(Body::wrap_stream(stream::iter(chunks)), size as u64)
+                .file_name("blob")
+                .mime_str("application/octet-stream")?,
+        );
+
+        let url = self
+            .server
+            .join("api/upload")
+            .expect("server URLs are bases");
+        let response = check(self.http.post(url).multipart(form).send().await?).await?;
+        let uploaded: UploadResponse = response.json().await?;
+
+        Ok(Uploaded {
+            link: ShareLink {
+                server: self.server.clone(),
+                uuid: uploaded.uuid,
+                secret,
+            },
+            delete_token: uploaded.delete_token,
+        })
+    }
+
+    /// Downloads and decrypts the file behind `link`, from the server in the
+    /// link. `password` is only needed for protected files.
+    pub async fn download(
+        &self,
+        link: &ShareLink,
+        password: Option<&str>,
+    ) -> Result<Download, Error> {
+        self.download_with_progress(link, password, |_, _| {}).await
+    }
+
+    /// [`Client::download`], calling `progress` with the bytes received so
+    /// far and the total as the download goes.
+    pub async fn download_with_progress(
+        &self,
+        link: &ShareLink,
+        password: Option<&str>,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<Download, Error> {
+        let mut info = self.info(link, None).await?;
+
+        let derived = match (&info.password, password) {
+            (None, _) => None,
+            (Some(_), None) => return Err(Error::PasswordRequired),
+            (Some(protection), Some(password)) => {
+                Some(password::derive(password, &protection.salt)?)
+            }
+        };
+        // Name and type are only told once the password is proven
+        if derived.is_some() {
+            info = self.info(link, derived.as_ref()).await?;
+        }
+
+        let request = self.http.get(link.url("api/file/{uuid}/download"));
+        let mut response = check(with_proof(request, derived.as_ref()).send().await?).await?;
+        let size = response.content_length().unwrap_or(0);
+        let mut data = Vec::with_capacity(size as usize);
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            progress(data.len() as u64, size);
+        }
+
+        let key_password = derived.filter(|_| info.password.as_ref().is_some_and(|p| p.key_mix));
+        // Files uploaded before names were sealed have them in plain
+        let (file_name, file_type) = match (info.metadata, info.metadata_version) {
+            (Some(sealed), SEALED_VERSION) => {
+                metadata::open(&link.secret.key, key_password.as_ref(), &sealed)?
+            }
+            _ => (
+                info.file_name.unwrap_or_default(),
+                info.file_type.unwrap_or_default(),
+            ),
+        };
+
+        let data = link.secret.decrypt(&data, key_password.as_ref())?;
+        Ok(Download {
+            file_name,
+            file_type,
+            data: match info.padded {
+                true => padding::unpad(data)?,
+                false => data,
+            },
+        })
+    }
+
+    /// Deletes a file uploaded to this client's server.
+    pub async fn delete(&self, uuid: Uuid, delete_token: &str) -> Result<(), Error> {
+        let url = self
+            .server
+            .join(&format!("api/file/{}", uuid))
+            .expect("server URLs are bases");
+
+        check(
+            self.http
+                .delete(url)
+                .bearer_auth(delete_token)
+                .send()
+                .await?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn info(&self, link: &ShareLink, password: Option<&Derived>) -> Result<FileInfo, Error> {
+        let request = self.http.get(link.url("api/file/{uuid}"));
+        let response = check(with_proof(request, password).send().await?).await?;
+
+        Ok(response.json().await?)
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+use aes_gcm::{aead::Aead, Aes256Gcm, Key, KeyInit, Nonce};
+use base64::{engine::general_purpose, Engine};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{error::Error, password::Derived};
+
+pub const KEY_SIZE: usize = 32;
+pub const IV_SIZE: usize = 12;
+
+/// The AES-256-GCM key and IV a file is encrypted with before it's uploaded,
+/// the same way the web UI does it: the whole file in one go, with a random
+/// 12 byte IV. Only share links carry it, the server never sees it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret {
+    pub key: [u8; KEY_SIZE],
+    pub iv: [u8; IV_SIZE],
+}
+
+impl Secret {
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_SIZE];
+        let mut iv = [0; IV_SIZE];
+        OsRng.fill_bytes(&mut key);
+        OsRng.fill_bytes(&mut iv);
+
+        Secret { key, iv }
+    }
+
+    /// Reads the `iv~key` fragment of a share link.
+    pub fn from_fragment(fragment: &str) -> Result<Self, Error> {
+        let (iv, key) = fragment
+            .split_once('~')
+            .ok_or(Error::InvalidLink("no key"))?;
+
+        let iv = decode(iv).ok_or(Error::InvalidLink("invalid IV"))?;
+        let key = decode(key).ok_or(Error::InvalidLink("invalid key"))?;
+
+        Ok(Secret { key, iv })
+    }
+
+    /// The `iv~key` fragment of a share link, both base64url without padding.
+    pub fn fragment(&self) -> String {
+        format!(
+            "{}~{}",
+            general_purpose::URL_SAFE_NO_PAD.encode(self.iv),
+            general_purpose::URL_SAFE_NO_PAD.encode(self.key)
+        )
+    }
+
+    /// With `password` the key is mixed with what only the password gives,
+    /// for files uploaded with `password_key_mix`.
+    pub fn encrypt(&self, plaintext: &[u8], password: Option<&Derived>) -> Vec<u8> {
+        self.cipher(password)
+            .encrypt(Nonce::from_slice(&self.iv), plaintext)
+            .expect("AES-GCM encrypts anything that fits in memory")
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8], password: Option<&Derived>) -> Result<Vec<u8>, Error> {
+        self.cipher(password)
+            .decrypt(Nonce::from_slice(&self.iv), ciphertext)
+            .map_err(|_| Error::DecryptFailed)
+    }
+
+    fn cipher(&self, password: Option<&Derived>) -> Aes256Gcm {
+        let key = match password {
+            Some(password) => password.mix_key(&self.key),
+            None => self.key,
+        };
+
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+    }
+}
+
+/// Keeps the key out of logs.
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secret").finish_non_exhaustive()
+    }
+}
+
+fn decode<const N: usize>(encoded: &str) -> Option<[u8; N]> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectors from the web UI, see docs/encryption.md
+    const FRAGMENT: &str = "oKGio6Slpqeoqaqr~AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+
+    fn secret() -> Secret {
+        Secret {
+            key: std::array::from_fn(|i| i as u8),
+            iv: std::array::from_fn(|i| 0xa0 + i as u8),
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_fragment() {
+        assert_eq!(Secret::from_fragment(FRAGMENT).unwrap(), secret());
+        assert_eq!(secret().fragment(), FRAGMENT);
+
+        assert!(Secret::from_fragment("oKGio6Slpqeoqaqr").is_err());
+        assert!(
+            Secret::from_fragment("oKGio6Slpqeo~AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn seals_like_web_ui() {
+        let sealed = secret().encrypt(b"CipherDrop", None);
+        assert_eq!(
+            hex::encode(&sealed),
+            "a5710c4520b946cd0d1506cf67c1719a65b144159db97e020a9c"
+        );
+        assert_eq!(secret().decrypt(&sealed, None).unwrap(), b"CipherDrop");
+    }
+
+    #[test]
+    fn seals_with_password_like_web_ui() {
+        let password = Derived::from_parts([0; 32], std::array::from_fn(|i| 0x40 + i as u8));
+
+        let sealed = secret().encrypt(b"CipherDrop", Some(&password));
+        assert_eq!(
+            hex::encode(&sealed),
+            "c4fe16d901dfb76d7e22bf97d4596e33eb21348d375c44e356e3"
+        );
+        assert_eq!(
+            secret().decrypt(&sealed, Some(&password)).unwrap(),
+            b"CipherDrop"
+        );
+        assert!(secret().decrypt(&sealed, None).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+/// Everything the client can fail with.
+#[derive(Debug)]
+pub enum Error {
+    /// The share link or server URL can't be used, and why.
+    InvalidLink(&'static str),
+    /// The file is password protected and no password was given.
+    PasswordRequired,
+    /// The server answered with an error, `code` is the stable one from its
+    /// API (see "API errors" in the README).
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    Http(reqwest::Error),
+    /// The file didn't decrypt with the key from the link, or the password.
+    DecryptFailed,
+    Internal(String),
+}
+
+impl Error {
+    /// The server's code for the error, if it came from the server.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Api { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLink(reason) => write!(f, "Invalid link: {}", reason),
+            Error::PasswordRequired => write!(f, "This file is password protected"),
+            Error::Api {
+                status,
+                code,
+                message,
+            } => write!(f, "{} ({}, {})", message, code, status),
+            Error::Http(e) => write!(f, "Request failed: {}", e),
+            Error::DecryptFailed => write!(f, "Failed decrypting file"),
+            Error::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
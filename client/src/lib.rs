@@ -0,0 +1,41 @@
+//! Client for [CipherDrop](https://cipherdrop.sh), speaking the same end to
+//! end format as the web UI: files are encrypted with AES-256-GCM under a
+//! random key and 12 byte IV before they're uploaded, and the share link
+//! carries both in its fragment as `#base64url(iv)~base64url(key)`. Links made
+//! here open in the browser and the other way around.
+//!
+//! ```no_run
+//! # async fn send() -> Result<(), cipherdrop_client::Error> {
+//! use cipherdrop_client::{Client, ShareLink, UploadOptions};
+//!
+//! let client = Client::new("https://cipherdrop.sh")?;
+//! let options = UploadOptions {
+//!     lifetime: Some("7d".to_string()),
+//!     ..Default::default()
+//! };
+//! let uploaded = client
+//!     .upload("notes.txt", "text/plain", b"hello", &options)
+//!     .await?;
+//!
+//! let link: ShareLink = uploaded.link.to_string().parse()?;
+//! let download = client.download(&link, None).await?;
+//! assert_eq!(download.data, b"hello");
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod crypt;
+mod error;
+mod link;
+mod metadata;
+mod padding;
+mod password;
+
+pub use client::{Client, Download, UploadOptions, Uploaded};
+pub use crypt::{Secret, IV_SIZE, KEY_SIZE};
+pub use error::Error;
+pub use link::ShareLink;
+pub use password::{
+    derive as derive_password, new_salt as new_password_salt, Derived, PROOF_HEADER,
+};
@@ -0,0 +1,122 @@
+use std::{fmt, str::FromStr};
+
+use url::Url;
+use uuid::Uuid;
+
+use crate::{crypt::Secret, error::Error};
+
+/// A link to a file, as the web UI hands them out:
+/// `https://cipherdrop.sh/file/{uuid}#{iv}~{key}`. Everything needed to
+/// decrypt the file is in the fragment, which browsers never send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareLink {
+    /// Where the server is, ending with a `/`.
+    pub server: Url,
+    pub uuid: Uuid,
+    pub secret: Secret,
+}
+
+/// Parses the URL of a server, which may live under a path.
+pub fn server_url(server: &str) -> Result<Url, Error> {
+    let mut url = Url::parse(server).map_err(|_| Error::InvalidLink("invalid URL"))?;
+    if url.cannot_be_a_base() || !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::InvalidLink("not an http(s) URL"));
+    }
+
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+
+    Ok(url)
+}
+
+impl ShareLink {
+    /// URL of the file under the server, like `file/{uuid}` or
+    /// `api/file/{uuid}/download`.
+    pub fn url(&self, path: &str) -> Url {
+        self.server
+            .join(&path.replace("{uuid}", &self.uuid.to_string()))
+            .expect("server URLs are bases")
+    }
+}
+
+impl FromStr for ShareLink {
+    type Err = Error;
+
+    fn from_str(link: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(link.trim()).map_err(|_| Error::InvalidLink("invalid URL"))?;
+        let secret = Secret::from_fragment(url.fragment().unwrap_or(""))?;
+
+        let path = url.path().trim_end_matches('/');
+        let (base, uuid) = path
+            .rsplit_once("/file/")
+            .ok_or(Error::InvalidLink("not a file link"))?;
+        let uuid = Uuid::try_parse(uuid).map_err(|_| Error::InvalidLink("invalid UUID"))?;
+
+        let mut server = url.clone();
+        server.set_path(base);
+        Ok(ShareLink {
+            server: server_url(server.as_str())?,
+            uuid,
+            secret,
+        })
+    }
+}
+
+impl fmt::Display for ShareLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.url("file/{uuid}"), self.secret.fragment())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "https://cipherdrop.sh/file/6f1c6e5a-0c3e-4a8e-9f3b-2d1e5c7a9b40#oKGio6Slpqeoqaqr~AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+
+    #[test]
+    fn parses_and_formats_web_ui_links() {
+        let link: ShareLink = LINK.parse().unwrap();
+
+        assert_eq!(link.server.as_str(), "https://cipherdrop.sh/");
+        assert_eq!(
+            link.uuid.to_string(),
+            "6f1c6e5a-0c3e-4a8e-9f3b-2d1e5c7a9b40"
+        );
+        assert_eq!(link.secret.key, std::array::from_fn(|i| i as u8));
+        assert_eq!(link.secret.iv, std::array::from_fn(|i| 0xa0 + i as u8));
+        assert_eq!(link.to_string(), LINK);
+    }
+
+    #[test]
+    fn keeps_server_path() {
+        let link: ShareLink = "http://localhost:8080/drop/file/6f1c6e5a-0c3e-4a8e-9f3b-2d1e5c7a9b40/#oKGio6Slpqeoqaqr~AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8"
+            .parse()
+            .unwrap();
+
+        assert_eq!(link.server.as_str(), "http://localhost:8080/drop/");
+        assert_eq!(
+            link.url("api/file/{uuid}/download").as_str(),
+            "http://localhost:8080/drop/api/file/6f1c6e5a-0c3e-4a8e-9f3b-2d1e5c7a9b40/download"
+        );
+        assert_eq!(link.to_string().parse::<ShareLink>().unwrap(), link);
+    }
+
+    #[test]
+    fn rejects_invalid_links() {
+        let uuid = "6f1c6e5a-0c3e-4a8e-9f3b-2d1e5c7a9b40";
+        let fragment = "oKGio6Slpqeoqaqr~AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+
+        for link in [
+            format!("https://cipherdrop.sh/file/{}", uuid),
+            format!("https://cipherdrop.sh/bundle/{}#{}", uuid, fragment),
+            format!("https://cipherdrop.sh/file/nope#{}", fragment),
+            format!("ftp://cipherdrop.sh/file/{}#{}", uuid, fragment),
+        ] {
+            assert!(link.parse::<ShareLink>().is_err(), "{}", link);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use aes_gcm::{aead::Aead, Aes256Gcm, Key, KeyInit, Nonce};
+use base64::{engine::general_purpose, Engine};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    crypt::{IV_SIZE, KEY_SIZE},
+    error::Error,
+    password::Derived,
+};
+
+/// Metadata version of files whose name and type are sealed, see [`seal`].
+/// Files uploaded before have version 0 and their name and type in plain.
+pub const SEALED_VERSION: i16 = 1;
+const KEY_INFO: &[u8] = b"cipherdrop metadata key";
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    name: String,
+    #[serde(rename = "type")]
+    file_type: String,
+}
+
+/// Seals the name and type of a file like `frontend/metadata.js` does: a
+/// random IV followed by the AES-256-GCM encrypted JSON, base64url encoded.
+/// The key is derived from the link key, and from the password too for files
+/// uploaded with key mix.
+pub fn seal(
+    link_key: &[u8; KEY_SIZE],
+    password: Option<&Derived>,
+    file_name: &str,
+    file_type: &str,
+) -> String {
+    let mut iv = [0; IV_SIZE];
+    OsRng.fill_bytes(&mut iv);
+
+    let metadata = serde_json::to_vec(&Metadata {
+        name: file_name.to_string(),
+        file_type: file_type.to_string(),
+    })
+    .expect("metadata serializes");
+    let sealed = cipher(link_key, password)
+        .encrypt(Nonce::from_slice(&iv), metadata.as_slice())
+        .expect("AES-GCM encrypts anything that fits in memory");
+
+    general_purpose::URL_SAFE_NO_PAD.encode([iv.as_slice(), &sealed].concat())
+}
+
+/// The name and type in `sealed`.
+pub fn open(
+    link_key: &[u8; KEY_SIZE],
+    password: Option<&Derived>,
+    sealed: &str,
+) -> Result<(String, String), Error> {
+    let sealed = general_purpose::URL_SAFE_NO_PAD
+        .decode(sealed)
+        .map_err(|_| Error::DecryptFailed)?;
+    if sealed.len() < IV_SIZE {
+        return Err(Error::DecryptFailed);
+    }
+
+    let (iv, sealed) = sealed.split_at(IV_SIZE);
+    let metadata = cipher(link_key, password)
+        .decrypt(Nonce::from_slice(iv), sealed)
+        .map_err(|_| Error::DecryptFailed)?;
+    let metadata: Metadata = serde_json::from_slice(&metadata).map_err(|_| Error::DecryptFailed)?;
+
+    Ok((metadata.name, metadata.file_type))
+}
+
+fn cipher(link_key: &[u8; KEY_SIZE], password: Option<&Derived>) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key(link_key, password)))
+}
+
+fn key(link_key: &[u8; KEY_SIZE], password: Option<&Derived>) -> [u8; KEY_SIZE] {
+    let mut material = link_key.to_vec();
+    if let Some(password) = password {
+        material.extend_from_slice(password.mix());
+    }
+
+    let mut key = [0; KEY_SIZE];
+    Hkdf::<Sha256>::new(None, &material)
+        .expand(KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectors from the web UI, see docs/encryption.md
+    const LINK_KEY: [u8; KEY_SIZE] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    fn password() -> Derived {
+        Derived::from_parts([0; 32], std::array::from_fn(|i| 0x40 + i as u8))
+    }
+
+    #[test]
+    fn derives_key_like_web_ui() {
+        assert_eq!(
+            hex::encode(key(&LINK_KEY, None)),
+            "bca265e670e4cbd49d84d8d63439d6c939ce234a6c4558aa1e605651972b8a0f"
+        );
+        assert_eq!(
+            hex::encode(key(&LINK_KEY, Some(&password()))),
+            "28155ccc2a10c3d67dd3e0e9325639d21ad6f58d7045f843c533dcb97ba90164"
+        );
+    }
+
+    // Sealed by frontend/metadata.js
+    #[test]
+    fn opens_web_ui_metadata() {
+        let sealed = "z_7dn_D3ITpCZKXDxjPKrJOJSNpwLemg4amZ-bCKE3hpBrNvI_s9f0uYfXYpr2QzCip45o6BvUsx5yTHBW4dWjOjCu8";
+        assert_eq!(
+            open(&LINK_KEY, None, sealed).unwrap(),
+            ("notes.txt".to_string(), "text/plain".to_string())
+        );
+
+        let sealed = "--T9WRwCiYdKYig_-ffIR5DdyWlVZvew1mmymf-ixI373oDWyQJ1nyFezSK6mpUNcEKXG1o48Ji9veJu_6BKUraknArv--VJ9XJc";
+        assert_eq!(
+            open(&LINK_KEY, Some(&password()), sealed).unwrap(),
+            ("Bücher.pdf".to_string(), "application/pdf".to_string())
+        );
+        assert!(open(&LINK_KEY, None, sealed).is_err());
+    }
+
+    #[test]
+    fn opens_what_it_seals() {
+        let sealed = seal(&LINK_KEY, Some(&password()), "a b.txt", "text/plain");
+        assert_eq!(
+            open(&LINK_KEY, Some(&password()), &sealed).unwrap(),
+            ("a b.txt".to_string(), "text/plain".to_string())
+        );
+    }
+}
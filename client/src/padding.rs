@@ -0,0 +1,84 @@
+use crate::error::Error;
+
+/// Padmé, like `frontend/padding.js`: rounds `size` up so only the top bits
+/// of its binary length are kept, at no more than 12% overhead.
+pub fn padme(size: u64) -> u64 {
+    if size < 2 {
+        return size;
+    }
+
+    let exponent = size.ilog2();
+    let size_bits = exponent.ilog2() + 1;
+    let mask = (1u64 << (exponent - size_bits)) - 1;
+    (size + mask) & !mask
+}
+
+/// `data` followed by a 0x80 byte and zeros up to a Padmé size, so the size
+/// of the upload doesn't give away what it is.
+pub fn pad(data: &[u8]) -> Vec<u8> {
+    let size = padme(data.len() as u64 + 1) as usize;
+    let mut padded = Vec::with_capacity(size);
+    padded.extend_from_slice(data);
+    padded.push(0x80);
+    padded.resize(size, 0);
+    padded
+}
+
+/// Strips what [`pad`] added.
+pub fn unpad(mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let end = data
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .filter(|end| data[*end] == 0x80)
+        .ok_or(Error::DecryptFailed)?;
+
+    data.truncate(end);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padme_matches_web_ui() {
+        // What `padme` in frontend/padding.js gives
+        let sizes = [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (3, 3),
+            (9, 10),
+            (17, 18),
+            (100, 104),
+            (1000, 1024),
+            (1025, 1088),
+            (65536, 65536),
+            (65537, 67584),
+            (1000000, 1015808),
+            (123456789, 123731968),
+        ];
+
+        for (size, padded) in sizes {
+            assert_eq!(padme(size), padded, "size {}", size);
+        }
+    }
+
+    #[test]
+    fn unpads_what_it_pads() {
+        for size in [0, 1, 2, 100, 1000, 65536] {
+            let data = vec![0; size];
+            let padded = pad(&data);
+
+            assert_eq!(padded.len() as u64, padme(size as u64 + 1));
+            assert_eq!(unpad(padded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_padding() {
+        assert!(unpad(Vec::new()).is_err());
+        assert!(unpad(vec![0; 16]).is_err());
+        assert!(unpad(vec![1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::Error;
+
+/// Header a download proves it knows the password of a file with.
+pub const PROOF_HEADER: &str = "Password-Proof";
+const SALT_SIZE: usize = 16;
+const MEMORY: u32 = 19456;
+const ITERATIONS: u32 = 2;
+const PARALLELISM: u32 = 1;
+const KEY_INFO: &[u8] = b"cipherdrop password key";
+
+/// What a password derives to with a file's salt, like `frontend/password.js`
+/// does it. The first half is the proof sent to the server, the second half
+/// is only used in the key of files uploaded with key mix.
+pub struct Derived {
+    proof: [u8; 32],
+    mix: [u8; 32],
+}
+
+/// A random salt for a new protected upload, base64url encoded.
+pub fn new_salt() -> String {
+    let mut salt = [0; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    general_purpose::URL_SAFE_NO_PAD.encode(salt)
+}
+
+/// Argon2id of the NFC normalized `password`, which takes a moment on
+/// purpose.
+pub fn derive(password: &str, salt: &str) -> Result<Derived, Error> {
+    let salt = general_purpose::URL_SAFE_NO_PAD
+        .decode(salt)
+        .map_err(|_| Error::Internal("Invalid password salt".to_string()))?;
+    let params = Params::new(MEMORY, ITERATIONS, PARALLELISM, Some(64))
+        .map_err(|e| Error::Internal(e.to_string()))?;
+
+    let password: String = password.nfc().collect();
+    let mut derived = [0; 64];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), &salt, &mut derived)
+        .map_err(|e| Error::Internal(e.to_string()))?;
+
+    let (proof, mix) = derived.split_at(32);
+    Ok(Derived {
+        proof: proof.try_into().unwrap(),
+        mix: mix.try_into().unwrap(),
+    })
+}
+
+impl Derived {
+    #[cfg(test)]
+    pub(crate) fn from_parts(proof: [u8; 32], mix: [u8; 32]) -> Self {
+        Derived { proof, mix }
+    }
+
+    /// Value of the `Password-Proof` header.
+    pub fn proof(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(self.proof)
+    }
+
+    /// What the server keeps to check proofs against, sent on upload.
+    pub fn verifier(&self) -> String {
+        hex::encode(Sha256::digest(self.proof))
+    }
+
+    /// What only the password gives, mixed into keys of files uploaded with
+    /// key mix.
+    pub(crate) fn mix(&self) -> &[u8; 32] {
+        &self.mix
+    }
+
+    /// The key a file uploaded with key mix is encrypted with, from the key
+    /// in its link.
+    pub fn mix_key(&self, link_key: &[u8; 32]) -> [u8; 32] {
+        let mut material = [0; 64];
+        material[..32].copy_from_slice(link_key);
+        material[32..].copy_from_slice(&self.mix);
+
+        let mut key = [0; 32];
+        Hkdf::<Sha256>::new(None, &material)
+            .expand(KEY_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 length");
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectors from the web UI, see docs/encryption.md
+    const LINK_KEY: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    #[test]
+    fn derives_like_web_ui() {
+        let derived = derive("correct horse", "AAECAwQFBgcICQoLDA0ODw").unwrap();
+
+        assert_eq!(
+            derived.proof(),
+            "ZS0HJCz-o69MVq1-_TEpW03hj9PjrqtlW9_wHDJ_usM"
+        );
+        assert_eq!(
+            derived.verifier(),
+            "961f885f637e39fef63e2fb376e993a5c711c648395292fe44c8592daa4c953c"
+        );
+        assert_eq!(
+            hex::encode(derived.mix()),
+            "55538e77165cd731c83941251b3994819f727ca112ed2451e76b99566aa1dc9b"
+        );
+    }
+
+    #[test]
+    fn mixes_key_like_web_ui() {
+        let derived = Derived::from_parts([0; 32], std::array::from_fn(|i| 0x40 + i as u8));
+
+        assert_eq!(
+            hex::encode(derived.mix_key(&LINK_KEY)),
+            "3bae3e87726b2143da927d5e9ce4ce5f23fe033a3582acd5c87345f5c81a8c2b"
+        );
+    }
+}
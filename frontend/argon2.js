@@ -0,0 +1,342 @@
+// Argon2id (RFC 9106), for deriving file passwords in the browser where Web
+// Crypto doesn't offer it. 64 bit words are kept as two 32 bit halves, the
+// low half first, as they're laid out in little endian memory.
+
+const ARGON2_VERSION = 0x13;
+const ARGON2ID = 2;
+const ARGON2_SYNC_POINTS = 4;
+const BLOCK_WORDS = 256;
+
+const BLAKE2B_IV = new Uint32Array([
+    0xf3bcc908, 0x6a09e667, 0x84caa73b, 0xbb67ae85, 0xfe94f82b, 0x3c6ef372, 0x5f1d36f1, 0xa54ff53a,
+    0xade682d1, 0x510e527f, 0x2b3e6c1f, 0x9b05688c, 0xfb41bd6b, 0x1f83d9ab, 0x137e2179, 0x5be0cd19
+]);
+
+const BLAKE2B_SIGMA = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0]
+];
+
+// v[a] += v[b], on word indices
+const add64 = (v, a, b) => {
+    const lo = v[2 * a] + v[2 * b];
+    v[2 * a + 1] = v[2 * a + 1] + v[2 * b + 1] + (lo > 0xffffffff ? 1 : 0);
+    v[2 * a] = lo;
+};
+
+// v[a] += x, where x is given as halves
+const addHalves64 = (v, a, lo, hi) => {
+    const sum = v[2 * a] + lo;
+    v[2 * a + 1] = v[2 * a + 1] + hi + (sum > 0xffffffff ? 1 : 0);
+    v[2 * a] = sum;
+};
+
+// v[d] = (v[d] ^ v[a]) rotated right by n
+const xorRotr64 = (v, d, a, n) => {
+    let lo = v[2 * d] ^ v[2 * a];
+    let hi = v[2 * d + 1] ^ v[2 * a + 1];
+
+    if (n === 32) {
+        v[2 * d] = hi;
+        v[2 * d + 1] = lo;
+    } else if (n < 32) {
+        v[2 * d] = (lo >>> n) | (hi << (32 - n));
+        v[2 * d + 1] = (hi >>> n) | (lo << (32 - n));
+    } else {
+        n -= 32;
+        v[2 * d] = (hi >>> n) | (lo << (32 - n));
+        v[2 * d + 1] = (lo >>> n) | (hi << (32 - n));
+    }
+};
+
+// v[a] += v[b] + 2 * low(v[a]) * low(v[b]), the multiplication Argon2 adds
+// to BLAKE2b's mixing
+const fBlaMka = (v, a, b) => {
+    const x = v[2 * a];
+    const y = v[2 * b];
+    const xl = x & 0xffff, xh = x >>> 16, yl = y & 0xffff, yh = y >>> 16;
+    const ll = xl * yl, lh = xl * yh, hl = xh * yl, hh = xh * yh;
+    const mid = (ll >>> 16) + (lh & 0xffff) + (hl & 0xffff);
+    const productLo = (((mid & 0xffff) << 16) | (ll & 0xffff)) >>> 0;
+    const productHi = (hh + (lh >>> 16) + (hl >>> 16) + (mid >>> 16)) >>> 0;
+
+    add64(v, a, b);
+    addHalves64(v, a, (productLo << 1) >>> 0, ((productHi << 1) | (productLo >>> 31)) >>> 0);
+};
+
+const blake2bG = (v, m, a, b, c, d, x, y) => {
+    add64(v, a, b);
+    addHalves64(v, a, m[2 * x], m[2 * x + 1]);
+    xorRotr64(v, d, a, 32);
+    add64(v, c, d);
+    xorRotr64(v, b, c, 24);
+    add64(v, a, b);
+    addHalves64(v, a, m[2 * y], m[2 * y + 1]);
+    xorRotr64(v, d, a, 16);
+    add64(v, c, d);
+    xorRotr64(v, b, c, 63);
+};
+
+const blake2bCompress = (h, block, length, last) => {
+    const v = new Uint32Array(32);
+    v.set(h);
+    v.set(BLAKE2B_IV, 16);
+    v[24] ^= length % 0x100000000;
+    v[25] ^= Math.floor(length / 0x100000000);
+    if (last) {
+        v[28] = ~v[28];
+        v[29] = ~v[29];
+    }
+
+    const m = new Uint32Array(32);
+    for (let i = 0; i < 32; i++) {
+        m[i] = block[4 * i] | (block[4 * i + 1] << 8) | (block[4 * i + 2] << 16) | (block[4 * i + 3] << 24);
+    }
+
+    for (let round = 0; round < 12; round++) {
+        const s = BLAKE2B_SIGMA[round % 10];
+        blake2bG(v, m, 0, 4, 8, 12, s[0], s[1]);
+        blake2bG(v, m, 1, 5, 9, 13, s[2], s[3]);
+        blake2bG(v, m, 2, 6, 10, 14, s[4], s[5]);
+        blake2bG(v, m, 3, 7, 11, 15, s[6], s[7]);
+        blake2bG(v, m, 0, 5, 10, 15, s[8], s[9]);
+        blake2bG(v, m, 1, 6, 11, 12, s[10], s[11]);
+        blake2bG(v, m, 2, 7, 8, 13, s[12], s[13]);
+        blake2bG(v, m, 3, 4, 9, 14, s[14], s[15]);
+    }
+
+    for (let i = 0; i < 16; i++) {
+        h[i] ^= v[i] ^ v[i + 16];
+    }
+};
+
+// Unkeyed BLAKE2b of `data` with an output of `outLength` bytes
+const blake2b = (data, outLength) => {
+    const h = new Uint32Array(BLAKE2B_IV.slice(0, 16));
+    h[0] ^= 0x01010000 ^ outLength;
+
+    let offset = 0;
+    while (data.length - offset > 128) {
+        blake2bCompress(h, data.subarray(offset, offset + 128), offset + 128, false);
+        offset += 128;
+    }
+
+    const last = new Uint8Array(128);
+    last.set(data.subarray(offset));
+    blake2bCompress(h, last, data.length, true);
+
+    const out = new Uint8Array(outLength);
+    for (let i = 0; i < outLength; i++) {
+        out[i] = h[i >> 2] >>> (8 * (i & 3));
+    }
+    return out;
+};
+
+const concatBytes = parts => {
+    const out = new Uint8Array(parts.reduce((length, part) => length + part.length, 0));
+    let offset = 0;
+    for (const part of parts) {
+        out.set(part, offset);
+        offset += part.length;
+    }
+    return out;
+};
+
+const le32 = n => new Uint8Array([n & 0xff, (n >>> 8) & 0xff, (n >>> 16) & 0xff, (n >>> 24) & 0xff]);
+
+// H', BLAKE2b stretched to any output length
+const blake2bLong = (data, outLength) => {
+    const input = concatBytes([le32(outLength), data]);
+    if (outLength <= 64) {
+        return blake2b(input, outLength);
+    }
+
+    const out = new Uint8Array(outLength);
+    let v = blake2b(input, 64);
+    let offset = 0;
+    while (outLength - offset > 64) {
+        out.set(v.subarray(0, 32), offset);
+        offset += 32;
+        v = blake2b(v, Math.min(64, outLength - offset));
+    }
+    out.set(v, offset);
+    return out;
+};
+
+// Permutation P on the 16 words of `r` at the given word indices
+const permute = (r, w) => {
+    const g = (a, b, c, d) => {
+        fBlaMka(r, a, b);
+        xorRotr64(r, d, a, 32);
+        fBlaMka(r, c, d);
+        xorRotr64(r, b, c, 24);
+        fBlaMka(r, a, b);
+        xorRotr64(r, d, a, 16);
+        fBlaMka(r, c, d);
+        xorRotr64(r, b, c, 63);
+    };
+
+    g(w[0], w[4], w[8], w[12]);
+    g(w[1], w[5], w[9], w[13]);
+    g(w[2], w[6], w[10], w[14]);
+    g(w[3], w[7], w[11], w[15]);
+    g(w[0], w[5], w[10], w[15]);
+    g(w[1], w[6], w[11], w[12]);
+    g(w[2], w[7], w[8], w[13]);
+    g(w[3], w[4], w[9], w[14]);
+};
+
+const ROWS = Array.from({ length: 8 }, (_, i) => Array.from({ length: 16 }, (_, j) => 16 * i + j));
+const COLUMNS = Array.from({ length: 8 }, (_, i) =>
+    Array.from({ length: 16 }, (_, j) => 2 * i + (j & 1) + 16 * (j >> 1)));
+
+// The compression function G. With `withXor` the result is xored into the
+// block it replaces, as every pass after the first does.
+const fillBlock = (prev, prevOffset, ref, refOffset, next, nextOffset, withXor) => {
+    const r = new Uint32Array(BLOCK_WORDS);
+    const tmp = new Uint32Array(BLOCK_WORDS);
+    for (let i = 0; i < BLOCK_WORDS; i++) {
+        r[i] = ref[refOffset + i] ^ prev[prevOffset + i];
+        tmp[i] = withXor ? r[i] ^ next[nextOffset + i] : r[i];
+    }
+
+    for (const row of ROWS) {
+        permute(r, row);
+    }
+    for (const column of COLUMNS) {
+        permute(r, column);
+    }
+
+    for (let i = 0; i < BLOCK_WORDS; i++) {
+        next[nextOffset + i] = tmp[i] ^ r[i];
+    }
+};
+
+const bytesToWords = bytes => {
+    const words = new Uint32Array(bytes.length / 4);
+    for (let i = 0; i < words.length; i++) {
+        words[i] = bytes[4 * i] | (bytes[4 * i + 1] << 8) | (bytes[4 * i + 2] << 16) | (bytes[4 * i + 3] << 24);
+    }
+    return words;
+};
+
+// Argon2id of `password` and `salt` (both bytes), `memory` in KiB
+const argon2id = (password, salt, { memory, iterations, parallelism, length }) => {
+    const h0 = blake2b(concatBytes([
+        le32(parallelism), le32(length), le32(memory), le32(iterations), le32(ARGON2_VERSION), le32(ARGON2ID),
+        le32(password.length), password, le32(salt.length), salt, le32(0), le32(0)
+    ]), 64);
+
+    const segmentLength = Math.floor(memory / (ARGON2_SYNC_POINTS * parallelism));
+    const laneLength = segmentLength * ARGON2_SYNC_POINTS;
+    const blockCount = laneLength * parallelism;
+    const mem = new Uint32Array(blockCount * BLOCK_WORDS);
+
+    for (let lane = 0; lane < parallelism; lane++) {
+        for (let i = 0; i < 2; i++) {
+            const block = blake2bLong(concatBytes([h0, le32(i), le32(lane)]), 1024);
+            mem.set(bytesToWords(block), (lane * laneLength + i) * BLOCK_WORDS);
+        }
+    }
+
+    const zero = new Uint32Array(BLOCK_WORDS);
+    const input = new Uint32Array(BLOCK_WORDS);
+    const addresses = new Uint32Array(BLOCK_WORDS);
+
+    const nextAddresses = () => {
+        input[12]++;
+        fillBlock(zero, 0, input, 0, addresses, 0, false);
+        fillBlock(zero, 0, addresses, 0, addresses, 0, false);
+    };
+
+    for (let pass = 0; pass < iterations; pass++) {
+        for (let slice = 0; slice < ARGON2_SYNC_POINTS; slice++) {
+            for (let lane = 0; lane < parallelism; lane++) {
+                const dataIndependent = pass === 0 && slice < ARGON2_SYNC_POINTS / 2;
+                let start = 0;
+
+                if (dataIndependent) {
+                    input.fill(0);
+                    input[0] = pass;
+                    input[2] = lane;
+                    input[4] = slice;
+                    input[6] = blockCount;
+                    input[8] = iterations;
+                    input[10] = ARGON2ID;
+                }
+
+                if (pass === 0 && slice === 0) {
+                    start = 2;
+                    if (dataIndependent) {
+                        nextAddresses();
+                    }
+                }
+
+                let current = lane * laneLength + slice * segmentLength + start;
+                let prev = current % laneLength === 0 ? current + laneLength - 1 : current - 1;
+
+                for (let index = start; index < segmentLength; index++, current++, prev++) {
+                    if (current % laneLength === 1) {
+                        prev = current - 1;
+                    }
+
+                    let randLo, randHi;
+                    if (dataIndependent) {
+                        if (index % 128 === 0) {
+                            nextAddresses();
+                        }
+                        randLo = addresses[2 * (index % 128)];
+                        randHi = addresses[2 * (index % 128) + 1];
+                    } else {
+                        randLo = mem[prev * BLOCK_WORDS];
+                        randHi = mem[prev * BLOCK_WORDS + 1];
+                    }
+
+                    const refLane = pass === 0 && slice === 0 ? lane : randHi % parallelism;
+                    const sameLane = refLane === lane;
+
+                    let areaSize;
+                    if (pass === 0) {
+                        if (slice === 0) {
+                            areaSize = index - 1;
+                        } else {
+                            areaSize = slice * segmentLength + (sameLane ? index - 1 : (index === 0 ? -1 : 0));
+                        }
+                    } else {
+                        areaSize = laneLength - segmentLength + (sameLane ? index - 1 : (index === 0 ? -1 : 0));
+                    }
+
+                    const x = (BigInt(randLo) * BigInt(randLo)) >> 32n;
+                    const y = (BigInt(areaSize) * x) >> 32n;
+                    const relative = areaSize - 1 - Number(y);
+                    const startPosition = pass !== 0 && slice !== ARGON2_SYNC_POINTS - 1
+                        ? (slice + 1) * segmentLength
+                        : 0;
+                    const refIndex = (startPosition + relative) % laneLength;
+                    const ref = refLane * laneLength + refIndex;
+
+                    fillBlock(mem, prev * BLOCK_WORDS, mem, ref * BLOCK_WORDS, mem, current * BLOCK_WORDS, pass !== 0);
+                }
+            }
+        }
+    }
+
+    const final = mem.slice((laneLength - 1) * BLOCK_WORDS, laneLength * BLOCK_WORDS);
+    for (let lane = 1; lane < parallelism; lane++) {
+        const offset = (lane * laneLength + laneLength - 1) * BLOCK_WORDS;
+        for (let i = 0; i < BLOCK_WORDS; i++) {
+            final[i] ^= mem[offset + i];
+        }
+    }
+
+    const finalBytes = new Uint8Array(final.buffer);
+    return blake2bLong(finalBytes, length);
+};
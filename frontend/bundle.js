@@ -0,0 +1,273 @@
+const title = document.querySelector('h3.bundle-title');
+const entries = document.querySelector('.entries');
+const downloadAllButton = document.querySelector('button.download-all');
+const availability = document.querySelector('.availability');
+const errorP = document.querySelector('.error');
+const infoP = document.querySelector('.info');
+
+const uuid = document.querySelector('input#uuid').value;
+const availableTill = parseInt(document.querySelector('input#available_till').value);
+const sealedManifest = document.querySelector('input#manifest').value;
+const available = new Set(document.querySelector('input#available').value.split(',').filter(uuid => uuid !== ''));
+
+const entryTemplate = document.querySelector('.entry.hidden').cloneNode(true);
+entryTemplate.classList.remove('hidden');
+
+// Filled in from the manifest, only the link can open it
+var files = [];
+
+
+const base64UrlToArrayBuffer = string => {
+    const base64String = string.replace(/-/g, '+').replace(/_/g, '/');
+    const binaryString = atob(base64String);
+    const len = binaryString.length;
+    const bytes = new Uint8Array(len);
+    for (let i = 0; i < len; i++) {
+        bytes[i] = binaryString.charCodeAt(i);
+    }
+    return bytes.buffer;
+}
+
+const decrypt = async (encryptedData, encodedKey, encodedIv) => {
+    const key = await crypto.subtle.importKey(
+        'raw',
+        base64UrlToArrayBuffer(encodedKey),
+        {
+            name: 'AES-GCM',
+            length: 256
+        },
+        false,
+        [
+            "decrypt"
+        ]
+    );
+
+    return crypto.subtle.decrypt(
+        {
+            name: 'AES-GCM',
+            iv: base64UrlToArrayBuffer(encodedIv)
+        },
+        key,
+        encryptedData
+    );
+}
+
+const describeSize = bytes => {
+    const units = [['GB', 1073741824], ['MB', 1048576], ['KB', 1024]];
+    const [name, size] = units.find(([, size]) => bytes >= size) || [' bytes', 1];
+
+    return `${Math.round(bytes / size * 10) / 10}${name}`;
+}
+
+const error = msg => {
+    infoP.classList.add('hidden');
+    errorP.classList.remove('hidden');
+    errorP.innerText = msg;
+}
+
+const info = msg => {
+    errorP.classList.add('hidden');
+    infoP.classList.remove('hidden');
+    infoP.innerText = msg;
+}
+
+const openManifest = async () => {
+    const [iv, key] = window.location.hash.substring(1).split('~');
+    if (iv === undefined || key === undefined) {
+        throw new Error('The link is missing its key.');
+    }
+
+    try {
+        const manifest = await decrypt(base64UrlToArrayBuffer(sealedManifest), key, iv);
+        return JSON.parse(new TextDecoder().decode(manifest));
+    } catch {
+        throw new Error('Failed decrypting the list of files.');
+    }
+}
+
+// Downloads one file of the bundle and decrypts it, reporting progress from
+// 0 to 1
+const fetchFile = (file, onProgress) => {
+    return new Promise((resolve, reject) => {
+        const xhr = new XMLHttpRequest();
+        xhr.open('GET', `/api/bundle/${uuid}/file/${file.uuid}`, true);
+        xhr.responseType = 'arraybuffer';
+
+        xhr.onprogress = event => {
+            if (event.lengthComputable) {
+                onProgress(event.loaded / event.total);
+            }
+        };
+
+        xhr.onload = () => {
+            if (xhr.status !== 200) {
+                try {
+                    const data = JSON.parse(new TextDecoder().decode(xhr.response));
+                    return reject(new Error(data.message));
+                } catch {
+                    return reject(new Error('An error occured while downloading file.'));
+                }
+            }
+
+            // Entries from before padding don't say, they aren't padded
+            decrypt(xhr.response, file.key, file.iv)
+                .then(bytes => resolve(file.padded ? unpadPlaintext(bytes) : new Uint8Array(bytes)))
+                .catch(() => reject(new Error(`Failed decrypting ${file.name}.`)));
+        };
+
+        xhr.onerror = () => reject(new Error('An error occured while downloading file.'));
+
+        xhr.send();
+    });
+}
+
+const save = (blob, name) => {
+    const url = URL.createObjectURL(blob);
+
+    const a = document.createElement('a');
+    a.href = url;
+    a.download = name;
+    a.style.display = 'none';
+    document.body.appendChild(a);
+
+    a.onclick = () => {
+        setTimeout(() => {
+            URL.revokeObjectURL(url)
+            document.body.removeChild(a);
+        }, 500);
+    };
+
+    a.click();
+}
+
+const progress = (row, fraction) => {
+    const loader = row.querySelector('.loader');
+    loader.classList.remove('hidden');
+    loader.querySelector('div.inner').style.width = `${fraction * 100}%`;
+}
+
+const downloadOne = async (file, row) => {
+    errorP.classList.add('hidden');
+
+    try {
+        const bytes = await fetchFile(file, fraction => progress(row, fraction));
+        save(new File([bytes], file.name, { type: file.type }), file.name);
+        info('Download successful');
+    } catch (e) {
+        error(e.message);
+    }
+}
+
+// With the File System Access API the archive goes straight to disk as the
+// files come in, other browsers get it as one blob at the end
+const openArchive = async () => {
+    if (window.showSaveFilePicker !== undefined) {
+        const handle = await window.showSaveFilePicker({
+            suggestedName: 'cipherdrop.zip',
+            types: [{ description: 'Zip archive', accept: { 'application/zip': ['.zip'] } }]
+        });
+        const writable = await handle.createWritable();
+
+        return {
+            write: bytes => writable.write(bytes),
+            finish: () => writable.close(),
+            abort: () => writable.abort()
+        };
+    }
+
+    const parts = [];
+    return {
+        write: bytes => parts.push(bytes),
+        finish: () => save(new Blob(parts, { type: 'application/zip' }), 'cipherdrop.zip'),
+        abort: () => {}
+    };
+}
+
+const downloadAll = async () => {
+    errorP.classList.add('hidden');
+
+    let archive;
+    try {
+        archive = await openArchive();
+    } catch {
+        // The save dialog was cancelled
+        return;
+    }
+
+    const rows = Array.from(entries.querySelectorAll('.entry'));
+    const zip = new ZipWriter(archive.write);
+    try {
+        for (const [index, file] of files.entries()) {
+            if (!available.has(file.uuid)) {
+                continue;
+            }
+
+            info(`Downloading ${index + 1} of ${files.length}..`);
+            const bytes = await fetchFile(file, fraction => progress(rows[index], fraction));
+            await zip.add(file.name, bytes);
+        }
+
+        await zip.close();
+        await archive.finish();
+        info('Download successful');
+    } catch (e) {
+        await archive.abort();
+        error(e.message);
+    }
+}
+
+const showFiles = manifest => {
+    files = manifest.files;
+    title.innerText = `${files.length} file${files.length > 1 ? 's' : ''}`;
+
+    for (const file of files) {
+        const row = entryTemplate.cloneNode(true);
+        row.querySelector('span.name').innerText = file.name;
+        row.querySelector('small.size').innerText = describeSize(file.size);
+
+        const button = row.querySelector('button');
+        if (available.has(file.uuid)) {
+            button.addEventListener('click', () => downloadOne(file, row));
+        } else {
+            // Deleted by its uploader, or out of downloads
+            button.disabled = true;
+            button.innerText = 'Unavailable';
+        }
+
+        entries.appendChild(row);
+    }
+
+    if (!files.some(file => available.has(file.uuid))) {
+        downloadAllButton.disabled = true;
+    }
+}
+
+downloadAllButton.addEventListener('click', downloadAll);
+
+openManifest().then(showFiles).catch(e => {
+    downloadAllButton.disabled = true;
+    error(e.message);
+});
+
+
+// Calculate time remaining and put it in the HTML
+const now = Math.floor(Date.now() / 1000);
+const timeLeft = availableTill - now;
+
+if (timeLeft <= 0) {
+    availability.innerText = "These files are unavailable";
+} else {
+    const days = Math.floor(timeLeft / (60 * 60 * 24));
+    const hours = Math.floor(timeLeft / (60 * 60));
+    const minutes = Math.floor(timeLeft / 60);
+
+    if (days > 0) {
+        availability.innerText = `Files available for the next ${days} day${days > 1 ? 's' : ''}`
+    } else if (hours > 0) {
+        availability.innerText = `Files available for the next ${hours} hour${hours > 1 ? 's' : ''}`
+    } else if (minutes > 0) {
+        availability.innerText = `Files available for the next ${minutes} minute${minutes > 1 ? 's' : ''}`
+    } else {
+        availability.innerText = "Files are available for the next <1 minute"
+    }
+}
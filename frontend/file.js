@@ -3,13 +3,23 @@ const loader = document.querySelector('.loader');
 const availability = document.querySelector('.availability');
 const errorP = document.querySelector('.error');
 const infoP = document.querySelector('.info');
+const heading = document.querySelector('h3.file-name');
 
 const uuid = document.querySelector('input#uuid').value;
 const availableTill = parseInt(document.querySelector('input#available_till').value);
 var iv = document.querySelector('input#iv').value;
 var key = document.querySelector('input#key').value;
-const mimeType = document.querySelector('input#mime_type').value;
-const fileName = document.querySelector('input#file_name').value;
+const passwordInput = document.querySelector('input.password');
+const passwordSalt = document.querySelector('input#password_salt')?.value;
+const passwordKeyMix = document.querySelector('input#password_key_mix')?.value === 'true';
+const padded = document.querySelector('input#padded').value === 'true';
+// Protected files only tell their name once the password is proven, sealed
+// names are opened with the key in the link
+var mimeType = document.querySelector('input#mime_type')?.value;
+var fileName = document.querySelector('input#file_name')?.value;
+var sealedMetadata = document.querySelector('input#metadata')?.value;
+var proof = null;
+var mix = null;
 
 var file = null;
 var state = 0;
@@ -38,16 +48,25 @@ const stopProgress = () => {
     loader.classList.add('hidden');
 }
 
-button.addEventListener('click', () => {
+button.addEventListener('click', async () => {
     if (file !== null) {
         return handleFile(null);
     }
 
+    errorP.classList.add('hidden');
+
+    if (passwordSalt !== undefined && !await provePassword()) {
+        return;
+    }
+
     startingDownload();
 
     const xhr = new XMLHttpRequest();
     xhr.open('GET', `/api/file/${uuid}/download`, true);
     xhr.responseType = 'arraybuffer';
+    if (proof !== null) {
+        xhr.setRequestHeader('Password-Proof', proof);
+    }
 
     xhr.onprogress = event => {
         if (event.lengthComputable) {
@@ -76,6 +95,66 @@ button.addEventListener('click', () => {
     xhr.send();
 });
 
+const provePassword = async () => {
+    if (passwordInput.value === '') {
+        error('Enter the password of this file.');
+        return false;
+    }
+
+    infoP.classList.remove('hidden');
+    infoP.innerText = "Checking password..";
+    // Let the text show before the derivation blocks the page
+    await new Promise(resolve => setTimeout(resolve, 50));
+
+    const derived = derivePassword(passwordInput.value, passwordSalt);
+    const response = await fetch(`/api/file/${uuid}`, {
+        headers: {
+            'Password-Proof': derived.proof
+        }
+    });
+    const data = await response.json();
+
+    if (!response.ok) {
+        error(data.message);
+        return false;
+    }
+
+    proof = derived.proof;
+    mix = passwordKeyMix ? derived.mix : null;
+    if (data.metadata !== undefined) {
+        sealedMetadata = data.metadata;
+        try {
+            await revealName();
+        } catch {
+            error("Failed decrypting the file's name.");
+            return false;
+        }
+    } else {
+        fileName = data.file_name;
+        mimeType = data.file_type;
+    }
+    return true;
+}
+
+// Links used to carry the key in the query, newer ones in the fragment
+const readLinkKey = () => {
+    if (iv === "" || key === "") {
+        const fragments = window.location.hash.substring(1).split('~');
+        iv = fragments[0];
+        key = fragments[1];
+    }
+}
+
+const revealName = async () => {
+    readLinkKey();
+    const metadata = await openMetadata(base64UrlToArrayBuffer(key), mix, base64UrlToArrayBuffer(sealedMetadata));
+
+    fileName = metadata.name;
+    mimeType = metadata.type;
+    heading.innerText = fileName;
+    document.title = `CipherDrop - Download ${fileName}`;
+}
+
 const progress = percent => {
     infoP.classList.add('hidden');
     loader.classList.remove('hidden');
@@ -109,11 +188,7 @@ const handleFile = async bytes => {
     currentProgress = 80;
 
     if (file === null) {
-        if (iv === "" || key === "") {
-            const fragments = window.location.hash.substring(1).split('~');
-            iv = fragments[0];
-            key = fragments[1];
-        }
+        readLinkKey();
 
         file = await decryptFile(
             bytes,
@@ -161,7 +236,7 @@ const decryptFile = async (encryptedData, encodedKey, encodedIv, mimeType, fileN
     const rawKey = base64UrlToArrayBuffer(encodedKey);
     const iv = base64UrlToArrayBuffer(encodedIv);
 
-    const key = await crypto.subtle.importKey(
+    const key = mix !== null ? await mixPasswordKey(rawKey, mix, ['decrypt']) : await crypto.subtle.importKey(
         'raw',
         rawKey,
         {
@@ -185,7 +260,7 @@ const decryptFile = async (encryptedData, encodedKey, encodedIv, mimeType, fileN
 
     return new File(
         [
-            decryptedData
+            padded ? unpadPlaintext(decryptedData) : decryptedData
         ],
         fileName,
         {
@@ -195,6 +270,10 @@ const decryptFile = async (encryptedData, encodedKey, encodedIv, mimeType, fileN
 }
 
 
+if (sealedMetadata !== undefined) {
+    revealName().catch(() => error("Failed decrypting the file's name."));
+}
+
 // Calculate time remaining and put it in the HTML
 const now = Math.floor(Date.now() / 1000);
 const timeLeft = availableTill - now;
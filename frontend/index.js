@@ -1,8 +1,15 @@
 const dropZone = document.querySelector('div.drop');
 const button = document.querySelector('button');
-const fileInput = document.querySelector('input');
+const fileInput = document.querySelector('input[type=file]');
 const uploads = document.querySelector('.uploads');
-const select = document.querySelector('select');
+const lifetimeSelect = document.querySelector('select.lifetime');
+const maxDownloadsSelect = document.querySelector('select.max-downloads');
+const passwordInput = document.querySelector('input.password');
+const keyMixCheckbox = document.querySelector('input.key-mix');
+const padCheckbox = document.querySelector('input.pad');
+
+// Replaced by the server's limit once the policy is loaded
+let maxUploadSize = 1073741824;
 
 const uploadingTemplate = document.querySelector('.uploading.hidden').cloneNode(true);
 const successTemplate = document.querySelector('.success.hidden').cloneNode(true);
@@ -26,58 +33,108 @@ fileInput.addEventListener('change', () => {
 
 
 class FileUpload {
-    constructor(file) {
+    // A bundled file is listed by the bundle's manifest, so it's uploaded
+    // without its name and type and the bundle gets the link
+    constructor(file, bundled = false) {
         this.file = file;
+        this.bundled = bundled;
         this.fileType = file.type;
         this.fileName = file.name;
+        this.kind = 'file';
         this.templateHolder = document.createElement('div');
         uploads.appendChild(this.templateHolder);
     }
 
-    async start(lifetime) {
-        if (this.file.size > 1073741824) {
-            return this.error('File size exceeds 1GB limit.')
+    async start(lifetime, maxDownloads, password, keyMix, pad) {
+        if (this.file.size > maxUploadSize) {
+            return this.error(`File size exceeds ${describeSize(maxUploadSize)} limit.`)
         }
 
         this.progress();
-        const file = await encryptFile(this.file);
-        if (file.data.length > 1073741824) {
-            return this.error('File size exceeds 1GB limit.')
+
+        let passwordMetadata = {};
+        let mix = null;
+        if (password !== '') {
+            const salt = newPasswordSalt();
+            const derived = derivePassword(password, salt);
+            mix = keyMix ? derived.mix : null;
+            passwordMetadata = {
+                password_salt: salt,
+                password_verifier: await passwordVerifier(derived.proof),
+                password_key_mix: keyMix
+            };
+        }
+
+        const file = await encryptFile(this.file, mix, pad);
+        if (file.data.length > maxUploadSize) {
+            return this.error(`File size exceeds ${describeSize(maxUploadSize)} limit.`)
         }
 
         const fileBlob = new Blob([file.data], { type: 'application/octet-stream' });
 
-        const xhr = new XMLHttpRequest();
-        const formData = new FormData();
-
-        xhr.open('POST', '/api/upload', true);
-        formData.append('file_name', this.fileName);
-        formData.append('file_type', file.mimeType);
-        formData.append('lifetime', lifetime);
-        formData.append('file', fileBlob, 'file');
-
-        xhr.upload.onprogress = event => {
-            if (event.lengthComputable) {
-                let percentComplete = (event.loaded / event.total) * 100;
-                this.progress(percentComplete);
-            }
+        // Bundled files are named by the bundle's manifest instead
+        const nameMetadata = this.bundled
+            ? { file_name: '', file_type: '' }
+            : { metadata: arrayBufferToBase64Url(await sealMetadata(file.linkKey, mix, this.fileName, file.mimeType)) };
+
+        const metadata = {
+            ...nameMetadata,
+            lifetime,
+            max_downloads: maxDownloads,
+            padded: pad,
+            sha256: await sha256Hex(file.data),
+            ...passwordMetadata
         };
 
-        xhr.onload = () => {
-            const body = JSON.parse(xhr.responseText);
+        try {
+            const upload = await createUpload(fileBlob.size, metadata);
+            let deleteToken = upload.deleteToken;
+            let offset = 0;
+            let retries = 0;
 
-            if (body.success) {
-                this.success(`${window.location.protocol}//${window.location.hostname}/file/${body.uuid}#${file.iv}~${file.key}`);
-            } else {
-                this.error(body.message);
+            while (offset < fileBlob.size) {
+                try {
+                    const chunk = fileBlob.slice(offset, offset + UPLOAD_CHUNK_SIZE);
+                    const result = await patchUpload(upload.location, offset, chunk, loaded => {
+                        this.progress(((offset + loaded) / fileBlob.size) * 100);
+                    });
+
+                    offset = result.offset;
+                    deleteToken = result.deleteToken;
+                    retries = 0;
+                } catch (e) {
+                    if (retries >= UPLOAD_RETRIES) {
+                        throw e;
+                    }
+
+                    // Continue from whatever made it to the server
+                    retries++;
+                    await new Promise(resolve => setTimeout(resolve, 1000 * retries));
+                    offset = await uploadOffset(upload.location);
+                }
             }
-        }
 
-        xhr.onerror = () => {
-            this.error('An error occured while uploading file.');
+            const uuid = upload.location.split('/').pop();
+            if (this.bundled) {
+                this.progress(100);
+                return {
+                    uuid,
+                    delete_token: deleteToken,
+                    name: this.fileName,
+                    type: file.mimeType,
+                    size: this.file.size,
+                    iv: file.iv,
+                    key: file.key,
+                    padded: pad
+                };
+            }
+
+            this.success(`${window.location.protocol}//${window.location.hostname}/file/${uuid}#${file.iv}~${file.key}`, uuid, deleteToken);
+        } catch (e) {
+            this.error(e.message || 'An error occured while uploading file.');
         }
 
-        xhr.send(formData);
+        return null;
     }
 
     progress(percent = 0) {
@@ -88,20 +145,43 @@ class FileUpload {
         this.templateHolder.appendChild(progress);
     }
 
-    success(link) {
+    success(link, uuid, deleteToken) {
         const success = successTemplate.cloneNode(true);
         const input = success.querySelector('input');
         success.querySelector('span').innerText = this.fileName;
-        success.querySelector('button').addEventListener('click', () => {
+        success.querySelector('button.copy-link').addEventListener('click', () => {
             input.select();
             input.setSelectionRange(0, 99999);
             navigator.clipboard.writeText(input.value);
         });
+        success.querySelector('button.delete-file').addEventListener('click', () => this.delete(uuid, deleteToken));
         input.value = link;
         this.templateHolder.innerHTML = '';
         this.templateHolder.appendChild(success);
     }
 
+    delete(uuid, deleteToken) {
+        const xhr = new XMLHttpRequest();
+        xhr.open('DELETE', `/api/${this.kind}/${uuid}`, true);
+        xhr.setRequestHeader('Authorization', `Bearer ${deleteToken}`);
+
+        xhr.onload = () => {
+            const body = JSON.parse(xhr.responseText);
+
+            if (body.success) {
+                this.templateHolder.querySelector('div.copy').innerText = this.kind === 'bundle' ? 'Files deleted' : 'File deleted';
+            } else {
+                this.error(body.message);
+            }
+        }
+
+        xhr.onerror = () => {
+            this.error('An error occured while deleting file.');
+        }
+
+        xhr.send();
+    }
+
     error(msg) {
         const error = failTemplate.cloneNode(true);
         error.querySelector('span').innerText = this.fileName;
@@ -111,13 +191,190 @@ class FileUpload {
     }
 }
 
+// Several files shared under one link. Each is uploaded on its own, then
+// the bundle puts them together with a manifest of their names and keys that
+// only the link opens.
+class BundleUpload extends FileUpload {
+    constructor(files) {
+        super(files[0], true);
+        this.kind = 'bundle';
+        this.fileName = `${files.length} files`;
+        this.uploads = files.map(file => new FileUpload(file, true));
+        // The bundle's own row comes after those of its files
+        uploads.appendChild(this.templateHolder);
+    }
+
+    async start(lifetime, maxDownloads, pad) {
+        const results = await Promise.all(this.uploads.map(upload => upload.start(lifetime, maxDownloads, '', false, pad)));
+        const files = results.filter(result => result !== null);
+        if (files.length === 0) {
+            return this.error('None of the files could be uploaded.');
+        }
+
+        this.fileName = `${files.length} file${files.length > 1 ? 's' : ''}`;
+        this.progress(100);
+
+        try {
+            const manifest = await sealManifest({
+                version: 1,
+                files: files.map(({ delete_token, ...file }) => file)
+            });
+
+            const response = await fetch('/api/bundle', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/json'
+                },
+                body: JSON.stringify({
+                    files: files.map(file => ({ uuid: file.uuid, delete_token: file.delete_token })),
+                    manifest: manifest.data,
+                    lifetime
+                })
+            });
+            const body = await response.json();
+            if (!response.ok) {
+                return this.error(body.message);
+            }
+
+            // Files that failed keep their error row
+            this.uploads.forEach((upload, index) => {
+                if (results[index] !== null) {
+                    upload.templateHolder.remove();
+                }
+            });
+
+            this.success(`${window.location.protocol}//${window.location.hostname}/bundle/${body.uuid}#${manifest.iv}~${manifest.key}`, body.uuid, body.delete_token);
+        } catch (e) {
+            this.error(e.message || 'An error occured while creating bundle.');
+        }
+    }
+}
+
+const UPLOAD_CHUNK_SIZE = 16 * 1024 * 1024;
+const UPLOAD_RETRIES = 5;
+
+const tusRequest = (method, url, headers, body = null, onProgress = null) => {
+    return new Promise((resolve, reject) => {
+        const xhr = new XMLHttpRequest();
+        xhr.open(method, url, true);
+        xhr.setRequestHeader('Tus-Resumable', '1.0.0');
+        for (const [name, value] of Object.entries(headers)) {
+            xhr.setRequestHeader(name, value);
+        }
+
+        if (onProgress !== null) {
+            xhr.upload.onprogress = event => {
+                if (event.lengthComputable) {
+                    onProgress(event.loaded);
+                }
+            };
+        }
+
+        xhr.onload = () => {
+            if (xhr.status >= 200 && xhr.status < 300) {
+                return resolve(xhr);
+            }
+
+            try {
+                reject(new Error(JSON.parse(xhr.responseText).message));
+            } catch {
+                reject(new Error('An error occured while uploading file.'));
+            }
+        };
+        xhr.onerror = () => reject(new Error('An error occured while uploading file.'));
+
+        xhr.send(body);
+    });
+}
+
+const encodeMetadata = metadata => {
+    return Object.entries(metadata)
+        .map(([key, value]) => {
+            const bytes = new TextEncoder().encode(value);
+            return `${key} ${btoa(String.fromCharCode(...bytes))}`;
+        })
+        .join(',');
+}
+
+const createUpload = async (size, metadata) => {
+    const xhr = await tusRequest('POST', '/api/tus', {
+        'Upload-Length': size,
+        'Upload-Metadata': encodeMetadata(metadata)
+    });
+
+    return {
+        location: xhr.getResponseHeader('Location'),
+        deleteToken: xhr.getResponseHeader('Delete-Token')
+    };
+}
+
+const patchUpload = async (location, offset, chunk, onProgress) => {
+    const xhr = await tusRequest('PATCH', location, {
+        'Upload-Offset': offset,
+        'Content-Type': 'application/offset+octet-stream'
+    }, chunk, onProgress);
+
+    return {
+        offset: parseInt(xhr.getResponseHeader('Upload-Offset')),
+        deleteToken: xhr.getResponseHeader('Delete-Token')
+    };
+}
+
+const uploadOffset = async location => {
+    const xhr = await tusRequest('HEAD', location, {});
+    return parseInt(xhr.getResponseHeader('Upload-Offset'));
+}
+
+const describeDuration = seconds => {
+    const units = [['week', 604800], ['day', 86400], ['hour', 3600], ['minute', 60]];
+    const [name, size] = units.find(([, size]) => seconds % size === 0) || ['second', 1];
+    const count = seconds / size;
+
+    return `${count} ${name}${count > 1 ? 's' : ''}`;
+}
+
+const describeSize = bytes => {
+    const units = [['GB', 1073741824], ['MB', 1048576], ['KB', 1024]];
+    const [name, size] = units.find(([, size]) => bytes % size === 0) || [' bytes', 1];
+
+    return `${bytes / size}${name}`;
+}
+
+// Offer the lifetimes the server is configured with, the options in the page
+// are only used if the policy can't be loaded
+const loadPolicy = async () => {
+    const response = await fetch('/api/policy');
+    if (!response.ok) {
+        return;
+    }
+
+    const policy = await response.json();
+    maxUploadSize = policy.max_upload_size;
+    lifetimeSelect.replaceChildren(...policy.options.map(seconds => {
+        const option = document.createElement('option');
+        option.value = seconds;
+        option.innerText = describeDuration(seconds);
+        option.selected = seconds === policy.default;
+        return option;
+    }));
+}
+
+loadPolicy().catch(() => {});
+
 const handleFile = async file => {
     const fileUploader = new FileUpload(file);
 
-    fileUploader.start(select.value);
+    fileUploader.start(lifetimeSelect.value, maxDownloadsSelect.value, passwordInput.value, keyMixCheckbox.checked, padCheckbox.checked);
 }
 
 const handleFiles = files => { // FileList
+    // Protected files are shared one by one, the bundle page has no way to
+    // ask for their password
+    if (files.length > 1 && passwordInput.value === '') {
+        const bundleUploader = new BundleUpload(files);
+        return bundleUploader.start(lifetimeSelect.value, maxDownloadsSelect.value, padCheckbox.checked);
+    }
+
     for (const file of files) {
         handleFile(file);
     }
@@ -137,24 +394,42 @@ const arrayBufferToBase64Url = buffer => {
     return base64String.replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
 }
 
-const generateKey = async () => {
-    return crypto.subtle.generateKey(
+const sha256Hex = async data => {
+    const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', data));
+    return Array.from(digest, byte => byte.toString(16).padStart(2, '0')).join('');
+}
+
+// The manifest of a bundle is encrypted like a file, with a key of its own
+const sealManifest = async manifest => {
+    const key = crypto.getRandomValues(new Uint8Array(32));
+    const iv = crypto.getRandomValues(new Uint8Array(12));
+    const cryptoKey = await crypto.subtle.importKey('raw', key, { name: 'AES-GCM', length: 256 }, false, ['encrypt']);
+
+    const sealed = await crypto.subtle.encrypt(
         {
             name: "AES-GCM",
-            length: 256
+            iv
         },
-        true,
-        [
-            "encrypt",
-            "decrypt"
-        ]
+        cryptoKey,
+        new TextEncoder().encode(JSON.stringify(manifest))
     );
+
+    return {
+        data: arrayBufferToBase64Url(new Uint8Array(sealed)),
+        key: arrayBufferToBase64Url(key),
+        iv: arrayBufferToBase64Url(iv)
+    };
 }
 
-const encryptFile = async file => {
-    const key = await generateKey();
+// With `mix` the key in the link is only half of it, see password.js. With
+// `pad` the size is padded, see padding.js
+const encryptFile = async (file, mix, pad) => {
+    const linkKey = crypto.getRandomValues(new Uint8Array(32));
+    const key = mix === null
+        ? await crypto.subtle.importKey('raw', linkKey, { name: 'AES-GCM', length: 256 }, false, ['encrypt'])
+        : await mixPasswordKey(linkKey, mix, ['encrypt']);
     const iv = crypto.getRandomValues(new Uint8Array(12));
-    const fileBuffer = await fileToArrayBuffer(file);
+    const fileBuffer = pad ? padPlaintext(await fileToArrayBuffer(file)) : await fileToArrayBuffer(file);
 
     const encryptedData = await crypto.subtle.encrypt(
         {
@@ -165,10 +440,10 @@ const encryptFile = async file => {
         fileBuffer
     );
 
-    const exportedKey = await crypto.subtle.exportKey('raw', key);
     return {
         data: new Uint8Array(encryptedData),
-        key: arrayBufferToBase64Url(new Uint8Array(exportedKey)),
+        linkKey,
+        key: arrayBufferToBase64Url(linkKey),
         iv: arrayBufferToBase64Url(iv),
         mimeType: file.type,
         fileType: file.name.includes('.') ? file.name.split('.').pop() : null // .png?
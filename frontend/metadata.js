@@ -0,0 +1,62 @@
+// File names and types, sealed so the server only stores an opaque blob. The
+// key is derived from the file's link key, and the password's key material
+// too for files uploaded with key mix, so the name is as private as the file.
+// Sealed is a random 12 byte IV followed by the AES-GCM encrypted JSON.
+
+const METADATA_KEY_INFO = new TextEncoder().encode('cipherdrop metadata key');
+
+const metadataKey = async (linkKey, mix, usages) => {
+    const linkBytes = new Uint8Array(linkKey);
+    const material = new Uint8Array(linkBytes.length + (mix === null ? 0 : mix.length));
+    material.set(linkBytes);
+    if (mix !== null) {
+        material.set(mix, linkBytes.length);
+    }
+
+    return crypto.subtle.deriveKey(
+        {
+            name: 'HKDF',
+            hash: 'SHA-256',
+            salt: new Uint8Array(),
+            info: METADATA_KEY_INFO
+        },
+        await crypto.subtle.importKey('raw', material, 'HKDF', false, ['deriveKey']),
+        {
+            name: 'AES-GCM',
+            length: 256
+        },
+        false,
+        usages
+    );
+}
+
+const sealMetadata = async (linkKey, mix, name, type) => {
+    const iv = crypto.getRandomValues(new Uint8Array(12));
+    const sealed = await crypto.subtle.encrypt(
+        {
+            name: 'AES-GCM',
+            iv
+        },
+        await metadataKey(linkKey, mix, ['encrypt']),
+        new TextEncoder().encode(JSON.stringify({ name, type }))
+    );
+
+    const result = new Uint8Array(iv.length + sealed.byteLength);
+    result.set(iv);
+    result.set(new Uint8Array(sealed), iv.length);
+    return result;
+}
+
+const openMetadata = async (linkKey, mix, sealed) => {
+    const bytes = new Uint8Array(sealed);
+    const opened = await crypto.subtle.decrypt(
+        {
+            name: 'AES-GCM',
+            iv: bytes.subarray(0, 12)
+        },
+        await metadataKey(linkKey, mix, ['decrypt']),
+        bytes.subarray(12)
+    );
+
+    return JSON.parse(new TextDecoder().decode(opened));
+}
@@ -0,0 +1,43 @@
+// Size padding, so the length of an upload doesn't give away what it is. The
+// plaintext gets a 0x80 byte and as many zeros as it takes to reach a Padmé
+// size before it's encrypted, see "Size padding" in the README.
+
+// Keeps only the top bits of the size's binary length, at most 12% overhead
+const padme = size => {
+    if (size < 2) {
+        return size;
+    }
+
+    let exponent = 0;
+    while (2 ** (exponent + 1) <= size) {
+        exponent++;
+    }
+    let sizeBits = 0;
+    while (2 ** (sizeBits + 1) <= exponent) {
+        sizeBits++;
+    }
+
+    const step = 2 ** (exponent - sizeBits - 1);
+    return Math.ceil(size / step) * step;
+}
+
+const padPlaintext = buffer => {
+    const bytes = new Uint8Array(buffer);
+    const padded = new Uint8Array(padme(bytes.length + 1));
+    padded.set(bytes);
+    padded[bytes.length] = 0x80;
+    return padded;
+}
+
+const unpadPlaintext = buffer => {
+    const bytes = new Uint8Array(buffer);
+    let end = bytes.length - 1;
+    while (end >= 0 && bytes[end] === 0) {
+        end--;
+    }
+
+    if (end < 0 || bytes[end] !== 0x80) {
+        throw new Error('Invalid padding');
+    }
+    return bytes.subarray(0, end);
+}
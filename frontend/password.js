@@ -0,0 +1,54 @@
+// File passwords, see "Password protection" in the README. The password is
+// stretched with Argon2id into a proof for the server and, when the uploader
+// asks for it, key material that only the password gives.
+
+const PASSWORD_PARAMS = { memory: 19456, iterations: 2, parallelism: 1, length: 64 };
+const PASSWORD_SALT_SIZE = 16;
+const PASSWORD_KEY_INFO = new TextEncoder().encode('cipherdrop password key');
+
+const bytesToBase64Url = bytes => {
+    return btoa(String.fromCharCode(...bytes)).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+const base64UrlToBytes = string => {
+    return Uint8Array.from(atob(string.replace(/-/g, '+').replace(/_/g, '/')), c => c.charCodeAt(0));
+}
+
+const newPasswordSalt = () => bytesToBase64Url(crypto.getRandomValues(new Uint8Array(PASSWORD_SALT_SIZE)));
+
+// Takes a second or so, on purpose
+const derivePassword = (password, salt) => {
+    const derived = argon2id(new TextEncoder().encode(password.normalize('NFC')), base64UrlToBytes(salt), PASSWORD_PARAMS);
+
+    return {
+        proof: bytesToBase64Url(derived.subarray(0, 32)),
+        mix: derived.slice(32)
+    };
+}
+
+// What the server keeps to check proofs against
+const passwordVerifier = async proof => {
+    const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', base64UrlToBytes(proof)));
+    return Array.from(digest, byte => byte.toString(16).padStart(2, '0')).join('');
+}
+
+// The AES-GCM key of a file whose password is mixed into the key in its link
+const mixPasswordKey = async (linkKey, mix, usages) => {
+    const material = await crypto.subtle.importKey('raw', concatBytes([new Uint8Array(linkKey), mix]), 'HKDF', false, ['deriveKey']);
+
+    return crypto.subtle.deriveKey(
+        {
+            name: 'HKDF',
+            hash: 'SHA-256',
+            salt: new Uint8Array(),
+            info: PASSWORD_KEY_INFO
+        },
+        material,
+        {
+            name: 'AES-GCM',
+            length: 256
+        },
+        false,
+        usages
+    );
+}
@@ -0,0 +1,126 @@
+// A zip writer for saving a whole bundle at once. Files are stored as they
+// are, they're already as small as they get and compressing in the browser
+// would only be slow. Without Zip64 the archive has to stay under 4GB.
+
+const ZIP_MAX_SIZE = 0xffffffff;
+
+const CRC32_TABLE = (() => {
+    const table = new Uint32Array(256);
+    for (let n = 0; n < 256; n++) {
+        let c = n;
+        for (let k = 0; k < 8; k++) {
+            c = c & 1 ? 0xedb88320 ^ (c >>> 1) : c >>> 1;
+        }
+        table[n] = c >>> 0;
+    }
+    return table;
+})();
+
+const crc32 = bytes => {
+    let crc = 0xffffffff;
+    for (let i = 0; i < bytes.length; i++) {
+        crc = CRC32_TABLE[(crc ^ bytes[i]) & 0xff] ^ (crc >>> 8);
+    }
+    return (crc ^ 0xffffffff) >>> 0;
+}
+
+// Date and time in the MS-DOS format zip uses
+const dosDateTime = date => {
+    return {
+        time: (date.getHours() << 11) | (date.getMinutes() << 5) | (date.getSeconds() >> 1),
+        date: ((date.getFullYear() - 1980) << 9) | ((date.getMonth() + 1) << 5) | date.getDate()
+    };
+}
+
+class ZipWriter {
+    // `write` takes each part of the archive in order, and may return a
+    // promise to hold the next one back
+    constructor(write) {
+        this.write = write;
+        this.entries = [];
+        this.names = new Set();
+        this.offset = 0;
+    }
+
+    // Names are kept unique, a second "a.txt" becomes "a (1).txt"
+    uniqueName(name) {
+        const dot = name.lastIndexOf('.');
+        const [base, extension] = dot > 0 ? [name.slice(0, dot), name.slice(dot)] : [name, ''];
+
+        let unique = name;
+        for (let i = 1; this.names.has(unique); i++) {
+            unique = `${base} (${i})${extension}`;
+        }
+        this.names.add(unique);
+        return unique;
+    }
+
+    async add(name, bytes, modified = new Date()) {
+        const encodedName = new TextEncoder().encode(this.uniqueName(name));
+        const header = 30 + encodedName.length;
+
+        if (this.offset + header + bytes.length > ZIP_MAX_SIZE) {
+            throw new Error('The files are too large to save together, download them one by one.');
+        }
+
+        const entry = {
+            name: encodedName,
+            crc: crc32(bytes),
+            size: bytes.length,
+            offset: this.offset,
+            ...dosDateTime(modified)
+        };
+
+        const local = new DataView(new ArrayBuffer(header));
+        local.setUint32(0, 0x04034b50, true);
+        local.setUint16(4, 20, true);
+        local.setUint16(6, 0x0800, true); // Names are UTF-8
+        local.setUint16(8, 0, true); // Stored
+        local.setUint16(10, entry.time, true);
+        local.setUint16(12, entry.date, true);
+        local.setUint32(14, entry.crc, true);
+        local.setUint32(18, entry.size, true);
+        local.setUint32(22, entry.size, true);
+        local.setUint16(26, encodedName.length, true);
+        local.setUint16(28, 0, true);
+        new Uint8Array(local.buffer).set(encodedName, 30);
+
+        await this.write(new Uint8Array(local.buffer));
+        await this.write(bytes);
+        this.offset += header + bytes.length;
+        this.entries.push(entry);
+    }
+
+    async close() {
+        const start = this.offset;
+        let size = 0;
+
+        for (const entry of this.entries) {
+            const central = new DataView(new ArrayBuffer(46 + entry.name.length));
+            central.setUint32(0, 0x02014b50, true);
+            central.setUint16(4, 20, true);
+            central.setUint16(6, 20, true);
+            central.setUint16(8, 0x0800, true);
+            central.setUint16(10, 0, true);
+            central.setUint16(12, entry.time, true);
+            central.setUint16(14, entry.date, true);
+            central.setUint32(16, entry.crc, true);
+            central.setUint32(20, entry.size, true);
+            central.setUint32(24, entry.size, true);
+            central.setUint16(28, entry.name.length, true);
+            central.setUint32(42, entry.offset, true);
+            new Uint8Array(central.buffer).set(entry.name, 46);
+
+            await this.write(new Uint8Array(central.buffer));
+            size += central.byteLength;
+        }
+
+        const end = new DataView(new ArrayBuffer(22));
+        end.setUint32(0, 0x06054b50, true);
+        end.setUint16(8, this.entries.length, true);
+        end.setUint16(10, this.entries.length, true);
+        end.setUint32(12, size, true);
+        end.setUint32(16, start, true);
+        await this.write(new Uint8Array(end.buffer));
+    }
+}